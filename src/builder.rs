@@ -0,0 +1,408 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::error::Error;
+use crate::file_handler::{FileHandler, LockMode};
+use crate::format::{Decode, Encode, Format, DEFAULT_INDENT};
+use crate::paths;
+use crate::transform::ByteTransform;
+use serde::Serialize;
+use std::ffi::OsStr;
+use std::io::{self, Read};
+use std::mem;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The default limit on the size of a file which will be read, 16 MiB.
+const DEFAULT_MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Settings carried by a `FileHandler` for the whole of its lifetime.
+#[derive(Clone)]
+pub(crate) struct Options {
+    pub format: Format,
+    pub prefer_local_appdata: bool,
+    pub durable: bool,
+    pub sharing_retry_attempts: u32,
+    pub sharing_retry_backoff: Duration,
+    pub disable_system_cache: bool,
+    pub indent: Vec<u8>,
+    pub trailing_newline: bool,
+    pub max_size: u64,
+    pub case_insensitive: bool,
+    pub retry_attempts: u32,
+    pub retry_backoff: Duration,
+    pub retry_kinds: Vec<io::ErrorKind>,
+    pub lock_poll_interval: Duration,
+    pub lock_mode: LockMode,
+    pub mutex: Option<Arc<Mutex<()>>>,
+    pub transforms: Vec<Arc<dyn ByteTransform>>,
+    pub mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+    #[cfg(feature = "encryption")]
+    pub cipher_key: Option<[u8; 32]>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            format: Format::default(),
+            prefer_local_appdata: false,
+            durable: true,
+            sharing_retry_attempts: 5,
+            sharing_retry_backoff: Duration::from_millis(10),
+            disable_system_cache: false,
+            indent: DEFAULT_INDENT.to_vec(),
+            trailing_newline: false,
+            max_size: DEFAULT_MAX_SIZE,
+            case_insensitive: false,
+            retry_attempts: 0,
+            retry_backoff: Duration::from_millis(10),
+            retry_kinds: vec![io::ErrorKind::Interrupted],
+            lock_poll_interval: Duration::from_millis(10),
+            lock_mode: LockMode::default(),
+            mutex: None,
+            transforms: Vec::new(),
+            mode: None,
+            dir_mode: None,
+            #[cfg(feature = "encryption")]
+            cipher_key: None,
+        }
+    }
+}
+
+impl Options {
+    /// The user directory to search, taking `prefer_local_appdata` into account.
+    pub fn user_app_dir(&self) -> Result<PathBuf, Error> {
+        if self.prefer_local_appdata {
+            paths::local_user_app_dir()
+        } else {
+            paths::user_app_dir()
+        }
+    }
+
+    /// Encode `value` as it is stored on disk: serialised as by `serialize()`, then passed through
+    /// `transforms` and encrypted if a key has been set.
+    pub fn encode<T: Encode>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        self.seal(self.serialize(value)?)
+    }
+
+    /// Serialise `value` using `format`, `indent` and `trailing_newline`, without encrypting it.
+    pub fn serialize<T: Encode>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        value.encode(self.format, &self.indent, self.trailing_newline)
+    }
+
+    /// Pass already-serialised `contents` through `transforms` in order, then encrypt it if a key
+    /// has been set.
+    pub fn seal(&self, mut contents: Vec<u8>) -> Result<Vec<u8>, Error> {
+        for transform in &self.transforms {
+            contents = transform.on_write(contents)?;
+        }
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(ref key) = self.cipher_key {
+                return crate::cipher::seal(key, &contents);
+            }
+        }
+        Ok(contents)
+    }
+
+    /// The inverse of `seal()`: decrypt the raw `contents` read from disk in place if a key has
+    /// been set, then pass it through `transforms` in reverse order.
+    pub fn unseal(&self, contents: &mut Vec<u8>) -> Result<(), Error> {
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(ref key) = self.cipher_key {
+                crate::cipher::open_in_place(key, contents)?;
+            }
+        }
+        for transform in self.transforms.iter().rev() {
+            *contents = transform.on_read(mem::take(contents))?;
+        }
+        Ok(())
+    }
+
+    /// Whether `seal()` may change the serialised contents, so that they must be read in full
+    /// and unsealed before decoding.
+    fn is_sealed(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        {
+            if self.cipher_key.is_some() {
+                return true;
+            }
+        }
+        !self.transforms.is_empty()
+    }
+
+    /// Fail with `Error::TooLarge` if `size` exceeds `max_size`, e.g. before reading a file.
+    pub fn check_size(&self, size: u64) -> Result<(), Error> {
+        if size > self.max_size {
+            return Err(Error::TooLarge {
+                size,
+                limit: self.max_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the file must be text before it is decoded as a `T`: only for the text formats,
+    /// and not if it is encrypted or transformed.
+    pub fn expects_text<T: Decode>(&self) -> bool {
+        !self.is_sealed() && T::expects_text(self.format)
+    }
+
+    /// Whether the file must be text before it is decoded into borrowed data, which is always
+    /// done using `format`, as for `expects_text()`.
+    pub fn expects_text_slice(&self) -> bool {
+        !self.is_sealed() && self.format.is_text()
+    }
+
+    /// The inverse of `encode()`.
+    ///
+    /// At most `max_size` bytes are read from `reader`, in case it is a file which grows while
+    /// being read.
+    pub fn decode<T: Decode, R: Read>(&self, reader: R) -> Result<T, Error> {
+        let mut reader = reader.take(self.max_size.saturating_add(1));
+        let result = self.decode_unlimited(&mut reader);
+        if reader.limit() == 0 {
+            return Err(Error::TooLarge {
+                size: self.max_size + 1,
+                limit: self.max_size,
+            });
+        }
+        result
+    }
+
+    fn decode_unlimited<T: Decode, R: Read>(&self, reader: R) -> Result<T, Error> {
+        if self.is_sealed() {
+            let mut reader = reader;
+            let mut contents = Vec::new();
+            let _ = reader.read_to_end(&mut contents)?;
+            self.unseal(&mut contents)?;
+            return T::decode(&contents[..], self.format);
+        }
+        T::decode(reader, self.format)
+    }
+}
+
+/// Options which can be used to configure how a `FileHandler` is opened or created.
+///
+/// `FileHandler::open` and `FileHandler::new` are equivalent to calling `open` and `create` on a
+/// default `Builder`.
+///
+/// # Examples
+///
+/// ```
+/// use config_file_handler::{Builder, FileHandler, Format};
+///
+/// let file_handler: FileHandler<u64> = Builder::new()
+///     .format(Format::Json)
+///     .create("builder_example.json", true)
+///     .unwrap();
+/// # let _ = file_handler;
+/// # config_file_handler::cleanup(&"builder_example.json").unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct Builder {
+    options: Options,
+}
+
+impl Builder {
+    /// Create a builder with all options set to their defaults.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the encoding used to read and write the file.  Defaults to `Format::Json`.
+    pub fn format(&mut self, format: Format) -> &mut Self {
+        self.options.format = format;
+        self
+    }
+
+    /// Search [`local_user_app_dir()`](fn.local_user_app_dir.html) rather than the roaming
+    /// [`user_app_dir()`](fn.user_app_dir.html) when opening or creating the file.  This only
+    /// makes a difference on Windows.  Defaults to `false`.
+    pub fn prefer_local_appdata(&mut self, prefer_local_appdata: bool) -> &mut Self {
+        self.options.prefer_local_appdata = prefer_local_appdata;
+        self
+    }
+
+    /// Whether writes are flushed to disk (with `sync_all`) before returning, and, for functions
+    /// which create a file or rename a file into place, whether the containing directory is
+    /// flushed too so that the new directory entry survives a crash.  Flushing directories is a
+    /// no-op on Windows.
+    ///
+    /// Without this, written data may still be in the OS page cache and lost on power failure.
+    /// Disabling it is faster and may be suitable for throwaway data.  Defaults to `true`.
+    pub fn durable(&mut self, durable: bool) -> &mut Self {
+        self.options.durable = durable;
+        self
+    }
+
+    /// Which file locks are taken around reads and writes.  Defaults to `LockMode::Shared`, i.e.
+    /// the shared lock for reads and the exclusive lock for writes.
+    ///
+    /// If the filesystem doesn't support locking, accessing the file fails with
+    /// `Error::LockUnsupported` unless this is set to `LockMode::None`.
+    pub fn lock_mode(&mut self, lock_mode: LockMode) -> &mut Self {
+        self.options.lock_mode = lock_mode;
+        self
+    }
+
+    /// How often to retry opening the file for writing if it fails with a sharing violation or
+    /// access denied error on Windows, which are typically caused by antivirus software or the
+    /// search indexer briefly holding the file open.  The delay before the first retry is
+    /// `backoff`, doubling with each further retry; after `attempts` retries the original error
+    /// is returned.  This has no effect on other platforms.
+    ///
+    /// Defaults to 5 attempts with a backoff of 10ms, i.e. around 300ms in total.
+    pub fn sharing_violation_retry(&mut self, attempts: u32, backoff: Duration) -> &mut Self {
+        self.options.sharing_retry_attempts = attempts;
+        self.options.sharing_retry_backoff = backoff;
+        self
+    }
+
+    /// How often to retry opening, reading or writing the file if it fails with a transient
+    /// error, e.g. `EIO` or `ESTALE` from a network filesystem such as NFS, or one of the kinds set
+    /// by [`retry_on()`](#method.retry_on).  The delay before the first retry is `backoff`,
+    /// doubling with each further retry; after `attempts` retries the error is returned.  Other
+    /// errors are returned immediately.
+    ///
+    /// Defaults to no retries.
+    pub fn retry(&mut self, attempts: u32, backoff: Duration) -> &mut Self {
+        self.options.retry_attempts = attempts;
+        self.options.retry_backoff = backoff;
+        self
+    }
+
+    /// The kinds of IO error which are retried as set by [`retry()`](#method.retry), in addition
+    /// to `EIO` and `ESTALE`.  Defaults to `Interrupted`.
+    pub fn retry_on(&mut self, kinds: &[io::ErrorKind]) -> &mut Self {
+        self.options.retry_kinds = kinds.to_vec();
+        self
+    }
+
+    /// Never search or create the file in [`system_cache_dir()`](fn.system_cache_dir.html), e.g.
+    /// where writing to shared directories is forbidden.  If the file then can't be created in any
+    /// other location, `Error::NoWritableLocation` is returned.  Defaults to `false`.
+    pub fn disable_system_cache(&mut self, disable_system_cache: bool) -> &mut Self {
+        self.options.disable_system_cache = disable_system_cache;
+        self
+    }
+
+    /// Indent each level of written JSON by `width` spaces rather than the default of 2, so that
+    /// the file matches the style of hand-edited ones.  This has no effect on the binary formats.
+    pub fn indent(&mut self, width: usize) -> &mut Self {
+        self.options.indent = vec![b' '; width];
+        self
+    }
+
+    /// Indent each level of written JSON by `indent`, e.g. `b"\t"`.  See
+    /// [`indent()`](#method.indent).
+    pub fn indent_with(&mut self, indent: &[u8]) -> &mut Self {
+        self.options.indent = indent.to_vec();
+        self
+    }
+
+    /// End written JSON with a single newline, as expected of text files by POSIX tools and many
+    /// linters.  This has no effect on the binary formats, or on a `RawConfig`, whose bytes are
+    /// written verbatim.  Defaults to `false`, so that existing files are rewritten unchanged.
+    pub fn trailing_newline(&mut self, trailing_newline: bool) -> &mut Self {
+        self.options.trailing_newline = trailing_newline;
+        self
+    }
+
+    /// Refuse to read files larger than `max_size` bytes, failing with `Error::TooLarge` rather
+    /// than allocating enough memory to decode them, e.g. for a corrupt or malicious file in a
+    /// shared directory.  Defaults to 16 MiB.
+    pub fn max_size(&mut self, max_size: u64) -> &mut Self {
+        self.options.max_size = max_size;
+        self
+    }
+
+    /// If the file isn't found in a location under its exact name, use a file there whose name
+    /// only differs in case, e.g. `Config.json` for `config.json`, logging a warning.  This only
+    /// affects opening an existing file, and makes no difference on case-insensitive filesystems.
+    /// Defaults to `false`.
+    pub fn case_insensitive(&mut self, case_insensitive: bool) -> &mut Self {
+        self.options.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Create files with the Unix permission bits `mode`, e.g. `0o640` for a config readable by a
+    /// service group, regardless of the process umask.  By default new files are created with
+    /// `0o666` less the umask.  This is ignored on Windows.
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.options.mode = Some(mode);
+        self
+    }
+
+    /// Create the directories made by `create()` with the Unix permission bits `mode`, e.g.
+    /// `0o750`, regardless of the process umask.  By default they are created with `0o777` less
+    /// the umask.  This is ignored on Windows.
+    pub fn dir_mode(&mut self, mode: u32) -> &mut Self {
+        self.options.dir_mode = Some(mode);
+        self
+    }
+
+    /// Serialise the handler's writes within this process using `mutex` rather than the global
+    /// mutex shared by every handler, e.g. to give a plugin subsystem its own domain so that its
+    /// writes don't wait for the host application's.  Handlers sharing `mutex` are serialised with
+    /// each other, and where the documentation of a write refers to the global mutex, it is
+    /// `mutex` which is held.
+    ///
+    /// This only affects threads in this process: the file locks which serialise access between
+    /// processes are still taken as usual.  A [`batch()`](fn.batch.html) only holds the global
+    /// mutex, so it doesn't stop other threads writing through handlers with their own mutex.
+    pub fn with_mutex(&mut self, mutex: Arc<Mutex<()>>) -> &mut Self {
+        self.options.mutex = Some(mutex);
+        self
+    }
+
+    /// Encrypt the file at rest with ChaCha20-Poly1305 using `key`.  The file is serialised using
+    /// the chosen format, then encrypted with a random nonce which is stored at the start of the
+    /// file.  Reading a file which wasn't encrypted with `key` fails with `Error::Decryption`.
+    #[cfg(feature = "encryption")]
+    pub fn cipher(&mut self, key: [u8; 32]) -> &mut Self {
+        self.options.cipher_key = Some(key);
+        self
+    }
+
+    /// Pass the file's contents through `transform` between serialisation and disk, e.g. to
+    /// compress it.  Transforms are applied in the order they were added when writing, before any
+    /// encryption set with `cipher()`, and in reverse order when reading.
+    pub fn transform<B: ByteTransform + 'static>(&mut self, transform: B) -> &mut Self {
+        self.options.transforms.push(Arc::new(transform));
+        self
+    }
+
+    /// Open an existing file using these options.  See
+    /// [`FileHandler::open`](struct.FileHandler.html#method.open).
+    pub fn open<T, S: AsRef<OsStr> + ?Sized>(
+        &self,
+        name: &S,
+        assert_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        FileHandler::open_with_options(name, assert_writable, self.options.clone())
+    }
+
+    /// Open an existing file, or create one initialised to a default, using these options.  See
+    /// [`FileHandler::new`](struct.FileHandler.html#method.new).
+    pub fn create<T, S>(
+        &self,
+        name: &S,
+        is_existing_file_writable: bool,
+    ) -> Result<FileHandler<T>, Error>
+    where
+        T: Default + Serialize,
+        S: AsRef<OsStr> + ?Sized,
+    {
+        FileHandler::new_with_options(name, is_existing_file_writable, self.options.clone())
+    }
+}
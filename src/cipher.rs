@@ -0,0 +1,44 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Encryption at rest using ChaCha20-Poly1305.  Sealed contents are a random 96-bit nonce followed
+//! by the ciphertext and authentication tag.
+
+use crate::error::Error;
+use chacha20poly1305::aead::{Aead, AeadCore, AeadInPlace, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+const NONCE_LEN: usize = 12;
+
+/// Encrypt `plaintext` with `key`, returning the nonce-prefixed ciphertext.
+pub(crate) fn seal(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let cipher = ChaCha20Poly1305::new(key.into());
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| Error::Encryption)?;
+
+    let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt the nonce-prefixed ciphertext in `contents` with `key`, replacing it with the
+/// plaintext.  `contents` which are too short, or fail authentication, give `Error::Decryption`.
+pub(crate) fn open_in_place(key: &[u8; 32], contents: &mut Vec<u8>) -> Result<(), Error> {
+    if contents.len() < NONCE_LEN {
+        return Err(Error::Decryption);
+    }
+    let nonce = *Nonce::from_slice(&contents[..NONCE_LEN]);
+    let _ = contents.drain(..NONCE_LEN);
+    ChaCha20Poly1305::new(key.into())
+        .decrypt_in_place(&nonce, b"", contents)
+        .map_err(|_| Error::Decryption)
+}
@@ -0,0 +1,48 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A source of the current wall-clock time, e.g. for timestamping backups.  This allows tests to
+/// freeze time rather than sleeping.
+pub(crate) trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The clock used unless replaced by `set_clock_for_testing()`.
+struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+lazy_static! {
+    static ref CLOCK: Mutex<Arc<dyn Clock>> = Mutex::new(Arc::new(SystemClock));
+}
+
+/// The current time according to the crate's clock.
+pub(crate) fn now() -> SystemTime {
+    let clock = Arc::clone(&*unwrap!(CLOCK.lock()));
+    clock.now()
+}
+
+/// Replace the crate's clock with `clock` until `reset_clock()` is called.
+#[cfg(test)]
+pub(crate) fn set_clock_for_testing<C: Clock + 'static>(clock: C) {
+    *unwrap!(CLOCK.lock()) = Arc::new(clock);
+}
+
+/// Restore the system clock after `set_clock_for_testing()`.
+#[cfg(test)]
+pub(crate) fn reset_clock() {
+    *unwrap!(CLOCK.lock()) = Arc::new(SystemClock);
+}
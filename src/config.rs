@@ -0,0 +1,21 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A config type with a canonical file name, so that it can be opened without naming the file
+/// each time.
+///
+/// See [`FileHandler::open_default()`](struct.FileHandler.html#method.open_default) and
+/// [`FileHandler::new_default()`](struct.FileHandler.html#method.new_default).
+pub trait Config: Serialize + DeserializeOwned + Default {
+    /// The name of the file (not the full path) holding this config, e.g. `"crust.config"`.
+    const FILE_NAME: &'static str;
+}
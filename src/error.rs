@@ -7,34 +7,196 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
+#[cfg(feature = "json")]
 use serde_json::Error as JsonError;
 use std::env::VarError;
-use std::io::Error as IoError;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::PathBuf;
+use std::time::Duration;
 
 quick_error! {
     /// Error types.
+    // `quick_error!` doesn't accept doc comments on the fields of struct-like variants.
+    #[allow(missing_docs)]
     #[derive(Debug)]
     pub enum Error {
+        /// The file could not be created because it already exists.
+        AlreadyExists { path: PathBuf } {
+            display("File already exists: {}", path.display())
+        }
+        /// Wrapper for a `::bincode::Error`
+        #[cfg(feature = "bincode")]
+        Bincode(err: bincode::Error) {
+            display("Bincode error: {}", err)
+            source(err)
+        }
+        /// Wrapper for a `::ciborium` serialisation or deserialisation error
+        #[cfg(feature = "cbor")]
+        Cbor(err: Box<dyn ::std::error::Error + Send + Sync>) {
+            display("Cbor error: {}", err)
+            source(&**err)
+        }
+        /// The file was changed by someone else since it was read.
+        Conflict { path: PathBuf } {
+            display("{} was changed since it was read", path.display())
+        }
+        /// The file could not be decrypted, either because the key is wrong or because the file
+        /// has been modified or isn't encrypted.
+        #[cfg(feature = "encryption")]
+        Decryption {
+            display("Decryption failed: wrong key, or the file is corrupt or not encrypted")
+        }
+        /// The contents could not be encrypted.
+        #[cfg(feature = "encryption")]
+        Encryption {
+            display("Encryption failed")
+        }
         /// Wrapper for a `::std::env::VarError`
         Env(err: VarError) {
-            description("Environment error")
             display("Environment error: {}", err)
-            cause(err)
+            source(err)
             from()
         }
+        /// The file name contains a path separator or a component such as `..`, so it could refer
+        /// to a file outside the directories searched.
+        InvalidName { name: PathBuf } {
+            display("Invalid file name: {} must not contain path components", name.display())
+        }
         /// Wrapper for a `::std::io::Error`
         Io(err: IoError) {
-            description("IO error")
             display("IO error: {}", err)
-            cause(err)
+            source(err)
             from()
         }
+        /// Wrapper for a `::json5::Error`
+        #[cfg(feature = "json5")]
+        Json5(err: json5::Error) {
+            display("Json5 parse error: {}", err)
+            source(err)
+        }
+        /// Wrapper for a `::serde_json::Error`, with the path to the field which failed to decode,
+        /// e.g. `network.peers[3].port`.
+        #[cfg(feature = "detailed_errors")]
+        JsonAt { path: String, err: JsonError } {
+            display("Json parse error at {}: {}", path, err)
+            source(err)
+        }
         /// Wrapper for a `::serde_json::Error`
+        #[cfg(feature = "json")]
         JsonParser(err: JsonError) {
-            description("Json parse error")
             display("Json parse error: {}", err)
-            cause(err)
-            from()
+            source(err)
+        }
+        /// The filesystem doesn't support file locking, e.g. some network mounts.  Locking can be
+        /// disabled with `Builder::lock_mode(LockMode::None)`.
+        LockUnsupported(err: IoError) {
+            display("File locking is not supported here: {}", err)
+            source(err)
         }
+        /// The file could not be created in any of the locations searched.  Each attempted path is
+        /// listed with the reason it was rejected.
+        NoWritableLocation { attempts: Vec<(PathBuf, ErrorKind)> } {
+            display("No writable location found ({}); elevated privileges or an additional \
+                     search path may be needed", format_attempts(attempts))
+        }
+        /// The file could not be opened in any of the locations searched.  Each attempted path is
+        /// listed with the reason it was rejected, e.g. `NotFound`.
+        NotFoundAnywhere { attempts: Vec<(PathBuf, ErrorKind)> } {
+            display("Config file not found in any of the {} locations searched", attempts.len())
+        }
+        /// The file at `path` was to be decoded using a text format such as JSON, but its contents
+        /// are binary or not valid UTF-8.
+        NotText { path: PathBuf } {
+            display("{} is not a text config file: it contains binary data or invalid UTF-8",
+                    path.display())
+        }
+        /// The file at `path` could not be decoded.  `err` is the underlying error from the
+        /// file's format.
+        Parse { path: PathBuf, err: Box<Error> } {
+            display("failed to parse {}: {}", path.display(), err)
+            source(&**err)
+        }
+        /// The file was found, but it could not be opened for writing.
+        PermissionDenied { path: PathBuf } {
+            display("Permission denied: {} is not writable", path.display())
+        }
+        /// The file did not appear within `timeout`, as for `FileHandler::open_or_wait()`, or its
+        /// lock couldn't be acquired within it, as for
+        /// `FileHandler::lock_exclusive_guard_timeout()` and `FileHandler::open_with_deadline()`.
+        Timeout { name: PathBuf, timeout: Duration } {
+            display("Timed out after {:?} waiting for {}", timeout, name.display())
+        }
+        /// The file is larger than the limit set with `Builder::max_size()`.  If the file grew
+        /// while being read, `size` is the number of bytes read before giving up.
+        TooLarge { size: u64, limit: u64 } {
+            display("File is too large: {} bytes exceeds the limit of {}", size, limit)
+        }
+        /// An environment variable referenced in a path was not set.
+        UndefinedVariable { name: String } {
+            display("Environment variable {} is not set", name)
+        }
+        /// A URI given as a path has a scheme other than `file:`, as for `path_from_uri()`.
+        UnsupportedScheme { scheme: String } {
+            display("Unsupported URI scheme: {}", scheme)
+        }
+    }
+}
+
+fn format_attempts(attempts: &[(PathBuf, ErrorKind)]) -> String {
+    if attempts.is_empty() {
+        return "no location could be resolved".to_string();
+    }
+    attempts
+        .iter()
+        .map(|&(ref path, kind)| format!("{}: {:?}", path.display(), kind))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// `quick_error!` does not carry `cfg` attributes over to the `From` impls it generates, so these
+// are written out by hand for the feature-gated variants.
+
+#[cfg(feature = "json")]
+impl From<JsonError> for Error {
+    fn from(err: JsonError) -> Self {
+        Error::JsonParser(err)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Self {
+        Error::Bincode(err)
+    }
+}
+
+#[cfg(feature = "json5")]
+impl From<json5::Error> for Error {
+    fn from(err: json5::Error) -> Self {
+        Error::Json5(err)
+    }
+}
+
+#[cfg(feature = "detailed_errors")]
+impl From<serde_path_to_error::Error<JsonError>> for Error {
+    fn from(err: serde_path_to_error::Error<JsonError>) -> Self {
+        Error::JsonAt {
+            path: err.path().to_string(),
+            err: err.into_inner(),
+        }
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl From<ciborium::de::Error<IoError>> for Error {
+    fn from(err: ciborium::de::Error<IoError>) -> Self {
+        Error::Cbor(Box::new(err))
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl From<ciborium::ser::Error<IoError>> for Error {
+    fn from(err: ciborium::ser::Error<IoError>) -> Self {
+        Error::Cbor(Box::new(err))
     }
 }
@@ -7,27 +7,129 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
+use crate::builder::Options;
+use crate::clock;
+use crate::config::Config;
 use crate::error::Error;
+use crate::format::{self, Decode, Encode, Format, DEFAULT_INDENT};
 use crate::global_mutex;
+use crate::json_lines::JsonLinesHandler;
+use crate::location::Location;
+use crate::merge;
+use crate::name::validate_name;
+use crate::paths::{
+    self, bundle_resource_dir, current_bin_dir, not_found_error, system_cache_dir, user_app_dir,
+};
+#[cfg(feature = "mmap")]
+use crate::read_only::ReadOnlyHandler;
+use crate::shared::SharedConfig;
+use crate::snapshot::SnapshotHandler;
+use crate::uri::path_from_uri;
 use fs2::FileExt;
-use serde::de::DeserializeOwned;
-use serde::Serialize;
-use serde_json::{from_reader, to_string_pretty};
+use serde::de::{Deserialize, DeserializeOwned};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::cmp;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::env;
 use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::iter;
 use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-lazy_static! {
-    static ref ADDITIONAL_SEARCH_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+/// How often the file backing a `SharedConfig` is checked for changes.
+const SHARED_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// The first and longest delays between polls by `open_or_wait()`.
+const OPEN_WAIT_INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+const OPEN_WAIT_MAX_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Run `f` while holding the process-wide mutex which serialises writes, e.g. so that several
+/// related config files are updated as a group.
+///
+/// Calls which write a file, such as
+/// [`FileHandler::write_file()`](struct.FileHandler.html#method.write_file), normally take this
+/// mutex for their own duration.  Made from this thread within `f`, they instead rely on the
+/// batch's hold of it, so other threads' writes and batches can't interleave with them.  The
+/// per-file locks are still taken and released by each call rather than held across the batch,
+/// so processes which don't share the mutex, and reads, which don't take it, can still observe
+/// the files between writes.
+pub fn batch<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    global_mutex::hold_for(f)
+}
+
+/// Which of the two possible outcomes [`FileHandler::open_or_create()`]
+/// (struct.FileHandler.html#method.open_or_create) resulted in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CreationOutcome {
+    /// An existing file was opened.
+    Opened,
+    /// The file didn't exist and was created, initialised to a default.
+    Created,
+}
+
+/// Which file locks are taken around reads and writes, as set with [`Builder::lock_mode()`]
+/// (struct.Builder.html#method.lock_mode).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Reads also take the exclusive lock, so they are serialised with each other as well as with
+    /// writes.
+    Exclusive,
+    /// Reads take the shared lock and writes the exclusive lock.  This is the default.
+    #[default]
+    Shared,
+    /// No locks are taken, for filesystems which don't support them, such as some network mounts.
+    /// Readers may then see a partially-written file, and concurrent writes may interleave.
+    None,
 }
 
-/// Set an additional search path. This, if set, will be tried before the other default ones.
-pub fn set_additional_search_path<P: AsRef<OsStr> + ?Sized>(path: &P) {
-    *unwrap!(ADDITIONAL_SEARCH_PATH.lock()) = Some(From::from(path));
+/// How [`FileHandler::open_resilient()`](struct.FileHandler.html#method.open_resilient) obtained
+/// a usable file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Recovery {
+    /// An existing, valid file was opened.
+    Opened,
+    /// The file didn't exist and was created, initialised to a default.
+    Created,
+    /// The existing file couldn't be decoded, so it was moved to `backup` and the file was
+    /// recreated, initialised to a default.
+    RecoveredFromCorrupt {
+        /// The new path of the file which couldn't be decoded.
+        backup: PathBuf,
+    },
+}
+
+/// Facts about a config file, as returned by
+/// [`FileHandler::metadata()`](struct.FileHandler.html#method.metadata).
+#[derive(Clone, Debug)]
+pub struct ConfigMetadata {
+    /// The full path to the file.
+    pub path: PathBuf,
+    /// The kind of location the file was found or created in.
+    pub location: Location,
+    /// The size of the file in bytes.
+    pub len: u64,
+    /// When the file was last modified.
+    pub modified: SystemTime,
+    /// Whether the file could be opened for writing, or for a handler created by
+    /// `FileHandler::from_file()`, whether its handle was opened for writing.  This is `None` if it
+    /// can't be determined, which is the case for an inherited handle other than on Unix.
+    pub writable: Option<bool>,
 }
 
 /// Struct for reading and writing config files.
@@ -38,9 +140,46 @@ pub fn set_additional_search_path<P: AsRef<OsStr> + ?Sized>(path: &P) {
 /// in multiple threads and/or processes.
 pub struct FileHandler<T> {
     path: PathBuf,
+    location: Location,
+    assert_writable: bool,
+    options: Options,
+    was_created: bool,
+    inherited: Option<Arc<Mutex<File>>>,
+    memory: Option<Arc<Mutex<Vec<u8>>>>,
+    write_hooks: Vec<WriteHook>,
     _ph: PhantomData<T>,
 }
 
+/// A callback registered with `FileHandler::on_write()`.
+type WriteHook = Arc<dyn Fn(&Path) + Send + Sync>;
+
+/// The file backing a handler, opened for a single operation: either a newly-opened file, or the
+/// inherited file of a handler created by `from_file()`, which is locked for the duration.
+enum OpenedFile<'a> {
+    Opened(File),
+    Inherited(MutexGuard<'a, File>),
+}
+
+impl<'a> Deref for OpenedFile<'a> {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        match *self {
+            OpenedFile::Opened(ref file) => file,
+            OpenedFile::Inherited(ref file) => file,
+        }
+    }
+}
+
+impl<'a> DerefMut for OpenedFile<'a> {
+    fn deref_mut(&mut self) -> &mut File {
+        match *self {
+            OpenedFile::Opened(ref mut file) => file,
+            OpenedFile::Inherited(ref mut file) => file,
+        }
+    }
+}
+
 impl<T> FileHandler<T> {
     /// Constructor taking the required file name (not the full path)
     /// This function will return an error if the file does not exist.
@@ -55,597 +194,6170 @@ impl<T> FileHandler<T> {
     ///
     /// Parameter `assert_writable` dictates if the file should be writable or not.
     ///
+    /// `name` must be a plain file name: if it contains a path separator or a component such as
+    /// `..`, `Error::InvalidName` is returned.  See [`open_unchecked()`](#method.open_unchecked).
+    ///
+    /// If the file can't be opened in any location, `Error::NotFoundAnywhere` is returned, listing
+    /// each path tried and why it was rejected.
+    ///
     /// See [Thread- and Process-Safety](#thread--and-process-safety) for notes on thread- and
     /// process-safety.
     pub fn open<S: AsRef<OsStr> + ?Sized>(
         name: &S,
         assert_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        Self::open_with_options(name, assert_writable, Options::default())
+    }
+
+    /// Open an existing file like [`open()`](#method.open), without checking that `name` is a plain
+    /// file name.
+    ///
+    /// `open` rejects names containing path separators or `..` components with
+    /// `Error::InvalidName`, so that a name derived from untrusted input can't refer to a file
+    /// outside the directories searched.  This allows e.g. a config kept in a subdirectory of each
+    /// location to be opened as `"plugins/example.json"`, and must only be used with trusted names.
+    pub fn open_unchecked<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        assert_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        Self::search_and_open(
+            name.as_ref(),
+            assert_writable,
+            Options::default(),
+            |_, _| true,
+        )
+    }
+
+    /// Open an existing file like [`open()`](#method.open), only considering the copies for which
+    /// `predicate` returns `true`, e.g. to only accept a security-sensitive config from a
+    /// directory owned by root and ignore a copy shadowing it in the user's directory.
+    ///
+    /// `predicate` is called with the path and location of each candidate in the usual search
+    /// order, before it is opened, whether or not it exists.  The first candidate which passes and
+    /// can be opened is used.
+    pub fn open_filtered<S, P>(
+        name: &S,
+        assert_writable: bool,
+        predicate: P,
+    ) -> Result<FileHandler<T>, Error>
+    where
+        S: AsRef<OsStr> + ?Sized,
+        P: Fn(&Path, Location) -> bool,
+    {
+        let name = name.as_ref();
+        validate_name(name)?;
+        Self::search_and_open(name, assert_writable, Options::default(), predicate)
+    }
+
+    pub(crate) fn open_with_options<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        assert_writable: bool,
+        options: Options,
     ) -> Result<FileHandler<T>, Error> {
         let name = name.as_ref();
+        validate_name(name)?;
+        Self::search_and_open(name, assert_writable, options, |_, _| true)
+    }
 
-        if let Some(mut path) = unwrap!(ADDITIONAL_SEARCH_PATH.lock()).clone() {
-            path.push(name);
-            if OpenOptions::new()
-                .read(true)
-                .write(assert_writable)
-                .open(&path)
-                .is_ok()
-            {
-                return Ok(FileHandler {
-                    path,
-                    _ph: PhantomData,
-                });
+    fn search_and_open<P: Fn(&Path, Location) -> bool>(
+        name: &OsStr,
+        assert_writable: bool,
+        options: Options,
+        predicate: P,
+    ) -> Result<FileHandler<T>, Error> {
+        let mut attempts = Vec::new();
+
+        for (location, dir) in search_dirs(&options) {
+            let mut path = match dir {
+                Ok(dir) => dir.join(name),
+                Err(e) => {
+                    debug!("Skipped {:?}: {}", location, e);
+                    continue;
+                }
+            };
+            if options.case_insensitive && !path.exists() {
+                if let Some(found) = find_case_insensitive(&path) {
+                    warn!(
+                        "Using {} for {:?}, whose case doesn't match",
+                        found.display(),
+                        name
+                    );
+                    path = found;
+                }
+            }
+            if !predicate(&path, location) {
+                debug!(
+                    "Skipped {} ({:?}): rejected by predicate",
+                    path.display(),
+                    location
+                );
+                continue;
+            }
+            let opened = with_retry(&options, || {
+                OpenOptions::new()
+                    .read(true)
+                    .write(assert_writable)
+                    .open(&path)
+            });
+            match opened {
+                Ok(_) => {
+                    debug!("Opened {} ({:?})", path.display(), location);
+                    if location == Location::SystemCacheDir {
+                        warn!(
+                            "Fell through to the system cache dir for {}",
+                            path.display()
+                        );
+                    }
+                    return Ok(FileHandler::from_path(
+                        path,
+                        location,
+                        assert_writable,
+                        options,
+                    ));
+                }
+                Err(e) => {
+                    debug!("Skipped {} ({:?}): {}", path.display(), location, e);
+                    attempts.push((path, e.kind()));
+                }
             }
         }
 
-        if let Ok(mut path) = current_bin_dir() {
-            path.push(name);
-            if OpenOptions::new()
+        Err(Error::NotFoundAnywhere { attempts })
+    }
+
+    /// Open an existing file like [`open()`](#method.open), but return `Ok(None)` if there is no
+    /// copy of it in any location, so that a missing config can be told apart from a broken one.
+    ///
+    /// If no location has a usable copy, but opening one failed for a reason other than it not
+    /// existing (e.g. `PermissionDenied` when `assert_writable` is set), that error is returned.
+    pub fn try_open<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        assert_writable: bool,
+    ) -> Result<Option<FileHandler<T>>, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let options = Options::default();
+        let mut error = None;
+
+        for (location, dir) in search_dirs(&options) {
+            let path = match dir {
+                Ok(dir) => dir.join(name),
+                Err(_) => continue,
+            };
+            match OpenOptions::new()
                 .read(true)
                 .write(assert_writable)
                 .open(&path)
-                .is_ok()
             {
-                return Ok(FileHandler {
-                    path,
-                    _ph: PhantomData,
-                });
+                Ok(_) => {
+                    return Ok(Some(FileHandler::from_path(
+                        path,
+                        location,
+                        assert_writable,
+                        options,
+                    )))
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => {
+                    let _ = error.get_or_insert(e);
+                }
             }
         }
 
-        if let Ok(mut path) = bundle_resource_dir() {
-            path.push(name);
-            if OpenOptions::new()
-                .read(true)
-                .write(assert_writable)
-                .open(&path)
-                .is_ok()
-            {
-                return Ok(FileHandler {
-                    path,
-                    _ph: PhantomData,
+        match error {
+            Some(e) => Err(e.into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Open the first of `names` which exists, e.g. `&["config.json", "settings.json"]` to prefer
+    /// a file's current name over its legacy one.
+    ///
+    /// Each name is searched for in every location, as for [`open()`](#method.open), before the
+    /// next name is tried.  If a copy of a name is found but can't be opened, e.g. because
+    /// `assert_writable` is set and it is read-only, that error is returned rather than falling
+    /// back to the next name.  If none of them exist, a `NotFound` IO error is returned.
+    pub fn open_first_of<S: AsRef<OsStr> + ?Sized>(
+        names: &[&S],
+        assert_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        for name in names {
+            validate_name(name.as_ref())?;
+            if let Some(file_handler) = Self::try_open(*name, assert_writable)? {
+                return Ok(file_handler);
+            }
+        }
+        let names = names.iter().map(|name| name.as_ref()).collect::<Vec<_>>();
+        Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("None of {:?} found", names),
+        )))
+    }
+
+    /// Open each of `names` for reading, as for [`open()`](#method.open), e.g. to check at startup
+    /// that every config is present and readable.
+    ///
+    /// Returns the handlers in the same order as `names` if all of them could be opened, and
+    /// otherwise every name which couldn't be, with its error.
+    pub fn prewarm<S: AsRef<OsStr> + ?Sized>(
+        names: &[&S],
+    ) -> Result<Vec<FileHandler<T>>, Vec<(OsString, Error)>> {
+        let mut file_handlers = Vec::with_capacity(names.len());
+        let mut failures = Vec::new();
+        for name in names {
+            match Self::open(*name, false) {
+                Ok(file_handler) => file_handlers.push(file_handler),
+                Err(e) => failures.push((name.as_ref().to_os_string(), e)),
+            }
+        }
+        if failures.is_empty() {
+            Ok(file_handlers)
+        } else {
+            Err(failures)
+        }
+    }
+
+    /// Open an existing file like [`open()`](#method.open), waiting up to `timeout` for it to
+    /// appear if there is no copy of it yet, e.g. when it is written by another process started
+    /// alongside this one.
+    ///
+    /// The locations are polled, starting after 10ms and backing off to every 250ms, until a
+    /// non-empty copy of the file is found.  If there is none when `timeout` elapses,
+    /// `Error::Timeout` is returned.  Errors other than the file not existing, as for
+    /// [`try_open()`](#method.try_open), are returned immediately.
+    pub fn open_or_wait<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        assert_writable: bool,
+        timeout: Duration,
+    ) -> Result<FileHandler<T>, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let deadline = Instant::now() + timeout;
+        let mut backoff = OPEN_WAIT_INITIAL_BACKOFF;
+        loop {
+            if let Some(file_handler) = Self::try_open(name, assert_writable)? {
+                if fs::metadata(file_handler.path())?.len() > 0 {
+                    return Ok(file_handler);
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout {
+                    name: PathBuf::from(name),
+                    timeout,
                 });
             }
+            thread::sleep(cmp::min(backoff, deadline - now));
+            backoff = cmp::min(backoff * 2, OPEN_WAIT_MAX_BACKOFF);
         }
+    }
 
-        if let Ok(mut path) = user_app_dir() {
-            path.push(name);
-            if OpenOptions::new()
-                .read(true)
-                .write(assert_writable)
-                .open(&path)
-                .is_ok()
-            {
-                return Ok(FileHandler {
-                    path,
-                    _ph: PhantomData,
+    /// Open an existing file like [`open_or_wait()`](#method.open_or_wait), within a single
+    /// overall `deadline` covering both waiting for the file to appear and then for its shared
+    /// lock, e.g. for a bounded-time startup.
+    ///
+    /// Whatever time is left once the file is found is spent waiting for the lock, which is
+    /// polled as for [`lock_exclusive_guard_timeout()`](#method.lock_exclusive_guard_timeout)
+    /// and released again before this returns.  If either stage is still waiting at `deadline`,
+    /// `Error::Timeout` is returned.
+    pub fn open_with_deadline<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        assert_writable: bool,
+        deadline: Instant,
+    ) -> Result<FileHandler<T>, Error> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let file_handler = Self::open_or_wait(name, assert_writable, timeout)?;
+
+        let options = &file_handler.options;
+        if options.lock_mode != LockMode::None {
+            let file = file_handler.open_read()?;
+            let exclusive = options.lock_mode == LockMode::Exclusive;
+            if !lock_until(&file, exclusive, deadline, options.lock_poll_interval)? {
+                return Err(Error::Timeout {
+                    name: file_handler.path.clone(),
+                    timeout,
                 });
             }
+            file.unlock()?;
         }
+        Ok(file_handler)
+    }
 
-        let mut path = system_cache_dir()?;
-        path.push(name);
-        match OpenOptions::new()
+    /// The path at which [`new()`](#method.new) would create the file `name` if it doesn't exist
+    /// yet, e.g. to tell the user where their config will be stored before it is created.
+    ///
+    /// This checks the locations in the same order as `new`, and returns the first one which is
+    /// writable, without creating any file or directory.  If there is none,
+    /// `Error::NoWritableLocation` is returned.  If the file already exists, `new` opens it
+    /// instead; see [`open()`](#method.open).
+    pub fn default_creation_path<S: AsRef<OsStr> + ?Sized>(name: &S) -> Result<PathBuf, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        creation_path(name, &Options::default()).map(|(_, path)| path)
+    }
+
+    /// Open the existing file `name` in exactly the directory `base`, e.g. one given by a
+    /// `--data-dir` flag, rather than searching the usual locations.
+    ///
+    /// `name` is checked as for [`open()`](#method.open), and
+    /// [`location()`](#method.location) of the returned handler is `Location::Explicit`.
+    pub fn open_with_base<P: AsRef<Path>, S: AsRef<OsStr> + ?Sized>(
+        base: P,
+        name: &S,
+        assert_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let path = base.as_ref().join(name);
+        let _ = OpenOptions::new()
             .read(true)
             .write(assert_writable)
-            .open(&path)
-        {
-            Ok(_) => Ok(FileHandler {
+            .open(&path)?;
+        Ok(FileHandler::from_path(
+            path,
+            Location::Explicit,
+            assert_writable,
+            Options::default(),
+        ))
+    }
+
+    /// Open the existing file at `uri`, which may be a plain path or a `file://` URL, converted as
+    /// for [`path_from_uri()`](fn.path_from_uri.html).  No search is done, as for
+    /// [`open_with_base()`](#method.open_with_base) with the parent directory of the path.
+    pub fn open_uri(uri: &str, assert_writable: bool) -> Result<FileHandler<T>, Error> {
+        let path = path_from_uri(uri)?;
+        match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => Self::open_with_base(dir, name, assert_writable),
+            _ => Err(Error::InvalidName { name: path }),
+        }
+    }
+
+    /// Try to open the file `name` in every location searched by [`open()`](#method.open), e.g. to
+    /// show which copies shadow which.
+    ///
+    /// Unlike `open`, this doesn't stop at the first copy found: one result is returned per
+    /// location, in search order, and [`location()`](#method.location) tells them apart.
+    pub fn open_all<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        assert_writable: bool,
+    ) -> Vec<Result<FileHandler<T>, Error>> {
+        let name = name.as_ref();
+        if let Err(e) = validate_name(name) {
+            return vec![Err(e)];
+        }
+        let options = Options::default();
+        search_dirs(&options)
+            .into_iter()
+            .map(|(location, dir)| {
+                let path = dir?.join(name);
+                let _ = OpenOptions::new()
+                    .read(true)
+                    .write(assert_writable)
+                    .open(&path)?;
+                Ok(FileHandler::from_path(
+                    path,
+                    location,
+                    assert_writable,
+                    options.clone(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Open the most recently modified copy of the file `name` in any of the locations searched by
+    /// [`open()`](#method.open), rather than the first one found.
+    ///
+    /// This supports a "last writer wins" policy where a config may be written in several
+    /// locations.  Copies with the same modification time are ranked in the usual search order.
+    /// If no copy can be opened, the error from the last location tried is returned.
+    pub fn open_newest<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        assert_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let options = Options::default();
+        let mut newest: Option<(SystemTime, Location, PathBuf)> = None;
+        let mut last_error = None;
+
+        for (location, dir) in search_dirs(&options) {
+            let modified = dir.and_then(|dir| {
+                let path = dir.join(name);
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(assert_writable)
+                    .open(&path)?;
+                Ok((file.metadata()?.modified()?, path))
+            });
+            match modified {
+                Ok((modified, path)) => {
+                    if newest.as_ref().is_none_or(|newest| modified > newest.0) {
+                        newest = Some((modified, location, path));
+                    }
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        match newest {
+            Some((_, location, path)) => Ok(FileHandler::from_path(
                 path,
-                _ph: PhantomData,
-            }),
-            Err(e) => Err(From::from(e)),
+                location,
+                assert_writable,
+                options,
+            )),
+            None => Err(unwrap!(last_error)),
         }
     }
 
-    /// Get the full path to the file.
-    pub fn path(&self) -> &Path {
-        &self.path
+    /// Open the variant of `base_name` for `locale`, falling back to less specific variants.
+    ///
+    /// For a `base_name` of `messages.json` and a `locale` of `fr_CA` (or `fr-CA`), this tries
+    /// `messages.fr_CA.json`, then `messages.fr.json`, then `messages.json`, each across all the
+    /// locations searched by [`open()`](#method.open).  The first readable match wins.  If none is
+    /// found, the error from opening `base_name` is returned.
+    pub fn open_localized<S: AsRef<OsStr> + ?Sized>(
+        base_name: &S,
+        locale: &str,
+        assert_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        let base_name = Path::new(base_name.as_ref());
+        let language = locale.split(['_', '-']).next().unwrap_or("");
+
+        let mut tags = vec![locale];
+        if language != locale {
+            tags.push(language);
+        }
+        for tag in tags.into_iter().filter(|tag| !tag.is_empty()) {
+            let name = variant_name(base_name, tag);
+            if let Ok(file_handler) = Self::open(&name, assert_writable) {
+                return Ok(file_handler);
+            }
+        }
+        Self::open(base_name, assert_writable)
     }
-}
 
-impl<T> FileHandler<T>
-where
-    T: Default + Serialize,
-{
-    /// Constructor taking the required file name (not the full path)
-    /// The config file will be initialised to a default if it does not exist.
+    /// Open the variant of `base_name` for the deployment `profile`, falling back to `base_name`
+    /// itself.
     ///
-    /// This function tests whether it has read access to the file in the following locations in
-    /// this order (see also [an example config file flowchart]
-    /// (https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf)):
+    /// For a `base_name` of `config.json` and a `profile` of `prod`, this opens
+    /// `config.prod.json` if it is found in any of the locations searched by
+    /// [`open()`](#method.open), and otherwise `config.json`.  If `profile` is empty, the value of
+    /// the `CONFIG_PROFILE` environment variable is used; if that is unset or empty too,
+    /// `base_name` is opened directly.  A profiled file which is found but can't be opened, e.g.
+    /// because `assert_writable` is set and it is read-only, is an error rather than falling back.
+    pub fn open_with_profile<S: AsRef<OsStr> + ?Sized>(
+        base_name: &S,
+        profile: &str,
+        assert_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        let base_name = Path::new(base_name.as_ref());
+        let profile = match profile {
+            "" => env::var(PROFILE_ENV_VAR).unwrap_or_default(),
+            profile => profile.to_owned(),
+        };
+        if !profile.is_empty() {
+            let name = variant_name(base_name, &profile);
+            validate_name(&name)?;
+            if let Some(file_handler) = Self::try_open(&name, assert_writable)? {
+                return Ok(file_handler);
+            }
+        }
+        Self::open(base_name, assert_writable)
+    }
+
+    /// Move the file `name` from the location `from` to `to`, e.g. from
+    /// `Location::SystemCacheDir` where an older version of the application kept it up to
+    /// `Location::UserAppDir`, and open it there.
     ///
-    ///   1. [`current_bin_dir()`](fn.current_bin_dir.html)
-    ///   2. [`user_app_dir()`](fn.user_app_dir.html)
-    ///   3. [`system_cache_dir()`](fn.system_cache_dir.html)
+    /// The file is moved as by [`relocate_to()`](#method.relocate_to) if there is a copy in
+    /// `from` but none in `to`, and the directory of `to` is writable, creating it if `new()`
+    /// would.  Otherwise, or if the move fails, which is logged, the file is opened wherever it
+    /// is found, as for [`open()`](#method.open).
+    pub fn migrate_location<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        from: Location,
+        to: Location,
+        assert_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let options = Options::default();
+
+        let source = first_readable(name, &[from], &options);
+        if let (Some((_, path)), None) = (source, first_readable(name, &[to], &options)) {
+            let target_dir = creation_dirs(&options)
+                .into_iter()
+                .filter(|&(location, _, _)| location == to)
+                .find_map(|(_, dir, create_dir)| dir.ok().map(|dir| (dir, create_dir)));
+            if let Some((dir, create_dir)) = target_dir {
+                if create_dir && !dir.is_dir() {
+                    if let Err(e) = create_dir_all(&dir, &options) {
+                        debug!("Couldn't create {}: {}", dir.display(), e);
+                    }
+                }
+                if dir.is_dir() && is_dir_writable(&dir) {
+                    let mut file_handler =
+                        FileHandler::from_path(path, from, assert_writable, options.clone());
+                    match file_handler.relocate_to(&dir) {
+                        Ok(()) => {
+                            debug!(
+                                "Migrated {:?} from {:?} to {}",
+                                name,
+                                from,
+                                file_handler.path.display()
+                            );
+                            file_handler.location = to;
+                            return Ok(file_handler);
+                        }
+                        Err(e) => warn!("Failed to migrate {:?} to {:?}: {}", name, to, e),
+                    }
+                }
+            }
+        }
+        Self::open_with_options(name, assert_writable, options)
+    }
+
+    /// Open an existing file like [`open()`](#method.open), encrypted at rest with `key`.
     ///
-    /// Parameter `is_existing_file_writable` will assert that if the file pre-exists should it be
-    /// also writable or not. (E.g. it is enough for `crust-config` file to merely exist as
-    /// readable, but `bootstrap-cache` must be writable too if it exists, else no updation can
-    /// happen).
+    /// This is shorthand for opening with [`Builder::cipher()`](struct.Builder.html#method.cipher),
+    /// which can also be used to create the file initialised to an encrypted default.
+    #[cfg(feature = "encryption")]
+    pub fn with_cipher<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        assert_writable: bool,
+        key: [u8; 32],
+    ) -> Result<FileHandler<T>, Error> {
+        let options = Options {
+            cipher_key: Some(key),
+            ..Options::default()
+        };
+        Self::open_with_options(name, assert_writable, options)
+    }
+
+    /// Open an existing file like [`open()`](#method.open), taking the file locks given by
+    /// `lock_mode` around reads and writes.
     ///
-    /// See [Thread- and Process-Safety](#thread--and-process-safety) for notes on thread- and
-    /// process-safety.
-    #[allow(clippy::new_ret_no_self)]
-    pub fn new<S: AsRef<OsStr> + ?Sized>(
+    /// See [`Builder::lock_mode()`](struct.Builder.html#method.lock_mode).
+    pub fn open_with_lock_mode<S: AsRef<OsStr> + ?Sized>(
         name: &S,
-        is_existing_file_writable: bool,
+        assert_writable: bool,
+        lock_mode: LockMode,
     ) -> Result<FileHandler<T>, Error> {
-        if let Ok(fh) = Self::open(name, is_existing_file_writable) {
-            return Ok(fh);
+        let options = Options {
+            lock_mode,
+            ..Options::default()
+        };
+        Self::open_with_options(name, assert_writable, options)
+    }
+
+    /// Open an existing file like [`open()`](#method.open) and memory-map it, for fast repeated
+    /// reads of a large file which rarely changes.
+    ///
+    /// See [`ReadOnlyHandler`](struct.ReadOnlyHandler.html) for the assumptions this makes about
+    /// other writers.
+    #[cfg(feature = "mmap")]
+    pub fn open_read_only<S: AsRef<OsStr> + ?Sized>(name: &S) -> Result<ReadOnlyHandler<T>, Error> {
+        let file_handler = Self::open(name, false)?;
+        ReadOnlyHandler::new(file_handler.path, file_handler.options)
+    }
+
+    /// Open the newline-delimited JSON file `name`, creating it empty in the first writable of the
+    /// locations tried by [`new()`](#method.new) if no copy of it exists yet.
+    ///
+    /// See [`JsonLinesHandler`](struct.JsonLinesHandler.html) for how records are stored.
+    pub fn open_json_lines<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+    ) -> Result<JsonLinesHandler<T>, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        if let Some(file_handler) = Self::try_open(name, true)? {
+            return Ok(JsonLinesHandler::new(
+                file_handler.path,
+                file_handler.options,
+            ));
+        }
+
+        let options = Options::default();
+        let _guard = global_mutex::lock();
+        let (_, path, _) = create_or_keep_file(name, b"", &options, true)?;
+        Ok(JsonLinesHandler::new(path, options))
+    }
+
+    /// Take a private copy of the file, so that it can be read repeatedly with the same result
+    /// even if the file is rewritten in the meantime, without holding its lock throughout.
+    ///
+    /// The file is copied verbatim under the shared lock to a new file in the system temp
+    /// directory, which is removed when the returned [`SnapshotHandler`]
+    /// (struct.SnapshotHandler.html) is dropped.
+    pub fn snapshot(&self) -> Result<SnapshotHandler<T>, Error> {
+        let contents = self.read_raw()?;
+        SnapshotHandler::new(&self.path, &contents, self.options.clone())
+    }
+
+    /// Constructor taking the required file name (not the full path), which requires the file to
+    /// be writable.
+    ///
+    /// The locations are searched in the same order as for [`open()`](#method.open), but the
+    /// search stops at the first location where the file is readable.  If that file can't also be
+    /// opened for writing, `Error::PermissionDenied` is returned with its path, rather than falling
+    /// through to the next location.  This makes a config which exists but is read-only
+    /// diagnosable.  If the file isn't readable in any location, `Error::NotFoundAnywhere` is
+    /// returned, as for `open`.
+    pub fn open_writable<S: AsRef<OsStr> + ?Sized>(name: &S) -> Result<FileHandler<T>, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let mut attempts = Vec::new();
+
+        for (location, dir) in search_dirs(&Options::default()) {
+            let path = match dir {
+                Ok(dir) => dir.join(name),
+                Err(e) => {
+                    debug!("Skipped {:?}: {}", location, e);
+                    continue;
+                }
+            };
+            if let Err(e) = File::open(&path) {
+                attempts.push((path, e.kind()));
+                continue;
+            }
+            let opened = check_simulated_read_only(&path)
+                .and_then(|()| OpenOptions::new().write(true).open(&path));
+            return match opened {
+                Ok(_) => Ok(FileHandler::from_path(
+                    path,
+                    location,
+                    true,
+                    Options::default(),
+                )),
+                Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied || is_read_only_fs(e) => {
+                    Err(Error::PermissionDenied { path })
+                }
+                Err(e) => Err(From::from(e)),
+            };
+        }
+
+        Err(Error::NotFoundAnywhere { attempts })
+    }
+
+    /// Constructor taking the required file name (not the full path), which creates an empty file
+    /// if it doesn't already exist.
+    ///
+    /// This is intended for sentinel files whose presence matters rather than their contents.  An
+    /// existing writable file is opened as by [`open()`](#method.open) and left unchanged;
+    /// otherwise a zero-byte file is created in the first writable location, in the same order as
+    /// for [`new()`](#method.new), without serialising anything.  See also
+    /// [`read_file_or_default()`](#method.read_file_or_default).
+    pub fn touch<S: AsRef<OsStr> + ?Sized>(name: &S) -> Result<FileHandler<T>, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let options = Options::default();
+        if let Ok(file_handler) = Self::open_with_options(name, true, options.clone()) {
+            return Ok(file_handler);
+        }
+
+        let _guard = global_mutex::lock();
+
+        let (location, path, created) = create_or_keep_file(name, &[], &options, true)?;
+        let mut file_handler = FileHandler::from_path(path, location, true, options);
+        file_handler.was_created = created;
+        Ok(file_handler)
+    }
+
+    fn from_path(
+        path: PathBuf,
+        location: Location,
+        assert_writable: bool,
+        options: Options,
+    ) -> FileHandler<T> {
+        FileHandler {
+            path,
+            location,
+            assert_writable,
+            options,
+            was_created: false,
+            inherited: None,
+            memory: None,
+            write_hooks: Vec::new(),
+            _ph: PhantomData,
         }
+    }
+
+    /// Create a handler which reads and writes the already-open `file`, e.g. one inherited from a
+    /// supervising process in a sandbox where the file's path can't be opened.
+    ///
+    /// No search is done, and the file is locked as usual on each read and write.  `file` must have
+    /// been opened for reading, and also for writing if the handler will be written, and must not
+    /// have been opened in append mode.  The handler has an empty [`path()`](#method.path) and a
+    /// [`location()`](#method.location) of `Location::Explicit`, so methods which operate on the
+    /// path rather than the file's contents, e.g. [`relocate_to()`](#method.relocate_to) or
+    /// [`begin()`](#method.begin), fail.  Clones of the handler share the file.
+    pub fn from_file(file: File) -> FileHandler<T> {
+        let mut file_handler = FileHandler::from_path(
+            PathBuf::new(),
+            Location::Explicit,
+            false,
+            Options::default(),
+        );
+        file_handler.inherited = Some(Arc::new(Mutex::new(file)));
+        file_handler
+    }
+
+    /// Create a handler which reads and writes the open file descriptor `fd`, taking ownership of
+    /// it.  See [`from_file()`](#method.from_file).
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open file descriptor which isn't owned by anything else, as for
+    /// `std::os::unix::io::FromRawFd::from_raw_fd()`.
+    #[cfg(unix)]
+    #[allow(unsafe_code)]
+    pub unsafe fn from_raw_fd(fd: std::os::unix::io::RawFd) -> FileHandler<T> {
+        use std::os::unix::io::FromRawFd;
+
+        Self::from_file(File::from_raw_fd(fd))
+    }
+
+    /// Create a handler which reads and writes the open file handle `handle`, taking ownership of
+    /// it.  See [`from_file()`](#method.from_file).
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be an open file handle which isn't owned by anything else, as for
+    /// `std::os::windows::io::FromRawHandle::from_raw_handle()`.
+    #[cfg(windows)]
+    #[allow(unsafe_code)]
+    pub unsafe fn from_raw_handle(handle: std::os::windows::io::RawHandle) -> FileHandler<T> {
+        use std::os::windows::io::FromRawHandle;
+
+        Self::from_file(File::from_raw_handle(handle))
+    }
+
+    /// Reinterpret the handler as one for a value of type `U`, e.g. to read the same file as a
+    /// `serde_json::Value` for inspection and as a typed config for use, without searching for it
+    /// again.  The path, location and options are kept.
+    pub fn map<U>(self) -> FileHandler<U> {
+        FileHandler {
+            path: self.path,
+            location: self.location,
+            assert_writable: self.assert_writable,
+            options: self.options,
+            was_created: self.was_created,
+            inherited: self.inherited,
+            memory: self.memory,
+            write_hooks: self.write_hooks,
+            _ph: PhantomData,
+        }
+    }
+
+    /// Register `hook` to be called with the file's path after each successful write through this
+    /// handler, e.g. to record config changes in an audit log.  Several hooks may be registered,
+    /// and are called in order.  Clones of the handler share the hooks registered so far.
+    ///
+    /// Hooks are called once the write has completed and its locks have been released, so they
+    /// may themselves read or write config files.  A hook which panics is caught and logged, and
+    /// doesn't fail the write or stop the other hooks from running.
+    pub fn on_write<F>(&mut self, hook: F)
+    where
+        F: Fn(&Path) + Send + Sync + 'static,
+    {
+        self.write_hooks.push(Arc::new(hook));
+    }
+
+    /// Call the hooks registered with `on_write()` if `result` is of a successful write.
+    fn notify_write<R>(
+        &self,
+        result: Result<R, Error>,
+        written: fn(&R) -> bool,
+    ) -> Result<R, Error> {
+        if result.as_ref().is_ok_and(written) {
+            self.run_write_hooks();
+        }
+        result
+    }
+
+    fn run_write_hooks(&self) {
+        for hook in &self.write_hooks {
+            if panic::catch_unwind(AssertUnwindSafe(|| hook(&self.path))).is_err() {
+                warn!("Write hook for {} panicked", self.path.display());
+            }
+        }
+    }
+
+    /// Close the file passed to [`from_file()`](#method.from_file), releasing its descriptor once
+    /// any clones of the handler sharing it have also been closed or dropped.  Other handlers hold
+    /// nothing open between operations, so this is a no-op for them.
+    ///
+    /// The handler's path is kept, so later operations open the file at [`path()`](#method.path)
+    /// as for any other handler.  For a handler created by `from_file()`, that path is empty and
+    /// they fail.
+    pub fn close(&mut self) -> Result<(), Error> {
+        self.inherited = None;
+        Ok(())
+    }
+
+    /// Pick up a file which has been replaced at [`path()`](#method.path), e.g. by a deployment
+    /// tool renaming a new copy over it.
+    ///
+    /// On Unix, replacing a file this way gives the path a new inode, while any handle which is
+    /// already open keeps reading and writing the old one.  A handler opens the file afresh for
+    /// each operation, so it always sees the file currently at its path, and this is a no-op.
+    /// The exception is a handler created by [`from_file()`](#method.from_file), which only
+    /// holds a handle and has no path to reopen, so an `InvalidInput` IO error is returned for
+    /// it.  Memory-mapped views from [`open_read_only()`](#method.open_read_only) hold the old
+    /// inode until [`ReadOnlyHandler::remap()`](struct.ReadOnlyHandler.html#method.remap) is
+    /// called.
+    pub fn reopen(&mut self) -> Result<(), Error> {
+        if self.inherited.is_some() && self.path.as_os_str().is_empty() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "A handler created from an open file has no path to reopen",
+            )));
+        }
+        Ok(())
+    }
+
+    /// Open the file for reading, positioned at its start.
+    fn open_read(&self) -> Result<OpenedFile<'_>, Error> {
+        match self.inherited {
+            Some(ref file) => Self::rewind(file),
+            None => Ok(OpenedFile::Opened(File::open(&self.path)?)),
+        }
+    }
+
+    /// Open the file for writing, positioned at its start.  It isn't truncated.
+    fn open_write(&self) -> Result<OpenedFile<'_>, Error> {
+        match self.inherited {
+            Some(ref file) => Self::rewind(file),
+            None => Ok(OpenedFile::Opened(open_for_write(
+                &self.path,
+                &self.options,
+                false,
+            )?)),
+        }
+    }
+
+    /// Open the existing file for reading and writing, positioned at its start.
+    fn open_read_write(&self) -> Result<OpenedFile<'_>, Error> {
+        match self.inherited {
+            Some(ref file) => Self::rewind(file),
+            None => Ok(OpenedFile::Opened(
+                OpenOptions::new().read(true).write(true).open(&self.path)?,
+            )),
+        }
+    }
+
+    fn rewind(file: &Mutex<File>) -> Result<OpenedFile<'_>, Error> {
+        let mut file = unwrap!(file.lock());
+        let _ = file.seek(SeekFrom::Start(0))?;
+        Ok(OpenedFile::Inherited(file))
+    }
+
+    /// Read the raw contents of the file under the shared lock.
+    fn read_raw(&self) -> Result<Vec<u8>, Error> {
+        if let Some(ref memory) = self.memory {
+            return Ok(unwrap!(memory.lock()).clone());
+        }
+        with_retry(&self.options, || {
+            let mut file = self.open_read()?;
+            self.options.check_size(file.metadata()?.len())?;
+            read_locked(&mut file, self.options.lock_mode)
+        })
+    }
+
+    /// Read the raw contents of the file and decode them into a `serde_json::Value`.
+    fn read_raw_value(&self) -> Result<Value, Error> {
+        let contents = self.read_raw()?;
+        self.options
+            .decode(&contents[..])
+            .map_err(|e| parse_error(&self.path, e))
+    }
+
+    /// Pass the raw contents of the file to `update`, and replace them with the new contents it
+    /// returns, if any.  This is done while holding the global mutex and the exclusive file lock,
+    /// or for an in-memory handler the lock on its contents, so no other write can come between
+    /// the read and the write.  Write hooks aren't run.
+    fn update_raw<R, F>(&self, update: F) -> Result<R, Error>
+    where
+        F: FnOnce(Vec<u8>) -> Result<(Option<Vec<u8>>, R), Error>,
+    {
+        if let Some(ref memory) = self.memory {
+            let mut memory = unwrap!(memory.lock());
+            let (contents, result) = update(memory.clone())?;
+            if let Some(contents) = contents {
+                *memory = contents;
+            }
+            return Ok(result);
+        }
+
+        let options = &self.options;
+        let _guard = global_mutex::lock_in(&options.mutex);
+        exclusive_lock(
+            &mut *self.open_read_write()?,
+            options.lock_mode,
+            |file| -> Result<R, Error> {
+                let mut current = Vec::new();
+                let _ = file.read_to_end(&mut current)?;
+                let (contents, result) = update(current)?;
+                if let Some(contents) = contents {
+                    overwrite(file, &contents, options.durable)?;
+                }
+                Ok(result)
+            },
+        )
+    }
+
+    /// Get the full path to the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file can currently be opened for writing, e.g. to decide whether to offer
+    /// editing it.  The file is opened without truncation and closed again immediately, so it is
+    /// never modified.
+    pub fn is_writable(&self) -> bool {
+        check_simulated_read_only(&self.path).is_ok()
+            && OpenOptions::new().write(true).open(&self.path).is_ok()
+    }
+
+    /// Get the path, location, size, modification time and writability of the file together, e.g.
+    /// for a diagnostics report.
+    ///
+    /// The file is opened once, for writing if possible, and its size and modification time are
+    /// then read while holding the shared lock, so they are consistent with each other.  For a
+    /// handler created by [`from_file()`](#method.from_file), the inherited handle's access mode
+    /// is queried instead.
+    pub fn metadata(&self) -> Result<ConfigMetadata, Error> {
+        let (mut file, writable) = match self.inherited {
+            Some(_) => {
+                let file = self.open_read()?;
+                let writable = is_open_for_writing(&file)?;
+                (file, writable)
+            }
+            None => match OpenOptions::new().read(true).write(true).open(&self.path) {
+                Ok(file) => (OpenedFile::Opened(file), Some(true)),
+                Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                    (self.open_read()?, Some(false))
+                }
+                Err(e) => return Err(e.into()),
+            },
+        };
+        let metadata = shared_lock(&mut file, self.options.lock_mode, |file| file.metadata())?;
+        Ok(ConfigMetadata {
+            path: self.path.clone(),
+            location: self.location,
+            len: metadata.len(),
+            modified: metadata.modified()?,
+            writable,
+        })
+    }
+
+    /// Get the kind of location the file was found or created in.
+    pub fn location(&self) -> Location {
+        self.location
+    }
+
+    /// Re-run the search for the file `name` and point the handler at the result.
+    ///
+    /// This lets a long-running process pick up a config which has appeared in a higher-priority
+    /// location since the handler was created, e.g. an override placed in the
+    /// [`current_bin_dir()`](fn.current_bin_dir.html).  The search is the same as for
+    /// [`open()`](#method.open), using this handler's options and writability requirement.  If the
+    /// file can't be found anywhere, an error is returned and the handler is left unchanged.
+    ///
+    /// Any reads or writes running concurrently on other clones of this handler are the caller's
+    /// responsibility; they may still be operating on the previous path.
+    pub fn refresh_path<S: AsRef<OsStr> + ?Sized>(&mut self, name: &S) -> Result<(), Error> {
+        let refreshed = Self::open_with_options(name, self.assert_writable, self.options.clone())?;
+        self.path = refreshed.path;
+        self.location = refreshed.location;
+        Ok(())
+    }
+
+    /// If the file isn't writable, e.g. because it is a packaged default in a read-only system
+    /// location, copy it to the first writable location in which [`new()`](#method.new) would
+    /// create it and point the handler at the copy, so that it can be edited as a user override.
+    ///
+    /// The contents are copied verbatim.  Returns `true` if the file was copied, or `false` if it
+    /// was already writable.  If no location is writable, `Error::NoWritableLocation` is returned.
+    pub fn promote_to_writable(&mut self) -> Result<bool, Error> {
+        if self.memory.is_some() || self.is_writable() {
+            return Ok(false);
+        }
+        let name = self
+            .path
+            .file_name()
+            .ok_or_else(|| not_found_error(&self.path))?
+            .to_os_string();
+        let contents = self.read_raw()?;
+
+        let _guard = global_mutex::lock_in(&self.options.mutex);
+
+        let (location, path) = create_file(&name, &contents, &self.options)?;
+        debug!("Promoted {} to {}", self.path.display(), path.display());
+        self.path = path;
+        self.location = location;
+        self.assert_writable = true;
+        self.inherited = None;
+        Ok(true)
+    }
+
+    /// Move the file into `new_dir`, keeping its file name, and point the handler at it.
+    ///
+    /// The move is done while holding the global mutex, by renaming the file or, if that fails
+    /// (e.g. because `new_dir` is on a different filesystem), by copying it and removing the
+    /// original.  If the file couldn't be moved, the handler still points at the original file;
+    /// once it has been, the handler points at the new path even if flushing the directories then
+    /// fails.  [`location()`](#method.location) is unchanged by this.
+    pub fn relocate_to<P: AsRef<Path>>(&mut self, new_dir: P) -> Result<(), Error> {
+        let file_name = self
+            .path
+            .file_name()
+            .ok_or_else(|| not_found_error(&self.path))?;
+        let new_path = new_dir.as_ref().join(file_name);
+
+        let _guard = global_mutex::lock_in(&self.options.mutex);
+
+        if fs::rename(&self.path, &new_path).is_err() {
+            let _ = fs::copy(&self.path, &new_path)?;
+            if let Err(e) = fs::remove_file(&self.path) {
+                let _ = fs::remove_file(&new_path);
+                return Err(e.into());
+            }
+        }
+        let old_path = mem::replace(&mut self.path, new_path);
+        if self.options.durable {
+            for dir in self.path.parent().iter().chain(old_path.parent().iter()) {
+                sync_dir(dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rename the file to `new_name` in the same directory, e.g. when migrating to a new config
+    /// file name, and point the handler at it.
+    ///
+    /// The rename is done while holding the global mutex.  If a file named `new_name` already
+    /// exists, `Error::AlreadyExists` is returned with its path unless `overwrite` is set, in which
+    /// case it is replaced.  `new_name` is checked as for [`open()`](#method.open).  As for
+    /// [`relocate_to()`](#method.relocate_to), the handler points at the new name once the file
+    /// has been renamed, even if flushing the directory then fails.
+    pub fn rename_config<S: AsRef<OsStr> + ?Sized>(
+        &mut self,
+        new_name: &S,
+        overwrite: bool,
+    ) -> Result<(), Error> {
+        let new_name = new_name.as_ref();
+        validate_name(new_name)?;
+        let new_path = self.path.with_file_name(new_name);
+
+        let _guard = global_mutex::lock_in(&self.options.mutex);
+
+        if !overwrite && new_path.exists() {
+            return Err(Error::AlreadyExists { path: new_path });
+        }
+        fs::rename(&self.path, &new_path)?;
+        self.path = new_path;
+        if self.options.durable {
+            if let Some(dir) = self.path.parent() {
+                sync_dir(dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Swap the contents of this handler's file and `other`'s, e.g. to promote a candidate config
+    /// while keeping the current one as a backup.
+    ///
+    /// While holding both handlers' mutexes and the exclusive locks of both files, this handler's
+    /// file is hard-linked to a new temporary name beside it, `other`'s file is renamed into its
+    /// place, and the temporary file is renamed to `other`'s path.  The temporary name is unique to
+    /// the call and the link fails rather than replace an existing file, so no unrelated file is
+    /// touched.  If a rename fails, the ones already done are undone where possible; if undoing
+    /// them fails too, this handler's original contents are left in the temporary file and a
+    /// warning naming it is logged.  Both files must be on the same filesystem, and it must
+    /// support hard links.
+    pub fn swap(&self, other: &FileHandler<T>) -> Result<(), Error> {
+        if self.path == other.path {
+            return Ok(());
+        }
+
+        let _guards = global_mutex::lock_both(&self.options.mutex, &other.options.mutex);
+
+        // The files are locked in order of path, so that two swaps of the same pair can't deadlock.
+        let mut files = [File::open(&self.path)?, File::open(&other.path)?];
+        if other.path < self.path {
+            files.swap(0, 1);
+        }
+        let [ref mut first, ref mut second] = files;
+        let lock_mode = self.options.lock_mode;
+        exclusive_lock(first, lock_mode, |_| {
+            exclusive_lock(second, lock_mode, |_| {
+                exchange_files(&self.path, &other.path)
+            })
+        })?;
+
+        if self.options.durable {
+            for dir in self.path.parent().iter().chain(other.path.parent().iter()) {
+                sync_dir(dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether this handler was returned by [`new()`](#method.new) after initialising the file to
+    /// a default (or by [`touch()`](#method.touch) after creating it), as opposed to opening an
+    /// already-existing file.
+    ///
+    /// The file is created exclusively, so if several threads or processes call `new` for the same
+    /// missing file at the same time, only the one which actually created it sees `true`; the
+    /// others open its copy and see `false`.
+    pub fn was_created(&self) -> bool {
+        self.was_created
+    }
+
+    /// Copy the raw contents of the file to `dest`, e.g. to export a config for support.
+    ///
+    /// The file is read while holding the shared lock, so the copy is never torn by a concurrent
+    /// writer.  `dest` is owned by the caller and is not locked.  Since the bytes are copied
+    /// verbatim, this works regardless of the file's format.
+    pub fn copy_to<P: AsRef<Path>>(&self, dest: P) -> Result<(), Error> {
+        fs::write(dest, self.read_raw()?)?;
+        Ok(())
+    }
+
+    /// Read the contents of the file into `buf` and decode them, allowing the result to borrow
+    /// from `buf`, e.g. for a config type holding `&'de str` fields.
+    ///
+    /// `buf` is cleared first.  The shared lock is only held while reading into it.  As for
+    /// [`read_file()`](#method.read_file), a binary file is rejected with `Error::NotText` for the
+    /// text formats.  This isn't supported for `Format::Cbor`.
+    pub fn read_file_into<'de>(&self, buf: &'de mut Vec<u8>) -> Result<T, Error>
+    where
+        T: Deserialize<'de>,
+    {
+        *buf = self.read_raw()?;
+        if self.options.expects_text_slice() {
+            check_text_bytes(buf, &self.path)?;
+        }
+        self.options.unseal(buf)?;
+        self.options
+            .format
+            .deserialize_slice(buf)
+            .map_err(|e| parse_error(&self.path, e))
+    }
+
+    /// A hash of the raw contents of the file, read under the shared lock.
+    ///
+    /// This is the 64-bit FNV-1a hash of the bytes on disk, so it doesn't depend on the file's
+    /// format and is stable across process runs and platforms, making it suitable for persisting
+    /// for change detection.  It is not a cryptographic hash; see `content_digest()` (with the
+    /// `blake2` feature) for that.
+    pub fn content_hash(&self) -> Result<u64, Error> {
+        Ok(fnv1a(&self.read_raw()?))
+    }
+
+    /// The current length of the file in bytes, e.g. as a capacity hint for a buffer to read it
+    /// into, or `None` if it can't be determined.  The file may change size before it is read.
+    pub fn size_hint(&self) -> Option<usize> {
+        if let Some(ref memory) = self.memory {
+            return Some(unwrap!(memory.lock()).len());
+        }
+        let len = self.open_read().ok()?.metadata().ok()?.len();
+        usize::try_from(len).ok()
+    }
+
+    /// Acquire the exclusive file lock and hold it until the returned
+    /// [`ConfigLockGuard`](struct.ConfigLockGuard.html) is dropped, e.g. to read, compute and then
+    /// write the file without another process changing it in between.
+    ///
+    /// Other `FileHandler`s, and this one, block on the lock while it is held, so only use the
+    /// guard's own `read()` and `write()` to access the file from this thread until it is dropped.
+    pub fn lock_exclusive_guard(&self) -> Result<ConfigLockGuard<'_, T>, Error> {
+        let file = self.open_read_write()?;
+        if self.options.lock_mode != LockMode::None {
+            trace!("Acquiring exclusive lock on {:?}", *file);
+            file.lock_exclusive().map_err(lock_error)?;
+        }
+        Ok(ConfigLockGuard {
+            file_handler: self,
+            file,
+            written: false,
+        })
+    }
+
+    /// Acquire the exclusive file lock like
+    /// [`lock_exclusive_guard()`](#method.lock_exclusive_guard), but give up with
+    /// `Error::Timeout` if it can't be acquired within `timeout`, e.g. because another process is
+    /// holding it.
+    ///
+    /// The lock is polled without blocking, sleeping for the interval set with
+    /// [`set_lock_poll_interval()`](#method.set_lock_poll_interval) between attempts.
+    pub fn lock_exclusive_guard_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<ConfigLockGuard<'_, T>, Error> {
+        let file = self.open_read_write()?;
+        trace!("Acquiring exclusive lock on {:?}", *file);
+        let deadline = Instant::now() + timeout;
+        if self.options.lock_mode != LockMode::None
+            && !lock_until(&file, true, deadline, self.options.lock_poll_interval)?
+        {
+            return Err(Error::Timeout {
+                name: self.path.clone(),
+                timeout,
+            });
+        }
+        Ok(ConfigLockGuard {
+            file_handler: self,
+            file,
+            written: false,
+        })
+    }
+
+    /// Set how long to sleep between attempts to take a contended lock in the variants with a
+    /// timeout, such as [`lock_exclusive_guard_timeout()`](#method.lock_exclusive_guard_timeout).
+    /// A shorter interval acquires the lock sooner after it is released, at the cost of more CPU
+    /// time.  Defaults to 10ms.
+    ///
+    /// A zero interval would spin, so it is rejected with an `InvalidInput` IO error.
+    pub fn set_lock_poll_interval(&mut self, interval: Duration) -> Result<(), Error> {
+        if interval == Duration::from_secs(0) {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The lock poll interval must be greater than zero",
+            )));
+        }
+        self.options.lock_poll_interval = interval;
+        Ok(())
+    }
+
+    /// The BLAKE2s-256 digest of the raw contents of the file, read under the shared lock.
+    #[cfg(feature = "blake2")]
+    pub fn content_digest(&self) -> Result<[u8; 32], Error> {
+        use blake2::{Blake2s256, Digest};
+
+        Ok(Blake2s256::digest(self.read_raw()?).into())
+    }
+
+    /// Whether the BLAKE2s-256 digest of the raw contents of the file matches `expected`, e.g. as
+    /// returned by [`write_file_with_checksum()`](#method.write_file_with_checksum).
+    ///
+    /// Since the digest is over the bytes on disk, any change to the file is detected, including
+    /// one which only reformats it.
+    #[cfg(feature = "blake2")]
+    pub fn verify_checksum(&self, expected: &[u8; 32]) -> Result<bool, Error> {
+        Ok(self.content_digest()? == *expected)
+    }
+}
+
+// Implemented by hand since deriving would needlessly require `T: Clone` and `T: PartialEq`.
+
+impl<T> Clone for FileHandler<T> {
+    fn clone(&self) -> Self {
+        FileHandler {
+            path: self.path.clone(),
+            location: self.location,
+            assert_writable: self.assert_writable,
+            options: self.options.clone(),
+            was_created: self.was_created,
+            inherited: self.inherited.clone(),
+            memory: self.memory.clone(),
+            write_hooks: self.write_hooks.clone(),
+            _ph: PhantomData,
+        }
+    }
+}
+
+/// Two handlers are equal if they point at the same resolved path, or share the same file passed
+/// to `from_file()` or the same in-memory contents.
+impl<T> PartialEq for FileHandler<T> {
+    fn eq(&self, other: &Self) -> bool {
+        fn same<U>(ours: &Option<Arc<U>>, theirs: &Option<Arc<U>>) -> bool {
+            match (ours, theirs) {
+                (Some(ours), Some(theirs)) => Arc::ptr_eq(ours, theirs),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+        self.path == other.path
+            && same(&self.inherited, &other.inherited)
+            && same(&self.memory, &other.memory)
+    }
+}
+
+impl<T> Eq for FileHandler<T> {}
+
+impl<T> AsRef<Path> for FileHandler<T> {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<'a, T> From<&'a FileHandler<T>> for PathBuf {
+    fn from(file_handler: &'a FileHandler<T>) -> PathBuf {
+        file_handler.path.clone()
+    }
+}
+
+impl<T> fmt::Debug for FileHandler<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("FileHandler")
+            .field("path", &self.path)
+            .field("location", &self.location)
+            .finish()
+    }
+}
+
+impl<T: Config> FileHandler<T> {
+    /// Open the existing file [`T::FILE_NAME`](trait.Config.html#associatedconstant.FILE_NAME)
+    /// like [`open()`](#method.open).
+    pub fn open_default(assert_writable: bool) -> Result<FileHandler<T>, Error> {
+        Self::open(T::FILE_NAME, assert_writable)
+    }
+
+    /// Open the file [`T::FILE_NAME`](trait.Config.html#associatedconstant.FILE_NAME), creating it
+    /// initialised to `T::default()` if it doesn't exist, like [`new()`](#method.new).
+    pub fn new_default(is_existing_file_writable: bool) -> Result<FileHandler<T>, Error> {
+        Self::new(T::FILE_NAME, is_existing_file_writable)
+    }
+}
+
+impl<T> FileHandler<T>
+where
+    T: Default + Serialize,
+{
+    /// Create a handler which never touches the filesystem, initialised to `T::default()`, e.g.
+    /// for tests or for running without a writable filesystem.
+    ///
+    /// [`read_file()`](#method.read_file) returns the most recently written value, and
+    /// [`write_file()`](#method.write_file) replaces it.  The other reads and writes, including
+    /// transactions, [`compare_and_swap()`](#method.compare_and_swap) and
+    /// [`append_to_array()`](#method.append_to_array), work on the same contents, holding their
+    /// lock in place of the file lock.  As for [`from_file()`](#method.from_file), the handler has
+    /// an empty path and a location of `Location::Explicit`, so methods which need an actual file,
+    /// such as [`metadata()`](#method.metadata),
+    /// [`lock_exclusive_guard()`](#method.lock_exclusive_guard) or
+    /// [`relocate_to()`](#method.relocate_to), fail.  Clones of the handler share the same
+    /// contents.
+    pub fn new_in_memory_default() -> Result<FileHandler<T>, Error> {
+        let options = Options::default();
+        let contents = options.encode(&T::default())?;
+        let mut file_handler =
+            FileHandler::from_path(PathBuf::new(), Location::Explicit, false, options);
+        file_handler.memory = Some(Arc::new(Mutex::new(contents)));
+        Ok(file_handler)
+    }
+
+    /// Constructor taking the required file name (not the full path)
+    /// The config file will be initialised to a default if it does not exist.
+    ///
+    /// This function tests whether it has read access to the file in the following locations in
+    /// this order (see also [an example config file flowchart]
+    /// (https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf)):
+    ///
+    ///   1. [`current_bin_dir()`](fn.current_bin_dir.html)
+    ///   2. [`user_app_dir()`](fn.user_app_dir.html)
+    ///   3. [`system_cache_dir()`](fn.system_cache_dir.html)
+    ///
+    /// Parameter `is_existing_file_writable` will assert that if the file pre-exists should it be
+    /// also writable or not. (E.g. it is enough for `crust-config` file to merely exist as
+    /// readable, but `bootstrap-cache` must be writable too if it exists, else no updation can
+    /// happen).
+    ///
+    /// If the file doesn't exist and can't be created in any of the locations either,
+    /// `Error::NoWritableLocation` is returned, listing each path tried and why it was rejected.
+    ///
+    /// The default is only written to a file created by this call, so if another thread or
+    /// process creates the file first, e.g. when two instances start at once, its copy is used
+    /// rather than overwritten.
+    ///
+    /// See [Thread- and Process-Safety](#thread--and-process-safety) for notes on thread- and
+    /// process-safety.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        is_existing_file_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        Self::new_with_options(name, is_existing_file_writable, Options::default())
+    }
+
+    pub(crate) fn new_with_options<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        is_existing_file_writable: bool,
+        options: Options,
+    ) -> Result<FileHandler<T>, Error> {
+        validate_name(name.as_ref())?;
+        if let Ok(fh) = Self::open_with_options(name, is_existing_file_writable, options.clone()) {
+            return Ok(fh);
+        }
+
+        let contents = options.encode(&T::default())?;
+
+        let mutex = options.mutex.clone();
+        let _guard = global_mutex::lock_in(&mutex);
+
+        let (location, path, created) = create_or_keep_file(
+            name.as_ref(),
+            &contents,
+            &options,
+            is_existing_file_writable,
+        )?;
+        let mut file_handler =
+            FileHandler::from_path(path, location, is_existing_file_writable, options);
+        file_handler.was_created = created;
+        Ok(file_handler)
+    }
+
+    /// Open the file `name` in exactly the directory `base` like
+    /// [`open_with_base()`](#method.open_with_base), or create it there initialised to
+    /// `T::default()` if it doesn't exist, creating `base` too if necessary.
+    ///
+    /// No other location is tried, so if the file can't be created in `base`,
+    /// `Error::NoWritableLocation` is returned.
+    pub fn new_with_base<P: AsRef<Path>, S: AsRef<OsStr> + ?Sized>(
+        base: P,
+        name: &S,
+        is_existing_file_writable: bool,
+    ) -> Result<FileHandler<T>, Error> {
+        validate_name(name.as_ref())?;
+        let base = base.as_ref();
+        if let Ok(fh) = Self::open_with_base(base, name, is_existing_file_writable) {
+            return Ok(fh);
+        }
+
+        let options = Options::default();
+        let contents = options.encode(&T::default())?;
+
+        let _guard = global_mutex::lock();
+
+        let dirs = vec![(Location::Explicit, Ok(base.to_path_buf()), true)];
+        let mode = CreateMode::KeepExisting {
+            writable: is_existing_file_writable,
+        };
+        let (location, path, created) =
+            create_file_in(dirs, name.as_ref(), &contents, &options, mode)?;
+        let mut file_handler =
+            FileHandler::from_path(path, location, is_existing_file_writable, options);
+        file_handler.was_created = created;
+        Ok(file_handler)
+    }
+
+    /// Overwrite the file with `T::default()`, e.g. to implement a "reset settings" action.  This
+    /// is equivalent to passing the default to [`write_file()`](#method.write_file).
+    pub fn reset_to_default(&self) -> Result<(), Error> {
+        self.write_file(&T::default())
+    }
+}
+
+impl<T> FileHandler<T>
+where
+    T: Decode,
+{
+    /// Read the contents of the file and decode it using the handler's [`Format`](enum.Format.html)
+    /// (JSON unless set otherwise via the [`Builder`](struct.Builder.html)).
+    pub fn read_file(&self) -> Result<T, Error> {
+        let (options, path) = (&self.options, &self.path);
+        if let Some(ref memory) = self.memory {
+            let memory = unwrap!(memory.lock());
+            if options.expects_text::<T>() {
+                check_text_bytes(&memory, path)?;
+            }
+            return options.decode(&memory[..]);
+        }
+        with_retry(options, || {
+            shared_lock(&mut *self.open_read()?, options.lock_mode, |file| {
+                options.check_size(file.metadata()?.len())?;
+                if options.expects_text::<T>() {
+                    check_text(file, path)?;
+                }
+                options.decode(file).map_err(|e| parse_error(path, e))
+            })
+        })
+    }
+
+    /// Read the contents of the file like [`read_file()`](#method.read_file), but without taking
+    /// the shared file lock.
+    ///
+    /// This saves a couple of syscalls per read and is only intended for read-only tools which are
+    /// known to be the sole user of the file.  If another thread or process writes the file
+    /// concurrently, this function may observe a partially-written file and fail to decode it, or
+    /// worse, decode a mix of old and new contents.
+    pub fn read_file_nolock(&self) -> Result<T, Error> {
+        if self.memory.is_some() {
+            return self.read_file();
+        }
+        let mut file = self.open_read()?;
+        self.options.check_size(file.metadata()?.len())?;
+        if self.options.expects_text::<T>() {
+            check_text(&mut file, &self.path)?;
+        }
+        self.options
+            .decode(&mut *file)
+            .map_err(|e| parse_error(&self.path, e))
+    }
+
+    /// Check that the file can be read and decoded as `T`, e.g. to refuse to start with a broken
+    /// config, returning the error if not.  The decoded value is discarded.
+    pub fn verify(&self) -> Result<(), Error> {
+        self.read_file().map(|_: T| ())
+    }
+
+    /// Read the contents of the file, overriding individual fields from environment variables
+    /// starting with `prefix` followed by `_`, e.g. `APP_NETWORK_PORT=9000` for the prefix `APP`.
+    ///
+    /// The rest of each variable's name is lowercased and split on `_` into nested keys, where a
+    /// key is the longest run of segments (rejoined with `_`) which already exists in the file, so
+    /// `APP_MAX_PEERS` overrides `max_peers` if the file has such a key and `max.peers`
+    /// otherwise.  The value is parsed as JSON, falling back to a string if it isn't valid JSON.
+    /// Variables are applied in order of name, so a more specific one overrides a field of a less
+    /// specific one.  It requires a self-describing format such as JSON.
+    pub fn read_file_with_env_overrides(&self, prefix: &str) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let mut contents = self.read_raw_value()?;
+
+        let prefix = format!("{}_", prefix);
+        let mut overrides = env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .collect::<Vec<_>>();
+        overrides.sort();
+        for (key, value) in overrides {
+            let segments = key[prefix.len()..]
+                .split('_')
+                .filter(|segment| !segment.is_empty())
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>();
+            if segments.is_empty() {
+                continue;
+            }
+            let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+            merge::set_nested(&mut contents, &segments, value);
+        }
+        Ok(serde_json::from_value(contents)?)
+    }
+
+    /// Read the file in its own format and return its contents as pretty-printed JSON, e.g. for
+    /// diagnostics which should look the same whatever the file's format.
+    ///
+    /// The file is decoded as `T` and then re-encoded, so fields unknown to `T` are dropped.  The
+    /// handler's indentation is used.  The file is only read.
+    pub fn export_json(&self) -> Result<String, Error>
+    where
+        T: Serialize,
+    {
+        let json = Format::Json.serialize(&self.read_file()?, &self.options.indent)?;
+        Ok(unwrap!(String::from_utf8(json)))
+    }
+
+    /// List the differences between `current`, e.g. an edited copy of the config, and the value
+    /// in the file, as the JSON pointers (e.g. `/network/peers/3`) of each changed value.
+    ///
+    /// Both values are converted to `serde_json::Value` for comparison, so this works whatever the
+    /// file's format.  The file is only read.  An empty list means there are no unsaved changes.
+    pub fn diff(&self, current: &T) -> Result<Vec<String>, Error>
+    where
+        T: Serialize,
+    {
+        let on_disk = serde_json::to_value(self.read_file()?)?;
+        let current = serde_json::to_value(current)?;
+        let mut changes = Vec::new();
+        merge::diff(&on_disk, &current, "", &mut changes);
+        Ok(changes)
+    }
+
+    /// Start a transaction: read the file into a working copy which can be modified through the
+    /// returned [`ConfigTxn`](struct.ConfigTxn.html) and then written back with
+    /// [`commit()`](struct.ConfigTxn.html#method.commit).  Dropping the transaction instead
+    /// discards the changes.
+    pub fn begin(&self) -> Result<ConfigTxn<T>, Error>
+    where
+        T: Encode,
+    {
+        let contents = self.read_raw()?;
+        let value = self
+            .options
+            .decode(&contents[..])
+            .map_err(|e| parse_error(&self.path, e))?;
+        Ok(ConfigTxn {
+            file_handler: self.clone(),
+            value,
+            original_hash: fnv1a(&contents),
+        })
+    }
+
+    /// Turn the handler into a [`SharedConfig`](struct.SharedConfig.html), which holds the decoded
+    /// value and keeps it up to date as the file changes.
+    ///
+    /// The file is read once now, returning any error, and is then polled by a background thread
+    /// every half second.  If re-reading fails, e.g. because the file was saved with a syntax
+    /// error, the previous value is kept and the error is available from
+    /// [`SharedConfig::last_error()`](struct.SharedConfig.html#method.last_error).
+    pub fn into_shared(self) -> Result<SharedConfig<T>, Error>
+    where
+        T: Send + Sync + 'static,
+    {
+        SharedConfig::spawn(self, SHARED_POLL_INTERVAL)
+    }
+
+    /// Watch the file for changes, sending the new value over the returned channel each time it
+    /// changes.
+    ///
+    /// The file is polled by a background thread, and a change is only read once the file has
+    /// settled, i.e. its contents have stayed the same for `window`.  While the file is empty or
+    /// missing, e.g. part way through an editor saving it via a rename, it is ignored.  Errors
+    /// reading or decoding the settled file are sent too, once per failure.  The background thread
+    /// exits when it next has something to send after the receiver has been dropped.
+    pub fn watch_debounced(&self, window: Duration) -> Result<Receiver<Result<T, Error>>, Error>
+    where
+        T: Send + 'static,
+    {
+        let file_handler = self.clone();
+        let poll_interval = cmp::max(window / 4, Duration::from_millis(1));
+        let mut last_hash = self.content_hash()?;
+        // The hash of changed contents, and when they were first seen.  `failed` is set while
+        // reading the file fails, so that the error is only sent once.
+        let mut pending: Option<(u64, Instant)> = None;
+        let mut failed = false;
+        let (sender, receiver) = mpsc::channel();
+
+        let _ = thread::spawn(move || loop {
+            thread::sleep(poll_interval);
+            let contents = match file_handler.read_raw() {
+                Ok(ref contents) if contents.is_empty() => {
+                    pending = None;
+                    continue;
+                }
+                Ok(contents) => contents,
+                Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => {
+                    pending = None;
+                    continue;
+                }
+                Err(e) => {
+                    if !failed && sender.send(Err(e)).is_err() {
+                        return;
+                    }
+                    failed = true;
+                    continue;
+                }
+            };
+            failed = false;
+            let hash = fnv1a(&contents);
+            match pending {
+                _ if hash == last_hash => {
+                    pending = None;
+                    continue;
+                }
+                Some((pending_hash, since))
+                    if pending_hash == hash && since.elapsed() >= window => {}
+                Some((pending_hash, _)) if pending_hash == hash => continue,
+                _ => {
+                    pending = Some((hash, Instant::now()));
+                    continue;
+                }
+            }
+            pending = None;
+            last_hash = hash;
+            let result = file_handler
+                .options
+                .decode(&contents[..])
+                .map_err(|e| parse_error(&file_handler.path, e));
+            if sender.send(result).is_err() {
+                return;
+            }
+        });
+
+        Ok(receiver)
+    }
+
+    /// Replace the file with the one at `staged`, e.g. a new config staged as `config.json.new`
+    /// during an upgrade.
+    ///
+    /// While holding the global mutex and the exclusive file lock, `staged` is first checked to
+    /// decode as `T` and then renamed over the handler's file.  If decoding fails, the existing
+    /// file is left untouched and `staged` is preserved for inspection.  `staged` should be on the
+    /// same filesystem as the handler's file for the rename to be atomic.
+    pub fn install_from<P: AsRef<Path>>(&self, staged: P) -> Result<(), Error> {
+        let staged = staged.as_ref();
+        let options = &self.options;
+
+        let guard = global_mutex::lock_in(&self.options.mutex);
+
+        let mut file = OpenOptions::new().write(true).open(&self.path)?;
+        let result = exclusive_lock(&mut file, options.lock_mode, |_| -> Result<(), Error> {
+            let mut staged_file = File::open(staged)?;
+            let _: T = shared_lock(&mut staged_file, options.lock_mode, |file| {
+                options.decode(file).map_err(|e| parse_error(staged, e))
+            })?;
+            fs::rename(staged, &self.path)?;
+            if options.durable {
+                if let Some(dir) = self.path.parent() {
+                    sync_dir(dir)?;
+                }
+            }
+            Ok(())
+        });
+        drop(guard);
+        self.notify_write(result, |_| true)
+    }
+
+    /// Open an existing file like [`open()`](#method.open), or create it from `embedded` if there
+    /// is no copy of it in any location.
+    ///
+    /// This allows a richer initial config than `T::default()` to be shipped inside the binary,
+    /// e.g. via `include_bytes!`.  `embedded` is checked to decode as `T`, then written verbatim
+    /// (so any comments are kept) to the first writable location, in the same order as for
+    /// [`new()`](#method.new).
+    pub fn open_or_embedded<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        is_existing_file_writable: bool,
+        embedded: &'static [u8],
+    ) -> Result<FileHandler<T>, Error> {
+        let name = name.as_ref();
+        let options = Options::default();
+        validate_name(name)?;
+        if let Ok(fh) = Self::open_with_options(name, is_existing_file_writable, options.clone()) {
+            return Ok(fh);
+        }
+
+        let _: T = options
+            .decode(embedded)
+            .map_err(|e| parse_error(Path::new(name), e))?;
+
+        let _guard = global_mutex::lock();
+
+        let (location, path, created) =
+            create_or_keep_file(name, embedded, &options, is_existing_file_writable)?;
+        let mut file_handler =
+            FileHandler::from_path(path, location, is_existing_file_writable, options);
+        file_handler.was_created = created;
+        Ok(file_handler)
+    }
+}
+
+impl<T> FileHandler<T>
+where
+    T: Default + DeserializeOwned,
+{
+    /// Read the contents of the file, returning `T::default()` if the file is missing or empty
+    /// (e.g. it was created by [`touch()`](#method.touch)).
+    pub fn read_file_or_default(&self) -> Result<T, Error> {
+        if self.memory.is_some() {
+            return self.read_file();
+        }
+        match fs::metadata(&self.path) {
+            Ok(ref metadata) if metadata.len() == 0 => Ok(T::default()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+            _ => self.read_file(),
+        }
+    }
+}
+
+impl<T> FileHandler<T>
+where
+    T: Default + DeserializeOwned + Serialize,
+{
+    /// Read the contents of the file, substituting the default for any top-level field which
+    /// can't be decoded rather than rejecting the whole file, e.g. so that an application can
+    /// start with a partly-broken config and warn about the rest.
+    ///
+    /// The JSON pointers of the rejected fields, e.g. `/network`, are returned along with the
+    /// value, and are empty if the whole file decoded.  Each field of the file is tried in turn
+    /// on top of `T::default()` and kept only if the result still decodes, so a field which `T`
+    /// doesn't have is also rejected if `T` denies unknown fields.  It requires a self-describing
+    /// format such as JSON, and the file must decode to an object.
+    pub fn read_file_tolerant(&self) -> Result<(T, Vec<String>), Error> {
+        let contents = self.read_raw_value()?;
+        let error = match serde_json::from_value(contents.clone()) {
+            Ok(value) => return Ok((value, Vec::new())),
+            Err(e) => parse_error(&self.path, e.into()),
+        };
+        let (fields, mut merged) = match (contents, serde_json::to_value(T::default())?) {
+            (Value::Object(fields), default @ Value::Object(_)) => (fields, default),
+            _ => return Err(error),
+        };
+
+        let mut rejected = Vec::new();
+        for (key, field) in fields {
+            let mut candidate = merged.clone();
+            let _ = candidate
+                .as_object_mut()
+                .and_then(|object| object.insert(key.clone(), field));
+            if serde_json::from_value::<T>(candidate.clone()).is_ok() {
+                merged = candidate;
+            } else {
+                warn!(
+                    "Ignoring invalid field {:?} in {}",
+                    key,
+                    self.path.display()
+                );
+                rejected.push(format!("/{}", key.replace('~', "~0").replace('/', "~1")));
+            }
+        }
+        Ok((serde_json::from_value(merged)?, rejected))
+    }
+
+    /// Open an existing file, or create one initialised to a default, like [`new()`](#method.new),
+    /// but also report which of the two happened.
+    ///
+    /// This allows first-time setup to be run only when the result is `CreationOutcome::Created`.
+    /// As for [`was_created()`](#method.was_created), only one of several callers racing to create
+    /// the file gets that result.
+    pub fn open_or_create<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        is_existing_file_writable: bool,
+    ) -> Result<(FileHandler<T>, CreationOutcome), Error> {
+        let file_handler = Self::new(name, is_existing_file_writable)?;
+        let outcome = if file_handler.was_created {
+            CreationOutcome::Created
+        } else {
+            CreationOutcome::Opened
+        };
+        Ok((file_handler, outcome))
+    }
+
+    /// Open an existing file, or create one initialised to a default, like
+    /// [`open_or_create()`](#method.open_or_create), but also recover from a file which can't be
+    /// decoded, e.g. one truncated by a crash, so that startup can always proceed.
+    ///
+    /// If the existing file fails to decode, including because it isn't text, e.g. it is filled
+    /// with NUL bytes after a crash, it is renamed to `<name>.corrupt.<timestamp>` beside it,
+    /// where the timestamp is in seconds since the Unix epoch, and the file is recreated from
+    /// `T::default()`.  The path of the renamed copy is returned in
+    /// `Recovery::RecoveredFromCorrupt`.  Other errors, e.g. from IO, are returned as they are.
+    pub fn open_resilient<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        is_existing_file_writable: bool,
+    ) -> Result<(FileHandler<T>, Recovery), Error> {
+        let (file_handler, outcome) = Self::open_or_create(name, is_existing_file_writable)?;
+        if outcome == CreationOutcome::Created {
+            return Ok((file_handler, Recovery::Created));
+        }
+        match file_handler.read_file() {
+            Ok(_) => return Ok((file_handler, Recovery::Opened)),
+            Err(Error::Parse { .. }) | Err(Error::NotText { .. }) => (),
+            Err(e) => return Err(e),
+        }
+
+        let timestamp = clock::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let mut backup = file_handler.path.clone().into_os_string();
+        backup.push(format!(".corrupt.{}", timestamp));
+        let backup = PathBuf::from(backup);
+        warn!(
+            "Moving corrupt {} to {}",
+            file_handler.path.display(),
+            backup.display()
+        );
+        {
+            let _guard = global_mutex::lock();
+            fs::rename(&file_handler.path, &backup)?;
+        }
+        file_handler.write_file(&T::default())?;
+        Ok((file_handler, Recovery::RecoveredFromCorrupt { backup }))
+    }
+
+    /// Read the contents of the file, using `T::default()` for any fields missing from it.
+    ///
+    /// The file is decoded into a `serde_json::Value` and merged over the default value, with the
+    /// file's keys winning (nested objects are merged recursively), before being converted to `T`.
+    /// This allows older files to be read after new fields have been added to `T` without marking
+    /// every field `#[serde(default)]`.  It requires a self-describing format such as JSON.
+    pub fn read_file_merged(&self) -> Result<T, Error> {
+        let contents = self.read_raw_value()?;
+
+        let mut merged = serde_json::to_value(T::default())?;
+        merge::merge(&mut merged, contents);
+        Ok(serde_json::from_value(merged)?)
+    }
+
+    /// Read the effective config from two layers: read-only packaged defaults, overridden by the
+    /// user's own file.
+    ///
+    /// The packaged defaults are the first readable copy of the file in
+    /// [`current_bin_dir()`](fn.current_bin_dir.html) or
+    /// [`bundle_resource_dir()`](fn.bundle_resource_dir.html), and the user override is the first
+    /// readable copy in [`user_app_dir()`](fn.user_app_dir.html) or
+    /// [`system_cache_dir()`](fn.system_cache_dir.html).  Each layer is merged over `T::default()`
+    /// in turn, as in [`read_file_merged()`](#method.read_file_merged), and missing layers are
+    /// skipped.
+    pub fn read_file_layered<S: AsRef<OsStr> + ?Sized>(name: &S) -> Result<T, Error> {
+        Self::read_file_layered_sourced(name).map(|(value, _)| value)
+    }
+
+    /// Read the effective config like [`read_file_layered()`](#method.read_file_layered), and
+    /// report which location supplied each top-level key, e.g. to show the user where a setting
+    /// came from.
+    ///
+    /// A key is attributed to the highest-precedence layer whose file contains it, even if that
+    /// file only overrides part of a nested object.  Keys which only come from `T::default()` are
+    /// not included.
+    pub fn read_file_layered_sourced<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+    ) -> Result<(T, HashMap<String, Location>), Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let options = Options::default();
+
+        let mut merged = serde_json::to_value(T::default())?;
+        let mut sources = HashMap::new();
+        for layer in &[DEFAULTS_LAYER, USER_LAYER] {
+            if let Some((location, path)) = first_readable(name, layer, &options) {
+                let contents = read_value(&path, &options)?;
+                if let Value::Object(ref map) = contents {
+                    sources.extend(map.keys().map(|key| (key.clone(), location)));
+                }
+                merge::merge(&mut merged, contents);
+            }
+        }
+        Ok((serde_json::from_value(merged)?, sources))
+    }
+
+    /// Open a handler for each of the two layers read by
+    /// [`read_file_layered()`](#method.read_file_layered), e.g. to save the user's edits to their
+    /// own file while leaving the packaged defaults alone.
+    ///
+    /// Each layer is the first readable copy of the file in its locations, or `None` if there is
+    /// none.  If `assert_writable` is set and the user layer exists but isn't writable,
+    /// `Error::PermissionDenied` is returned; the defaults are always opened read-only.
+    pub fn open_layers<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        assert_writable: bool,
+    ) -> Result<ConfigLayers<T>, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let options = Options::default();
+
+        let open_layer = |layer: &[Location], writable: bool| {
+            first_readable(name, layer, &options).map(|(location, path)| {
+                FileHandler::from_path(path, location, writable, options.clone())
+            })
+        };
+        let defaults = open_layer(DEFAULTS_LAYER, false);
+        let user = open_layer(USER_LAYER, assert_writable);
+        if let Some(ref user) = user {
+            if assert_writable && !user.is_writable() {
+                return Err(Error::PermissionDenied {
+                    path: user.path.clone(),
+                });
+            }
+        }
+        Ok(ConfigLayers {
+            name: name.to_os_string(),
+            defaults,
+            user,
+        })
+    }
+
+    /// Read the file `name` merged with its drop-in fragments, i.e. each `*.json` file in the
+    /// directory `<name>.d` beside it, e.g. `config.json.d/10-network.json`.
+    ///
+    /// The first location containing either the file or its drop-in directory is used, and the
+    /// file and then each fragment, in order of file name, are merged over `T::default()` as in
+    /// [`read_file_merged()`](#method.read_file_merged).  Either of the file and the directory may
+    /// be missing.  If neither exists in any location, `Error::NotFoundAnywhere` is returned.
+    pub fn read_file_with_dropins<S: AsRef<OsStr> + ?Sized>(name: &S) -> Result<T, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let options = Options::default();
+        let mut dropins_name = name.to_os_string();
+        dropins_name.push(".d");
+        let mut attempts = Vec::new();
+
+        for (location, dir) in search_dirs(&options) {
+            let dir = match dir {
+                Ok(dir) => dir,
+                Err(_) => continue,
+            };
+            let (path, dropins_dir) = (dir.join(name), dir.join(&dropins_name));
+            let has_dropins = dropins_dir.is_dir();
+            if !path.is_file() && !has_dropins {
+                attempts.push((path, io::ErrorKind::NotFound));
+                continue;
+            }
+            debug!("Reading {} with drop-ins ({:?})", path.display(), location);
+
+            let mut merged = serde_json::to_value(T::default())?;
+            if path.is_file() {
+                merge::merge(&mut merged, read_value(&path, &options)?);
+            }
+            if has_dropins {
+                let mut fragments = fs::read_dir(&dropins_dir)?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<io::Result<Vec<_>>>()?;
+                fragments
+                    .retain(|path| path.extension() == Some(OsStr::new("json")) && path.is_file());
+                fragments.sort();
+                for fragment in fragments {
+                    merge::merge(&mut merged, read_value(&fragment, &options)?);
+                }
+            }
+            return Ok(serde_json::from_value(merged)?);
+        }
+
+        Err(Error::NotFoundAnywhere { attempts })
+    }
+}
+
+impl<T> FileHandler<T>
+where
+    T: Encode,
+{
+    /// Create the file `name` initialised to `contents`, failing if it already exists.
+    ///
+    /// The file is created with `O_EXCL` semantics in the first writable of the locations tried by
+    /// [`new()`](#method.new), so if it already exists there, `Error::AlreadyExists` is returned
+    /// with its path and the existing file is left untouched.  This allows it to be used as an
+    /// atomic create-exclusive primitive, e.g. for a PID file.
+    pub fn create_new<S: AsRef<OsStr> + ?Sized>(
+        name: &S,
+        contents: &T,
+    ) -> Result<FileHandler<T>, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        let options = Options::default();
+        let contents = options.encode(contents)?;
+
+        let _guard = global_mutex::lock();
+
+        let (location, path) = create_new_file(name, &contents, &options)?;
+        let mut file_handler = FileHandler::from_path(path, location, true, options);
+        file_handler.was_created = true;
+        Ok(file_handler)
+    }
+
+    /// Open an existing file like [`try_open()`](#method.try_open), or else create it initialised
+    /// to the value returned by `build`, e.g. to run an interactive first-time setup.
+    ///
+    /// `build` is only called if no copy of the file exists in any location.  Its value is written
+    /// to the first writable of the locations tried by [`new()`](#method.new), and an error from
+    /// it is returned without creating anything.  If a copy exists but can't be opened, e.g.
+    /// because `assert_writable` is set and it is read-only, that error is returned rather than
+    /// building a new one.
+    pub fn open_or_build<S, F>(
+        name: &S,
+        assert_writable: bool,
+        build: F,
+    ) -> Result<FileHandler<T>, Error>
+    where
+        S: AsRef<OsStr> + ?Sized,
+        F: FnOnce() -> Result<T, Error>,
+    {
+        let name = name.as_ref();
+        validate_name(name)?;
+        if let Some(file_handler) = Self::try_open(name, assert_writable)? {
+            return Ok(file_handler);
+        }
+
+        let options = Options::default();
+        let contents = options.encode(&build()?)?;
+
+        let _guard = global_mutex::lock();
+
+        let (location, path, created) =
+            create_or_keep_file(name, &contents, &options, assert_writable)?;
+        let mut file_handler = FileHandler::from_path(path, location, assert_writable, options);
+        file_handler.was_created = created;
+        Ok(file_handler)
+    }
+
+    /// Write `contents` to the file, encoded using the handler's [`Format`](enum.Format.html).
+    ///
+    /// Unless disabled via [`Builder::durable()`](struct.Builder.html#method.durable), the data is
+    /// flushed to disk with `sync_all` before this returns, so it survives a crash or power
+    /// failure.
+    pub fn write_file(&self, contents: &T) -> Result<(), Error> {
+        self.write_bytes(&self.options.encode(contents)?)
+    }
+
+    /// Write `contents` to the file like [`write_file()`](#method.write_file), returning the
+    /// BLAKE2s-256 digest of the bytes written for later use with
+    /// [`verify_checksum()`](#method.verify_checksum).
+    #[cfg(feature = "blake2")]
+    pub fn write_file_with_checksum(&self, contents: &T) -> Result<[u8; 32], Error> {
+        use blake2::{Blake2s256, Digest};
+
+        let contents = self.options.encode(contents)?;
+        self.write_bytes(&contents)?;
+        Ok(Blake2s256::digest(&contents).into())
+    }
+
+    /// Write `contents` to the file like [`write_file()`](#method.write_file), with the keys of
+    /// every object sorted, so that the same config is always written byte-for-byte identically,
+    /// e.g. for files tracked in version control or addressed by their hash.
+    ///
+    /// `contents` is converted to a `serde_json::Value` whose keys are sorted recursively, which is
+    /// then written using the handler's format and indentation.  This means e.g. the order of a
+    /// `HashMap` field doesn't matter.  It requires a self-describing format such as JSON.
+    pub fn write_file_canonical(&self, contents: &T) -> Result<(), Error>
+    where
+        T: Serialize,
+    {
+        let mut value = serde_json::to_value(contents)?;
+        merge::sort_keys(&mut value);
+        self.write_bytes(&self.options.encode(&value)?)
+    }
+
+    /// Write `contents` to the file like [`write_file()`](#method.write_file), but encoded by
+    /// `serialize` rather than the handler's [`Format`](enum.Format.html).
+    ///
+    /// This allows e.g. a canonicalising serialiser with sorted keys to be used while keeping the
+    /// same locking as `write_file`.  The bytes are produced before any lock is taken, and are
+    /// still encrypted if a key has been set.
+    pub fn write_file_with<F>(&self, contents: &T, serialize: F) -> Result<(), Error>
+    where
+        F: FnOnce(&T) -> Result<Vec<u8>, Error>,
+    {
+        self.write_bytes(&self.options.seal(serialize(contents)?)?)
+    }
+
+    /// Write `contents` to the handler's file like [`write_file()`](#method.write_file), and to
+    /// every other copy of a file with the same name in the locations searched by
+    /// [`open()`](#method.open), e.g. to keep redundant copies in sync.
+    ///
+    /// Copies are only written where they already exist; none are created.  While holding the
+    /// global mutex, each is written under its exclusive lock.  A failure to write one copy doesn't
+    /// stop the others from being written, but the first such error is returned.
+    pub fn write_file_all(&self, contents: &T) -> Result<(), Error> {
+        let contents = self.options.encode(contents)?;
+        if self.memory.is_some() {
+            return self.write_bytes(&contents);
+        }
+        let name = self.path.file_name().unwrap_or_default();
+
+        let guard = global_mutex::lock_in(&self.options.mutex);
+
+        let mut result = self
+            .open_write()
+            .and_then(|mut file| write_with_lock(&mut file, &contents, &self.options));
+        let copies = search_dirs(&self.options)
+            .into_iter()
+            .filter_map(|(_, dir)| dir.ok())
+            .map(|dir| dir.join(name))
+            .filter(|path| *path != self.path && path.is_file());
+        for path in copies {
+            let written = OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .map_err(Error::from)
+                .and_then(|mut file| write_with_lock(&mut file, &contents, &self.options));
+            result = result.and(written);
+        }
+        drop(guard);
+        self.notify_write(result, |_| true)
+    }
+
+    /// Write `contents` to the file like [`write_file()`](#method.write_file), unless the file
+    /// already holds exactly the same serialised bytes, so that its modification time isn't
+    /// bumped and watchers of it aren't triggered needlessly.
+    ///
+    /// The file is read, compared and written while holding the global mutex and the exclusive file
+    /// lock.  An encrypted file is compared after decrypting it.  Returns `true` if the file was
+    /// written, or `false` if it was left untouched.
+    pub fn write_file_if_changed(&self, contents: &T) -> Result<bool, Error> {
+        let options = &self.options;
+        let serialized = options.serialize(contents)?;
+        let unchanged =
+            |mut current: Vec<u8>| options.unseal(&mut current).is_ok() && current == serialized;
+
+        let result = self.update_raw(|current| {
+            if unchanged(current) {
+                return Ok((None, false));
+            }
+            Ok((Some(options.seal(serialized.clone())?), true))
+        });
+        self.notify_write(result, |&written| written)
+    }
+
+    /// Write `new` to the file only if its current contents decode to a value equal to
+    /// `expected`, e.g. to update a config without losing a concurrent change made since it was
+    /// read.
+    ///
+    /// The file is read, compared and written while holding the global mutex and the exclusive file
+    /// lock.  Returns `true` if `new` was written, or `false` if the current value differs from
+    /// `expected`, in which case the file is left untouched.
+    pub fn compare_and_swap(&self, expected: &T, new: &T) -> Result<bool, Error>
+    where
+        T: Decode + PartialEq,
+    {
+        let (options, path) = (&self.options, &self.path);
+        let contents = options.encode(new)?;
+
+        let result = self.update_raw(|current| {
+            let current: T = options
+                .decode(&current[..])
+                .map_err(|e| parse_error(path, e))?;
+            if current != *expected {
+                return Ok((None, false));
+            }
+            Ok((Some(contents), true))
+        });
+        self.notify_write(result, |&written| written)
+    }
+
+    /// Capture the current contents of the file, e.g. before letting the user experiment with
+    /// settings, so that they can be put back with [`restore()`](#method.restore) on "cancel".
+    ///
+    /// The raw bytes are read under the shared lock and held in memory, so the checkpoint costs no
+    /// more than the file's size and is unaffected by later changes to the file.
+    pub fn checkpoint(&self) -> Result<Checkpoint, Error> {
+        Ok(Checkpoint {
+            contents: self.read_raw()?,
+        })
+    }
+
+    /// Put the file back to the contents captured by [`checkpoint()`](#method.checkpoint).
+    ///
+    /// The bytes are written verbatim under the global mutex and the exclusive file lock, as for
+    /// [`write_file()`](#method.write_file), so readers see either the current contents or the
+    /// restored ones.
+    pub fn restore(&self, checkpoint: &Checkpoint) -> Result<(), Error> {
+        self.write_bytes(&checkpoint.contents)
+    }
+
+    fn write_bytes(&self, contents: &[u8]) -> Result<(), Error> {
+        if let Some(ref memory) = self.memory {
+            *unwrap!(memory.lock()) = contents.to_vec();
+            return self.notify_write(Ok(()), |_| true);
+        }
+        let guard = global_mutex::lock_in(&self.options.mutex);
+
+        let result = with_retry(&self.options, || {
+            write_with_lock(&mut *self.open_write()?, contents, &self.options)
+        });
+        drop(guard);
+        self.notify_write(result, |_| true)
+    }
+}
+
+impl<U> FileHandler<Vec<U>>
+where
+    U: DeserializeOwned + Serialize,
+{
+    /// Append `item` to the array stored in the file, e.g. a cache of peer addresses.
+    ///
+    /// The file is read, extended and rewritten while holding the global mutex and the exclusive
+    /// file lock, so concurrent appends from other threads or processes aren't lost.
+    pub fn append_to_array(&self, item: &U) -> Result<(), Error> {
+        // Serialises `items` followed by `item`, so that `item` needn't be cloned to push it.
+        struct Appended<'a, U>(&'a [U], &'a U);
+
+        impl<'a, U: Serialize> Serialize for Appended<'a, U> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq(self.0.iter().chain(iter::once(self.1)))
+            }
+        }
+
+        let (options, path) = (&self.options, &self.path);
+
+        // The whole array is currently rewritten.  For JSON, this could be optimised into a true
+        // append by overwriting the closing bracket, since the rest of the file is unchanged.
+        let result = self.update_raw(|current| {
+            let items: Vec<U> = options
+                .decode(&current[..])
+                .map_err(|e| parse_error(path, e))?;
+            Ok((Some(options.encode(&Appended(&items, item))?), ()))
+        });
+        self.notify_write(result, |_| true)
+    }
+}
+
+/// The contents of a config file at some point, returned by [`FileHandler::checkpoint()`]
+/// (struct.FileHandler.html#method.checkpoint) to be passed to [`FileHandler::restore()`]
+/// (struct.FileHandler.html#method.restore).
+#[derive(Clone)]
+pub struct Checkpoint {
+    contents: Vec<u8>,
+}
+
+impl fmt::Debug for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checkpoint")
+            .field("len", &self.contents.len())
+            .finish()
+    }
+}
+
+/// A working copy of a config, returned by [`FileHandler::begin()`]
+/// (struct.FileHandler.html#method.begin).
+///
+/// The value can be read and modified via `Deref` and `DerefMut`.
+pub struct ConfigTxn<T> {
+    file_handler: FileHandler<T>,
+    value: T,
+    original_hash: u64,
+}
+
+impl<T: Encode> ConfigTxn<T> {
+    /// Write the working copy back to the file.
+    ///
+    /// While holding the global mutex and the exclusive file lock, the file is first checked to be
+    /// unchanged since the transaction began.  If it has been changed, e.g. by another process,
+    /// `Error::Conflict` is returned and the file is left untouched.
+    pub fn commit(self) -> Result<(), Error> {
+        let file_handler = &self.file_handler;
+        let contents = file_handler.options.encode(&self.value)?;
+        let original_hash = self.original_hash;
+
+        let result = file_handler.update_raw(|current| {
+            if fnv1a(&current) != original_hash {
+                return Err(Error::Conflict {
+                    path: file_handler.path.clone(),
+                });
+            }
+            Ok((Some(contents), ()))
+        });
+        file_handler.notify_write(result, |_| true)
+    }
+}
+
+impl<T> Deref for ConfigTxn<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for ConfigTxn<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+/// The two layers of a config, returned by [`FileHandler::open_layers()`]
+/// (struct.FileHandler.html#method.open_layers).
+///
+/// Reading merges the user layer over the defaults, and writing only ever changes the user layer.
+pub struct ConfigLayers<T> {
+    name: OsString,
+    /// The read-only packaged defaults, if there are any.
+    pub defaults: Option<FileHandler<T>>,
+    /// The user's override, if there is one.
+    pub user: Option<FileHandler<T>>,
+}
+
+impl<T> ConfigLayers<T>
+where
+    T: Default + DeserializeOwned + Serialize,
+{
+    /// Read the effective config: each existing layer is merged over `T::default()` in turn, as
+    /// in [`FileHandler::read_file_layered()`](struct.FileHandler.html#method.read_file_layered).
+    pub fn read(&self) -> Result<T, Error> {
+        let mut merged = serde_json::to_value(T::default())?;
+        for file_handler in self.defaults.iter().chain(self.user.iter()) {
+            merge::merge(
+                &mut merged,
+                read_value(&file_handler.path, &file_handler.options)?,
+            );
+        }
+        Ok(serde_json::from_value(merged)?)
+    }
+
+    /// Write `contents` to the user layer.  If there is no user layer yet, the file is first
+    /// created in [`user_app_dir()`](fn.user_app_dir.html), or failing that in
+    /// [`system_cache_dir()`](fn.system_cache_dir.html), and becomes the user layer.
+    pub fn write(&mut self, contents: &T) -> Result<(), Error> {
+        if let Some(ref user) = self.user {
+            return user.write_file(contents);
+        }
+        let options = Options::default();
+        let dirs = creation_dirs(&options)
+            .into_iter()
+            .filter(|&(location, _, _)| USER_LAYER.contains(&location))
+            .collect();
+        let contents = options.encode(contents)?;
+
+        let _guard = global_mutex::lock();
+
+        let (location, path, _) =
+            create_file_in(dirs, &self.name, &contents, &options, CreateMode::Overwrite)?;
+        let mut user = FileHandler::from_path(path, location, true, options);
+        user.was_created = true;
+        self.user = Some(user);
+        Ok(())
+    }
+}
+
+/// The exclusive lock on a config file, returned by [`FileHandler::lock_exclusive_guard()`]
+/// (struct.FileHandler.html#method.lock_exclusive_guard).  The lock is released on drop.
+pub struct ConfigLockGuard<'a, T> {
+    file_handler: &'a FileHandler<T>,
+    file: OpenedFile<'a>,
+    written: bool,
+}
+
+impl<'a, T: Decode> ConfigLockGuard<'a, T> {
+    /// Read the contents of the locked file and decode it, as for
+    /// [`FileHandler::read_file()`](struct.FileHandler.html#method.read_file).
+    pub fn read(&mut self) -> Result<T, Error> {
+        let options = &self.file_handler.options;
+        options.check_size(self.file.metadata()?.len())?;
+        let _ = self.file.seek(SeekFrom::Start(0))?;
+        options
+            .decode(&mut *self.file)
+            .map_err(|e| parse_error(&self.file_handler.path, e))
+    }
+}
+
+impl<'a, T: Encode> ConfigLockGuard<'a, T> {
+    /// Replace the contents of the locked file with `contents`, as for
+    /// [`FileHandler::write_file()`](struct.FileHandler.html#method.write_file).
+    pub fn write(&mut self, contents: &T) -> Result<(), Error> {
+        let options = &self.file_handler.options;
+        let contents = options.encode(contents)?;
+        overwrite(&mut self.file, &contents, options.durable)?;
+        self.written = true;
+        Ok(())
+    }
+}
+
+impl<'a, T> Drop for ConfigLockGuard<'a, T> {
+    fn drop(&mut self) {
+        // Closing a newly-opened file would release the lock anyway, but an inherited one stays
+        // open.
+        let _ = self.file.unlock();
+        trace!("Released exclusive lock on {:?}", *self.file);
+        if self.written {
+            self.file_handler.run_write_hooks();
+        }
+    }
+}
+
+/// Read a config from the standard input, decoded as JSON, as for a config file given as `-` on
+/// the command line.
+///
+/// No locking is done, since it doesn't apply to the standard streams.
+pub fn read_from_stdin<T: DeserializeOwned>() -> Result<T, Error> {
+    Format::default().deserialize(io::stdin().lock())
+}
+
+/// Write a config to the standard output, encoded as JSON like
+/// [`FileHandler::write_file()`](struct.FileHandler.html#method.write_file) would write it.
+///
+/// No locking is done, since it doesn't apply to the standard streams.
+pub fn write_to_stdout<T: Serialize>(contents: &T) -> Result<(), Error> {
+    let contents = Format::default().serialize(contents, DEFAULT_INDENT)?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(&contents)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Decode a config from `contents` exactly as [`FileHandler::read_file()`]
+/// (struct.FileHandler.html#method.read_file) would decode a file holding them with the default
+/// options, e.g. to check inline test fixtures, including the handling of a byte order mark and
+/// the size limit.
+pub fn parse_config<T: Decode>(contents: &str) -> Result<T, Error> {
+    Options::default().decode(contents.as_bytes())
+}
+
+/// Encode `contents` exactly as [`FileHandler::write_file()`]
+/// (struct.FileHandler.html#method.write_file) would write it with the default options.
+pub fn serialize_config<T: Encode>(contents: &T) -> Result<String, Error> {
+    let contents = Options::default().encode(contents)?;
+    String::from_utf8(contents)
+        .map_err(|e| Error::Io(io::Error::new(io::ErrorKind::InvalidData, e)))
+}
+
+/// The directories searched by [`FileHandler::open()`](struct.FileHandler.html#method.open), in
+/// the order they are searched, each with its location and the result of resolving it, e.g. for
+/// tooling which checks every location for a config.
+///
+/// This includes the additional search paths, for the current thread and then globally, if set,
+/// and the subdirectory set by [`set_config_subdir()`](fn.set_config_subdir.html), if any.  The
+/// directories are resolved when this is called, and needn't exist.
+pub fn search_locations() -> impl Iterator<Item = (Location, Result<PathBuf, Error>)> {
+    search_dirs(&Options::default()).into_iter()
+}
+
+/// List the names (not the full paths) of the files in the directory of `location`.
+///
+/// An empty list is returned if the directory doesn't exist, or if `location` is
+/// `Location::AdditionalSearchPath` and no additional search path has been set.
+pub fn list_configs(location: Location) -> Result<Vec<OsString>, Error> {
+    let mut names = Vec::new();
+    for dir in location_dirs(location, &Options::default()) {
+        let dir = dir?;
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                names.push(entry.file_name());
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Remove the file from every location where it can be read.
+///
+/// Like [`FileHandler::open()`](struct.FileHandler.html#method.open), this returns
+/// `Error::InvalidName` if `name` contains path separators or `..` components.
+pub fn cleanup<S: AsRef<OsStr>>(name: &S) -> Result<(), Error> {
+    let name = name.as_ref();
+    validate_name(name)?;
+    let i1 = current_bin_dir().into_iter();
+    let i2 = user_app_dir().into_iter();
+    let i3 = system_cache_dir().into_iter();
+
+    let dirs = i1.chain(i2.chain(i3));
+    let subdir = paths::config_subdir();
+
+    for mut path in dirs {
+        path.extend(&subdir);
+        path.push(name);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The environment variable which selects the profile for `open_with_profile()` when none is
+/// given.
+const PROFILE_ENV_VAR: &str = "CONFIG_PROFILE";
+
+/// `base_name` with `.<tag>` inserted before its extension, e.g. `config.prod.json`.
+fn variant_name(base_name: &Path, tag: &str) -> OsString {
+    let mut name = base_name.file_stem().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(tag);
+    if let Some(extension) = base_name.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    name
+}
+
+/// Resolve the directories of `location` in which config files are kept: its base directories,
+/// joined with the subdirectory set by `set_config_subdir()` if there is one.  This is a single
+/// directory, except for the additional search path, which may have a thread-local and a global
+/// directory, or neither.
+fn location_dirs(location: Location, options: &Options) -> Vec<Result<PathBuf, Error>> {
+    let dirs = match location {
+        Location::AdditionalSearchPath => paths::additional_search_paths()
+            .into_iter()
+            .map(Ok)
+            .collect(),
+        Location::CurrentBinDir => vec![current_bin_dir()],
+        Location::BundleResourceDir => vec![bundle_resource_dir()],
+        Location::UserAppDir => vec![options.user_app_dir()],
+        Location::SystemCacheDir if options.disable_system_cache => vec![],
+        Location::SystemCacheDir => vec![system_cache_dir()],
+        Location::Explicit => vec![],
+    };
+    match paths::config_subdir() {
+        Some(subdir) => dirs
+            .into_iter()
+            .map(|dir| dir.map(|dir| dir.join(&subdir)))
+            .collect(),
+        None => dirs,
+    }
+}
+
+/// The directories searched by `open`, in order of preference.
+fn search_dirs(options: &Options) -> Vec<(Location, Result<PathBuf, Error>)> {
+    [
+        Location::AdditionalSearchPath,
+        Location::CurrentBinDir,
+        Location::BundleResourceDir,
+        Location::UserAppDir,
+        Location::SystemCacheDir,
+    ]
+    .iter()
+    .flat_map(|&location| {
+        location_dirs(location, options)
+            .into_iter()
+            .map(move |dir| (location, dir))
+    })
+    .collect()
+}
+
+/// The locations holding read-only packaged defaults, for layered reads.
+const DEFAULTS_LAYER: &[Location] = &[Location::CurrentBinDir, Location::BundleResourceDir];
+/// The locations holding the user's overrides, for layered reads.
+const USER_LAYER: &[Location] = &[Location::UserAppDir, Location::SystemCacheDir];
+
+/// The location and path of the first readable copy of the file `name` in any of `locations`,
+/// searched in the usual order.
+fn first_readable(
+    name: &OsStr,
+    locations: &[Location],
+    options: &Options,
+) -> Option<(Location, PathBuf)> {
+    search_dirs(options)
+        .into_iter()
+        .filter(|&(location, _)| locations.contains(&location))
+        .filter_map(|(location, dir)| dir.ok().map(|dir| (location, dir.join(name))))
+        .find(|(_, path)| File::open(path).is_ok())
+}
+
+/// The path of the entry in the same directory as `path` whose name matches `path`'s ignoring
+/// case, if there is one.  If there are several, the first in sorted order is used.
+fn find_case_insensitive(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    let mut matches = fs::read_dir(path.parent()?)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|candidate| {
+            candidate
+                .file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(|candidate| candidate.to_lowercase() == name)
+        })
+        .collect::<Vec<_>>();
+    matches.sort();
+    matches.into_iter().next()
+}
+
+/// How many bytes at the start of a file `check_text()` looks at to decide whether it is text.
+const TEXT_SNIFF_LEN: u64 = 8 * 1024;
+
+/// Fail with `Error::NotText` if the start of `file` doesn't look like text, e.g. because a JSON
+/// handler was pointed at a binary file, rather than with an obscure error from deep within the
+/// parser.  The file is rewound afterwards.
+fn check_text(file: &mut File, path: &Path) -> Result<(), Error> {
+    let mut sample = Vec::new();
+    let _ = Read::by_ref(file)
+        .take(TEXT_SNIFF_LEN)
+        .read_to_end(&mut sample)?;
+    let _ = file.seek(SeekFrom::Start(0))?;
+    check_text_bytes(&sample, path)
+}
+
+/// Fail with `Error::NotText` if `contents`, read from `path`, don't start with text, as for
+/// `check_text()`.
+fn check_text_bytes(contents: &[u8], path: &Path) -> Result<(), Error> {
+    let sample = &contents[..cmp::min(contents.len(), TEXT_SNIFF_LEN as usize)];
+    if !format::looks_like_text(sample) {
+        return Err(Error::NotText {
+            path: path.to_path_buf(),
+        });
+    }
+    Ok(())
+}
+
+/// Decode the file at `path` into a `serde_json::Value` under the shared lock.
+fn read_value(path: &Path, options: &Options) -> Result<Value, Error> {
+    decode_value(&mut File::open(path)?, path, options)
+}
+
+/// Decode `file`, which was opened from `path`, into a `serde_json::Value` under the shared lock.
+fn decode_value(file: &mut File, path: &Path, options: &Options) -> Result<Value, Error> {
+    shared_lock(file, options.lock_mode, |file| {
+        options.check_size(file.metadata()?.len())?;
+        options.decode(file).map_err(|e| parse_error(path, e))
+    })
+}
+
+/// Attach `path` to a decoding error.  IO and `TooLarge` errors are passed through unchanged.
+pub(crate) fn parse_error(path: &Path, error: Error) -> Error {
+    match error {
+        Error::Io(_) | Error::TooLarge { .. } => error,
+        #[cfg(feature = "encryption")]
+        Error::Decryption => error,
+        error => Error::Parse {
+            path: path.to_path_buf(),
+            err: Box::new(error),
+        },
+    }
+}
+
+/// The directories `new` tries to create a file in, in order of preference, each paired with
+/// whether the directory itself may be created if it doesn't exist yet.  With a config
+/// subdirectory, any of them may be, so that the subdirectory can be created.
+fn creation_dirs(options: &Options) -> Vec<(Location, Result<PathBuf, Error>, bool)> {
+    let with_subdir = paths::config_subdir().is_some();
+    [
+        (Location::AdditionalSearchPath, false),
+        (Location::CurrentBinDir, false),
+        (Location::UserAppDir, true),
+        (Location::SystemCacheDir, true),
+    ]
+    .iter()
+    .flat_map(|&(location, create_dir)| {
+        location_dirs(location, options)
+            .into_iter()
+            .map(move |dir| (location, dir, create_dir || with_subdir))
+    })
+    .collect()
+}
+
+/// Create the file `name` in the first writable of the `creation_dirs()` and write `contents` to
+/// it, returning its location and path.  The caller should be holding the global mutex.
+///
+/// If no location is writable, the returned `Error::NoWritableLocation` lists every path tried.
+fn create_file(
+    name: &OsStr,
+    contents: &[u8],
+    options: &Options,
+) -> Result<(Location, PathBuf), Error> {
+    create_file_in(
+        creation_dirs(options),
+        name,
+        contents,
+        options,
+        CreateMode::Overwrite,
+    )
+    .map(|(location, path, _)| (location, path))
+}
+
+/// Create the file `name` like `create_file()`, but fail with `Error::AlreadyExists` rather than
+/// overwriting it if it already exists in the first writable location.
+fn create_new_file(
+    name: &OsStr,
+    contents: &[u8],
+    options: &Options,
+) -> Result<(Location, PathBuf), Error> {
+    create_file_in(
+        creation_dirs(options),
+        name,
+        contents,
+        options,
+        CreateMode::Exclusive,
+    )
+    .map(|(location, path, _)| (location, path))
+}
+
+/// Create the file `name` initialised to `contents` like `create_file()`, unless another thread
+/// or process created it first, in which case it is kept as it is.  The returned flag is whether
+/// the file was created.  See `CreateMode::KeepExisting`.
+fn create_or_keep_file(
+    name: &OsStr,
+    contents: &[u8],
+    options: &Options,
+    writable: bool,
+) -> Result<(Location, PathBuf, bool), Error> {
+    create_file_in(
+        creation_dirs(options),
+        name,
+        contents,
+        options,
+        CreateMode::KeepExisting { writable },
+    )
+}
+
+/// What `create_file_in()` does with a file which already exists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CreateMode {
+    /// Overwrite it with the new contents.
+    Overwrite,
+    /// Fail with `Error::AlreadyExists`.
+    Exclusive,
+    /// Keep it unchanged if it can be opened for reading, and for writing if `writable` is set,
+    /// and otherwise skip to the next location.  This is for creating a default, so that whoever
+    /// loses a race to create the file uses the winner's copy rather than clobbering a value which
+    /// may already have been written to it.
+    KeepExisting { writable: bool },
+}
+
+fn create_file_in(
+    dirs: Vec<(Location, Result<PathBuf, Error>, bool)>,
+    name: &OsStr,
+    contents: &[u8],
+    options: &Options,
+    mode: CreateMode,
+) -> Result<(Location, PathBuf, bool), Error> {
+    validate_name(name)?;
+    let mut attempts = Vec::new();
+
+    for (location, dir, create_dir) in dirs {
+        let dir = match dir {
+            Ok(dir) => dir,
+            Err(e) => {
+                debug!("Skipped creating in {:?}: {}", location, e);
+                continue;
+            }
+        };
+        if create_dir && !dir.is_dir() {
+            if let Err(e) = create_dir_all(&dir, options) {
+                debug!(
+                    "Skipped creating in {} ({:?}): {}",
+                    dir.display(),
+                    location,
+                    e
+                );
+                attempts.push((dir, e.kind()));
+                continue;
+            }
+        }
+        let path = dir.join(name);
+        match open_for_write(&path, options, mode != CreateMode::Overwrite) {
+            Ok(mut file) => {
+                // Some read-only mounts, e.g. overlays, only refuse the write itself.
+                match write_with_lock(&mut file, contents, options) {
+                    Err(Error::Io(ref e)) if is_read_only_fs(e) => {
+                        debug!(
+                            "Skipped creating {} ({:?}): {}",
+                            path.display(),
+                            location,
+                            e
+                        );
+                        attempts.push((path, e.kind()));
+                        continue;
+                    }
+                    result => result?,
+                }
+                if options.durable {
+                    sync_dir(&dir)?;
+                }
+                debug!("Created {} ({:?})", path.display(), location);
+                if location == Location::SystemCacheDir {
+                    warn!(
+                        "Fell through to the system cache dir for {}",
+                        path.display()
+                    );
+                }
+                return Ok((location, path, true));
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => match mode {
+                CreateMode::KeepExisting { writable } => {
+                    match OpenOptions::new().read(true).write(writable).open(&path) {
+                        Ok(_) => {
+                            debug!("Kept existing {} ({:?})", path.display(), location);
+                            return Ok((location, path, false));
+                        }
+                        Err(e) => {
+                            debug!(
+                                "Skipped creating {} ({:?}): {}",
+                                path.display(),
+                                location,
+                                e
+                            );
+                            attempts.push((path, e.kind()));
+                        }
+                    }
+                }
+                _ => return Err(Error::AlreadyExists { path }),
+            },
+            Err(e) => {
+                debug!(
+                    "Skipped creating {} ({:?}): {}",
+                    path.display(),
+                    location,
+                    e
+                );
+                attempts.push((path, e.kind()));
+            }
+        }
+    }
+
+    if !attempts.is_empty() && attempts.iter().all(|&(_, kind)| kind == READ_ONLY_FS_KIND) {
+        warn!("Every location for {:?} is on a read-only filesystem", name);
+    }
+    Err(Error::NoWritableLocation { attempts })
+}
+
+/// The kind of the IO error for a write to a read-only filesystem (`EROFS`).
+const READ_ONLY_FS_KIND: io::ErrorKind = io::ErrorKind::ReadOnlyFilesystem;
+
+/// Whether `error` is `EROFS`, i.e. the location is on a filesystem mounted read-only, such as
+/// the root of an immutable distribution, and can never be written.
+fn is_read_only_fs(error: &io::Error) -> bool {
+    error.kind() == READ_ONLY_FS_KIND
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Directories in which `open_for_write()` fails with `EROFS`, and which `is_writable()`
+    /// reports as read-only, on the current thread, to simulate read-only mounts in tests.
+    static SIMULATED_READ_ONLY_DIRS: std::cell::RefCell<Vec<PathBuf>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Fail with `EROFS` if `path` is in one of the `SIMULATED_READ_ONLY_DIRS`.
+#[cfg(test)]
+fn check_simulated_read_only(path: &Path) -> io::Result<()> {
+    let read_only =
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow().iter().any(|dir| path.starts_with(dir)));
+    if read_only {
+        return Err(io::Error::from(READ_ONLY_FS_KIND));
+    }
+    Ok(())
+}
+
+#[cfg(not(test))]
+fn check_simulated_read_only(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// The path at which `create_file()` would create the file `name`, without creating anything:
+/// the first of the `creation_dirs()` which is writable, or which doesn't exist yet but may be
+/// created in a writable parent.
+fn creation_path(name: &OsStr, options: &Options) -> Result<(Location, PathBuf), Error> {
+    let mut attempts = Vec::new();
+
+    for (location, dir, create_dir) in creation_dirs(options) {
+        let dir = match dir {
+            Ok(dir) => dir,
+            Err(_) => continue,
+        };
+        let path = dir.join(name);
+        let writable = if path.exists() {
+            check_simulated_read_only(&path)
+                .and_then(|()| OpenOptions::new().write(true).open(&path).map(|_| ()))
+        } else if dir.is_dir() {
+            check_dir_writable(&dir)
+        } else if create_dir {
+            dir.ancestors()
+                .find(|ancestor| ancestor.is_dir())
+                .map_or_else(|| Err(io::ErrorKind::NotFound.into()), check_dir_writable)
+        } else {
+            Err(io::ErrorKind::NotFound.into())
+        };
+        match writable {
+            Ok(()) => return Ok((location, path)),
+            Err(e) => attempts.push((path, e.kind())),
+        }
+    }
+
+    Err(Error::NoWritableLocation { attempts })
+}
+
+/// Whether files can be created in the existing directory `dir`.
+/// Whether the open `file` was opened for writing, from its access mode.
+#[cfg(unix)]
+fn is_open_for_writing(file: &File) -> io::Result<Option<bool>> {
+    use std::os::unix::io::AsRawFd;
+
+    // `F_GETFL` only reads the flags of the descriptor, which `file` keeps open.
+    #[allow(unsafe_code)]
+    let flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) };
+    if flags == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let access_mode = flags & libc::O_ACCMODE;
+    Ok(Some(
+        access_mode == libc::O_WRONLY || access_mode == libc::O_RDWR,
+    ))
+}
+
+/// The access mode of a handle can't be queried through the standard library, so whether it was
+/// opened for writing is unknown.
+#[cfg(not(unix))]
+fn is_open_for_writing(_file: &File) -> io::Result<Option<bool>> {
+    Ok(None)
+}
+
+fn is_dir_writable(dir: &Path) -> bool {
+    check_dir_writable(dir).is_ok()
+}
+
+/// Fail with the reason, e.g. `PermissionDenied`, if files can't be created in the existing
+/// directory `dir`.
+#[cfg(unix)]
+fn check_dir_writable(dir: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    check_simulated_read_only(dir)?;
+    let dir = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // `access()` only reads the NUL-terminated path, which outlives the call.
+    #[allow(unsafe_code)]
+    let accessible = unsafe { libc::access(dir.as_ptr(), libc::W_OK | libc::X_OK) == 0 };
+    if accessible {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Fail with the reason if files can't be created in the existing directory `dir`.  Windows
+/// ignores the read-only attribute of directories, so this is only an approximation.
+#[cfg(not(unix))]
+fn check_dir_writable(dir: &Path) -> io::Result<()> {
+    check_simulated_read_only(dir)?;
+    if fs::metadata(dir)?.permissions().readonly() {
+        return Err(io::ErrorKind::PermissionDenied.into());
+    }
+    Ok(())
+}
+
+/// Open `path` for writing, creating it if needed, and retrying transient Windows errors as
+/// configured by `options`.  If `create_new` is set, the open fails if the file already exists.
+///
+/// The file isn't truncated here, since that would let readers see it empty before the exclusive
+/// lock is taken; `write_with_lock()` truncates it under the lock instead.
+fn open_for_write(path: &Path, options: &Options, create_new: bool) -> io::Result<File> {
+    let mut open_options = OpenOptions::new();
+    if create_new {
+        let _ = open_options.write(true).create_new(true);
+    } else {
+        let _ = open_options.write(true).create(true).truncate(false);
+    }
+    // A new file is created with `mode` less the umask, so that it is never more accessible than
+    // `mode`, even before its permissions are set exactly below.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        if let Some(mode) = options.mode {
+            let _ = open_options.mode(mode);
+        }
+    }
+
+    check_simulated_read_only(path)?;
+
+    let existed = !create_new && path.exists();
+    let mut backoff = options.sharing_retry_backoff;
+    let mut retries = 0;
+    loop {
+        match open_options.open(path) {
+            Err(ref e)
+                if retries < options.sharing_retry_attempts && is_transient_open_error(e) =>
+            {
+                thread::sleep(backoff);
+                backoff *= 2;
+                retries += 1;
+            }
+            Ok(file) if !existed => {
+                set_file_mode(&file, options.mode)?;
+                return Ok(file);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Create `dir` and any missing parents, giving `dir` the configured `dir_mode`.
+fn create_dir_all(dir: &Path, options: &Options) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    set_mode(dir, options.dir_mode)
+}
+
+/// Set the permission bits of `path` to `mode`, if given, bypassing the umask.
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Set the permission bits of the open `file` to `mode`, if given, bypassing the umask.
+#[cfg(unix)]
+fn set_file_mode(file: &File, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    match mode {
+        Some(mode) => file.set_permissions(fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(_file: &File, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Whether `error` is likely caused by another process, such as a virus scanner, briefly holding
+/// the file open.
+#[cfg(windows)]
+fn is_transient_open_error(error: &io::Error) -> bool {
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    error.raw_os_error() == Some(ERROR_SHARING_VIOLATION)
+        || error.kind() == io::ErrorKind::PermissionDenied
+}
+
+#[cfg(not(windows))]
+fn is_transient_open_error(_error: &io::Error) -> bool {
+    false
+}
+
+/// Errors which `with_retry()` may retry.
+trait AsIoError {
+    fn as_io_error(&self) -> Option<&io::Error>;
+}
+
+impl AsIoError for io::Error {
+    fn as_io_error(&self) -> Option<&io::Error> {
+        Some(self)
+    }
+}
+
+impl AsIoError for Error {
+    fn as_io_error(&self) -> Option<&io::Error> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Run `f`, retrying it as set by `Builder::retry()` while it fails with a transient IO error.
+fn with_retry<R, E, F>(options: &Options, mut f: F) -> Result<R, E>
+where
+    E: AsIoError,
+    F: FnMut() -> Result<R, E>,
+{
+    let mut backoff = options.retry_backoff;
+    let mut retries = 0;
+    loop {
+        match f() {
+            Err(ref e)
+                if retries < options.retry_attempts
+                    && e.as_io_error()
+                        .is_some_and(|e| is_transient_error(e, options)) =>
+            {
+                debug!("Retrying after transient error: {:?}", e.as_io_error());
+                thread::sleep(backoff);
+                backoff *= 2;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether `error` is one of the kinds set by `Builder::retry_on()`, or is `EIO` or `ESTALE`,
+/// which network filesystems such as NFS may return transiently.
+fn is_transient_error(error: &io::Error, options: &Options) -> bool {
+    #[cfg(target_os = "linux")]
+    const TRANSIENT_OS_ERRORS: &[i32] = &[5, 116];
+    #[cfg(all(unix, not(target_os = "linux")))]
+    const TRANSIENT_OS_ERRORS: &[i32] = &[5, 70];
+    #[cfg(not(unix))]
+    const TRANSIENT_OS_ERRORS: &[i32] = &[];
+
+    options.retry_kinds.contains(&error.kind())
+        || error
+            .raw_os_error()
+            .is_some_and(|code| TRANSIENT_OS_ERRORS.contains(&code))
+}
+
+/// Run `f` while holding the lock on `file` which `lock_mode` calls for when writing.
+pub(crate) fn exclusive_lock<F, R, E>(
+    file: &mut File,
+    lock_mode: LockMode,
+    f: F,
+) -> Result<R, Error>
+where
+    F: FnOnce(&mut File) -> Result<R, E>,
+    Error: From<E>,
+{
+    with_lock(file, lock_mode, true, f)
+}
+
+/// Take the exclusive lock on `file`, or the shared lock unless `exclusive` is set, polling every
+/// `poll_interval` while it is held elsewhere.  Returns `false` if it is still held elsewhere at
+/// `deadline`.
+fn lock_until(
+    file: &File,
+    exclusive: bool,
+    deadline: Instant,
+    poll_interval: Duration,
+) -> Result<bool, Error> {
+    let contended = fs2::lock_contended_error().raw_os_error();
+    loop {
+        let result = if exclusive {
+            file.try_lock_exclusive()
+        } else {
+            FileExt::try_lock_shared(file)
+        };
+        match result {
+            Ok(()) => return Ok(true),
+            Err(ref e) if e.raw_os_error() == contended => (),
+            Err(e) => return Err(lock_error(e)),
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(false);
+        }
+        thread::sleep(cmp::min(poll_interval, deadline - now));
+    }
+}
+
+/// Run `f` while holding the lock on `file` which `lock_mode` calls for when reading.
+pub(crate) fn shared_lock<F, R, E>(file: &mut File, lock_mode: LockMode, f: F) -> Result<R, Error>
+where
+    F: FnOnce(&mut File) -> Result<R, E>,
+    Error: From<E>,
+{
+    with_lock(file, lock_mode, false, f)
+}
+
+fn with_lock<F, R, E>(file: &mut File, lock_mode: LockMode, write: bool, f: F) -> Result<R, Error>
+where
+    F: FnOnce(&mut File) -> Result<R, E>,
+    Error: From<E>,
+{
+    let exclusive = match lock_mode {
+        LockMode::None => return f(file).map_err(From::from),
+        LockMode::Shared => write,
+        LockMode::Exclusive => true,
+    };
+    let kind = if exclusive { "exclusive" } else { "shared" };
+    trace!("Acquiring {} lock on {:?}", kind, file);
+    if exclusive {
+        file.lock_exclusive()
+    } else {
+        file.lock_shared()
+    }
+    .map_err(lock_error)?;
+    let result = f(file);
+    file.unlock()?;
+    trace!("Released {} lock on {:?}", kind, file);
+    result.map_err(From::from)
+}
+
+/// Convert an error from taking a file lock, distinguishing a filesystem which doesn't support
+/// locking at all, e.g. some network mounts, from a lock which couldn't be taken.
+fn lock_error(error: io::Error) -> Error {
+    #[cfg(unix)]
+    const UNSUPPORTED: &[i32] = &[libc::ENOLCK, libc::ENOSYS, libc::EOPNOTSUPP, libc::ENOTSUP];
+    // ERROR_INVALID_FUNCTION and ERROR_NOT_SUPPORTED.
+    #[cfg(windows)]
+    const UNSUPPORTED: &[i32] = &[1, 50];
+    #[cfg(not(any(unix, windows)))]
+    const UNSUPPORTED: &[i32] = &[];
+
+    if error.kind() == io::ErrorKind::Unsupported
+        || error
+            .raw_os_error()
+            .is_some_and(|code| UNSUPPORTED.contains(&code))
+    {
+        Error::LockUnsupported(error)
+    } else {
+        Error::Io(error)
+    }
+}
+
+/// The 64-bit FNV-1a hash of `bytes`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Read the raw contents of `file` from its current position under the shared lock.
+///
+/// The buffer is sized up front from the file's length, to avoid repeatedly growing it for large
+/// files.
+fn read_locked(file: &mut File, lock_mode: LockMode) -> Result<Vec<u8>, Error> {
+    shared_lock(file, lock_mode, |file| {
+        let len = usize::try_from(file.metadata()?.len()).unwrap_or(0);
+        let mut contents = Vec::with_capacity(len);
+        let _ = file.read_to_end(&mut contents)?;
+        Ok::<_, io::Error>(contents)
+    })
+}
+
+/// Replace the contents of `file` with `contents` under the exclusive lock, flushing it to disk
+/// before the lock is released if `options.durable` is set.
+fn write_with_lock(file: &mut File, contents: &[u8], options: &Options) -> Result<(), Error> {
+    exclusive_lock(file, options.lock_mode, |file| {
+        overwrite(file, contents, options.durable)
+    })
+}
+
+/// Replace the contents of `file`, which must already be locked, with `contents`, flushing it to
+/// disk if `durable` is set.
+fn overwrite(file: &mut File, contents: &[u8], durable: bool) -> io::Result<()> {
+    file.set_len(0)?;
+    let _ = file.seek(SeekFrom::Start(0))?;
+    file.write_all(contents)?;
+    if durable {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Exchange the files at `a` and `b` by way of a new hard link to `a`, undoing the steps already
+/// done if one fails, as described for `FileHandler::swap()`.
+fn exchange_files(a: &Path, b: &Path) -> Result<(), Error> {
+    let temp = link_to_temp(a)?;
+    if let Err(e) = fs::rename(b, a) {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    if let Err(e) = fs::rename(&temp, b) {
+        if fs::rename(a, b)
+            .and_then(|()| fs::rename(&temp, a))
+            .is_err()
+        {
+            warn!(
+                "Failed to restore {}, whose original contents are in {}",
+                a.display(),
+                temp.display()
+            );
+        }
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Create a hard link to `path` beside it, under a name not used by any existing file, and return
+/// the link's path.
+fn link_to_temp(path: &Path) -> io::Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    loop {
+        let mut temp = path.as_os_str().to_os_string();
+        temp.push(format!(
+            ".{}.{}.swap",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp = PathBuf::from(temp);
+        match fs::hard_link(path, &temp) {
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            result => return result.map(|()| temp),
+        }
+    }
+}
+
+/// Flush the directory entries of `dir` to disk, so that a file renamed into it survives a crash.
+/// This is a no-op on Windows, where directories can't be opened as files.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::paths::{
+        clear_path_cache, expand_env_vars, resolved_dirs, with_search_path, CachedPath,
+        ScopedUserAppDirRemover, PATH_CACHE,
+    };
+    use crate::ConfigName;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[test]
+    fn read_write_file_test() {
+        let _cleaner = ScopedUserAppDirRemover::for_file("test0.json");
+        let file_handler = match FileHandler::new("test0.json", true) {
+            Ok(result) => result,
+            Err(err) => panic!("failed accessing file with error {:?}", err),
+        };
+        let test_value = 123_456_789u64;
+
+        let _ = file_handler.write_file(&test_value);
+        let read_value = match file_handler.read_file() {
+            Ok(result) => result,
+            Err(err) => panic!("failed reading file with error {:?}", err),
+        };
+        assert_eq!(test_value, read_value);
+    }
+
+    #[test]
+    fn existing_file_is_overwritten() {
+        let _cleaner = ScopedUserAppDirRemover::for_file("test1.json");
+        let file_handler = FileHandler::new("test1.json", true).expect("failed accessing file");
+
+        let write_value0 = vec![1, 2, 3];
+        file_handler
+            .write_file(&write_value0)
+            .expect("failed writing file");
+
+        let write_value1 = vec![4, 5, 6];
+        file_handler
+            .write_file(&write_value1)
+            .expect("failed writing file");
+
+        let read_value = file_handler.read_file().expect("failed reading file");
+        assert_eq!(read_value, write_value1);
+    }
+
+    #[test]
+    fn concurrent_writes() {
+        use std::iter;
+        use std::sync::{Arc, Barrier};
+        use std::thread;
+
+        const NUM_THREADS: usize = 100;
+        const DATA_SIZE: usize = 10_000;
+        const FILE_NAME: &str = "test2.json";
+
+        let _cleaner = ScopedUserAppDirRemover::for_file(FILE_NAME);
+        let barrier = Arc::new(Barrier::new(NUM_THREADS));
+
+        let handles = (0..NUM_THREADS)
+            .map(|i| {
+                let barrier = Arc::clone(&barrier);
+
+                thread::spawn(move || {
+                    let data = iter::repeat(i).take(DATA_SIZE).collect::<Vec<_>>();
+
+                    let _ = barrier.wait();
+
+                    let file_handler =
+                        FileHandler::new(FILE_NAME, true).expect("failed accessing file");
+                    file_handler.write_file(&data).expect("failed writing file");
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            unwrap!(handle.join());
+        }
+
+        let file_handler = FileHandler::new(FILE_NAME, true).expect("failed accessing file");
+        let mut data: Vec<usize> = file_handler.read_file().expect("failed reading file");
+
+        // Test that all elements in the vector are the same, to verify no
+        // interleaving took place.
+        data.sort();
+        data.dedup();
+        assert_eq!(data.len(), 1);
+    }
+
+    #[test]
+    fn concurrent_reads_are_never_torn() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        const NUM_READERS: usize = 4;
+        const NUM_WRITES: usize = 100;
+        const FILE_NAME: &str = "test36.json";
+
+        let short = vec![1u64; 10];
+        let long = vec![2u64; 10_000];
+        let options = Options {
+            durable: false,
+            ..Options::default()
+        };
+        let file_handler = unwrap!(FileHandler::new_with_options(FILE_NAME, true, options));
+        unwrap!(file_handler.write_file(&short));
+
+        // Shared locks on the same file don't exclude each other, so readers proceed in parallel.
+        let mut first = unwrap!(File::open(file_handler.path()));
+        let second = unwrap!(File::open(file_handler.path()));
+        unwrap!(shared_lock(
+            &mut first,
+            LockMode::Shared,
+            |_| -> io::Result<()> {
+                FileExt::try_lock_shared(&second)?;
+                FileExt::unlock(&second)
+            }
+        ));
+
+        let done = Arc::new(AtomicBool::new(false));
+        let readers = (0..NUM_READERS)
+            .map(|_| {
+                let (file_handler, short, long) =
+                    (file_handler.clone(), short.clone(), long.clone());
+                let done = Arc::clone(&done);
+                thread::spawn(move || {
+                    while !done.load(Ordering::SeqCst) {
+                        let contents = unwrap!(file_handler.read_file());
+                        assert!(contents == short || contents == long);
+                        // Give the writer a chance to take the exclusive lock.
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for i in 0..NUM_WRITES {
+            let contents = if i % 2 == 0 { &long } else { &short };
+            unwrap!(file_handler.write_file(contents));
+        }
+        done.store(true, Ordering::SeqCst);
+        for reader in readers {
+            unwrap!(reader.join());
+        }
+        unwrap!(cleanup(&FILE_NAME));
+    }
+
+    #[test]
+    fn new_reports_whether_file_was_created() {
+        let _cleaner = ScopedUserAppDirRemover::for_file("test5.json");
+        unwrap!(cleanup(&"test5.json"));
+
+        let file_handler: FileHandler<u64> =
+            FileHandler::new("test5.json", true).expect("failed accessing file");
+        assert!(file_handler.was_created());
+
+        let file_handler: FileHandler<u64> =
+            FileHandler::new("test5.json", true).expect("failed accessing file");
+        assert!(!file_handler.was_created());
+
+        unwrap!(cleanup(&"test5.json"));
+    }
+
+    #[test]
+    fn missing_parent_dirs_are_created() {
+        let root = env::temp_dir().join("config_file_handler_test8");
+        let dir = root.join("myorg").join("myapp");
+        let _ = fs::remove_dir_all(&root);
+
+        let (_, path, _) = create_file_in(
+            vec![(Location::UserAppDir, Ok(dir.clone()), true)],
+            OsStr::new("test8.json"),
+            b"1",
+            &Options::default(),
+            CreateMode::Overwrite,
+        )
+        .expect("failed creating file");
+        assert_eq!(path, dir.join("test8.json"));
+        assert_eq!(unwrap!(fs::read(&path)), b"1");
+
+        unwrap!(fs::remove_dir_all(&root));
+    }
+
+    #[test]
+    fn list_configs_in_location() {
+        let _cleaner = ScopedUserAppDirRemover::for_file("test9.json");
+        let _: FileHandler<u64> =
+            FileHandler::new("test9.json", true).expect("failed accessing file");
+
+        let names = list_configs(Location::CurrentBinDir).expect("failed listing configs");
+        assert!(names.contains(&OsString::from("test9.json")));
+
+        unwrap!(cleanup(&"test9.json"));
+    }
+
+    #[test]
+    fn touched_file_reads_as_default() {
+        let _cleaner = ScopedUserAppDirRemover::for_file("test10.json");
+        unwrap!(cleanup(&"test10.json"));
+
+        let file_handler: FileHandler<u64> =
+            FileHandler::touch("test10.json").expect("failed accessing file");
+        assert!(file_handler.was_created());
+        assert_eq!(unwrap!(fs::metadata(file_handler.path())).len(), 0);
+        assert_eq!(unwrap!(file_handler.read_file_or_default()), 0);
+
+        unwrap!(file_handler.write_file(&7));
+        let file_handler: FileHandler<u64> =
+            FileHandler::touch("test10.json").expect("failed accessing file");
+        assert!(!file_handler.was_created());
+        assert_eq!(unwrap!(file_handler.read_file_or_default()), 7);
+
+        unwrap!(cleanup(&"test10.json"));
+    }
+
+    #[test]
+    fn durable_and_non_durable_writes() {
+        use crate::Builder;
+
+        let _cleaner = ScopedUserAppDirRemover::for_file("test11.json");
+        for &durable in &[true, false] {
+            let file_handler = Builder::new()
+                .durable(durable)
+                .create("test11.json", true)
+                .expect("failed accessing file");
+            file_handler
+                .write_file(&vec![durable])
+                .expect("failed writing file");
+            let read_value: Vec<bool> = file_handler.read_file().expect("failed reading file");
+            assert_eq!(read_value, vec![durable]);
+        }
+
+        unwrap!(cleanup(&"test11.json"));
+    }
+
+    #[test]
+    fn thread_local_search_path() {
+        let dir = env::temp_dir().join("config_file_handler_test12");
+        unwrap!(fs::create_dir_all(&dir));
+
+        let file_handler: FileHandler<u64> = with_search_path(&dir, || {
+            FileHandler::new("test12.json", true).expect("failed accessing file")
+        });
+        assert_eq!(file_handler.path(), dir.join("test12.json"));
+
+        // The path is no longer searched once the closure has returned.
+        let file_handler: FileHandler<u64> =
+            FileHandler::new("test12.json", true).expect("failed accessing file");
+        assert_ne!(file_handler.path(), dir.join("test12.json"));
+
+        unwrap!(cleanup(&"test12.json"));
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn refresh_path_finds_higher_priority_file() {
+        let dir = env::temp_dir().join("config_file_handler_test13");
+        unwrap!(fs::create_dir_all(&dir));
+
+        let mut file_handler: FileHandler<u64> =
+            FileHandler::new("test13.json", true).expect("failed accessing file");
+        assert_eq!(file_handler.location(), Location::CurrentBinDir);
+
+        with_search_path(&dir, || {
+            unwrap!(fs::write(dir.join("test13.json"), b"1"));
+            file_handler
+                .refresh_path("test13.json")
+                .expect("failed refreshing path");
+        });
+        assert_eq!(file_handler.path(), dir.join("test13.json"));
+        assert_eq!(file_handler.location(), Location::AdditionalSearchPath);
+        assert_eq!(unwrap!(file_handler.read_file()), 1);
+
+        unwrap!(cleanup(&"test13.json"));
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn content_hash_is_over_raw_bytes() {
+        let _cleaner = ScopedUserAppDirRemover::for_file("test15.json");
+        let file_handler: FileHandler<u64> =
+            FileHandler::new("test15.json", true).expect("failed accessing file");
+
+        unwrap!(fs::write(file_handler.path(), b""));
+        assert_eq!(unwrap!(file_handler.content_hash()), 0xcbf2_9ce4_8422_2325);
+        unwrap!(fs::write(file_handler.path(), b"a"));
+        assert_eq!(file_handler.size_hint(), Some(1));
+        assert_eq!(unwrap!(file_handler.content_hash()), 0xaf63_dc4c_8601_ec8c);
+
+        unwrap!(cleanup(&"test15.json"));
+    }
+
+    #[test]
+    fn install_from_staged_file() {
+        let _cleaner = ScopedUserAppDirRemover::for_file("test4.json");
+        let file_handler = FileHandler::new("test4.json", true).expect("failed accessing file");
+        file_handler
+            .write_file(&vec![1, 2, 3])
+            .expect("failed writing file");
+
+        // A staged file which doesn't decode is rejected and left in place.
+        let staged = file_handler.path().with_extension("json.new");
+        unwrap!(fs::write(&staged, b"not json"));
+        assert!(file_handler.install_from(&staged).is_err());
+        assert!(staged.exists());
+        let read_value: Vec<i32> = file_handler.read_file().expect("failed reading file");
+        assert_eq!(read_value, vec![1, 2, 3]);
+
+        unwrap!(fs::write(&staged, b"[4, 5, 6]"));
+        file_handler
+            .install_from(&staged)
+            .expect("failed installing file");
+        assert!(!staged.exists());
+        let read_value: Vec<i32> = file_handler.read_file().expect("failed reading file");
+        assert_eq!(read_value, vec![4, 5, 6]);
+
+        unwrap!(cleanup(&"test4.json"));
+    }
+
+    #[test]
+    fn read_file_merged_fills_missing_fields() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Config {
+            port: u16,
+            peers: Vec<String>,
+        }
+
+        impl Default for Config {
+            fn default() -> Self {
+                Config {
+                    port: 5483,
+                    peers: vec!["127.0.0.1".to_string()],
+                }
+            }
+        }
+
+        let _cleaner = ScopedUserAppDirRemover::for_file("test7.json");
+        let file_handler: FileHandler<Config> =
+            FileHandler::new("test7.json", true).expect("failed accessing file");
+        unwrap!(fs::write(file_handler.path(), r#"{ "port": 1234 }"#));
+
+        assert!(file_handler.read_file().is_err());
+        let read_value = file_handler
+            .read_file_merged()
+            .expect("failed reading file");
+        assert_eq!(
+            read_value,
+            Config {
+                port: 1234,
+                ..Config::default()
+            }
+        );
+
+        unwrap!(cleanup(&"test7.json"));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_read_write_file() {
+        use crate::{Builder, Format};
+        use std::net::SocketAddr;
+
+        let _cleaner = ScopedUserAppDirRemover::for_file("test3.bin");
+        let file_handler = Builder::new()
+            .format(Format::Bincode)
+            .create("test3.bin", true)
+            .expect("failed accessing file");
+        let read_value: Vec<SocketAddr> = file_handler.read_file().expect("failed reading file");
+        assert!(read_value.is_empty());
+
+        let write_value = vec![unwrap!("127.0.0.1:5483".parse())];
+        file_handler
+            .write_file(&write_value)
+            .expect("failed writing file");
+        let read_value = file_handler.read_file().expect("failed reading file");
+        assert_eq!(read_value, write_value);
+
+        unwrap!(cleanup(&"test3.bin"));
+    }
+
+    #[cfg(feature = "blake2")]
+    #[test]
+    fn checksum_detects_reformatting() {
+        let name = "test22.json";
+        let file_handler = unwrap!(FileHandler::<Vec<u64>>::new(name, true));
+        let checksum = unwrap!(file_handler.write_file_with_checksum(&vec![1, 2]));
+        assert_eq!(checksum, unwrap!(file_handler.content_digest()));
+        assert!(unwrap!(file_handler.verify_checksum(&checksum)));
+
+        unwrap!(fs::write(file_handler.path(), b"[1,2]"));
+        assert_eq!(unwrap!(file_handler.read_file()), vec![1, 2]);
+        assert!(!unwrap!(file_handler.verify_checksum(&checksum)));
+        unwrap!(cleanup(&name));
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_read_write_file() {
+        use crate::{Builder, Format};
+        use std::collections::BTreeMap;
+
+        let _cleaner = ScopedUserAppDirRemover::for_file("test14.cbor");
+        let file_handler = Builder::new()
+            .format(Format::Cbor)
+            .create("test14.cbor", true)
+            .expect("failed accessing file");
+
+        let mut write_value = BTreeMap::new();
+        let _ = write_value.insert("peers".to_string(), vec![1u8, 2, 3]);
+        file_handler
+            .write_file(&write_value)
+            .expect("failed writing file");
+        let read_value = file_handler.read_file().expect("failed reading file");
+        assert_eq!(read_value, write_value);
+
+        unwrap!(cleanup(&"test14.cbor"));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_read_write_file() {
+        use crate::Builder;
+
+        let name = "test30.json";
+        let key = [30; 32];
+        let file_handler: FileHandler<Vec<String>> =
+            unwrap!(Builder::new().cipher(key).create(name, true));
+        assert!(unwrap!(file_handler.read_file()).is_empty());
+
+        let secrets = vec!["hunter2".to_string()];
+        unwrap!(file_handler.write_file(&secrets));
+        let raw = unwrap!(fs::read(file_handler.path()));
+        assert!(!String::from_utf8_lossy(&raw).contains("hunter2"));
+        assert_eq!(unwrap!(file_handler.read_file()), secrets);
+
+        let reopened = unwrap!(FileHandler::<Vec<String>>::with_cipher(name, false, key));
+        assert_eq!(unwrap!(reopened.read_file()), secrets);
+
+        let wrong_key = unwrap!(FileHandler::<Vec<String>>::with_cipher(
+            name, false, [0; 32]
+        ));
+        match wrong_key.read_file() {
+            Err(Error::Decryption) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        unwrap!(fs::write(file_handler.path(), b"[\"plaintext\"]"));
+        match file_handler.read_file() {
+            Err(Error::Decryption) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(cleanup(&name));
+    }
+
+    #[cfg(feature = "json5")]
+    #[test]
+    fn json5_read_write_file() {
+        use crate::{Builder, Format};
+
+        let _cleaner = ScopedUserAppDirRemover::for_file("test6.json");
+        let file_handler = Builder::new()
+            .format(Format::Json5)
+            .create("test6.json", true)
+            .expect("failed accessing file");
+
+        unwrap!(fs::write(file_handler.path(), "// Commented\n[1, 2, 3,]\n"));
+        let read_value: Vec<u32> = file_handler.read_file().expect("failed reading file");
+        assert_eq!(read_value, vec![1, 2, 3]);
+
+        // Writes are plain JSON.
+        file_handler
+            .write_file(&read_value)
+            .expect("failed writing file");
+        let written = unwrap!(fs::read_to_string(file_handler.path()));
+        assert_eq!(
+            unwrap!(serde_json::from_str::<Vec<u32>>(&written)),
+            read_value
+        );
+
+        unwrap!(cleanup(&"test6.json"));
+    }
+
+    #[test]
+    fn try_open_distinguishes_missing_files() {
+        let name = "test35.json";
+        assert!(unwrap!(FileHandler::<u64>::try_open(name, false)).is_none());
+
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        let opened = unwrap!(unwrap!(FileHandler::<u64>::try_open(name, true)));
+        assert_eq!(opened, file_handler);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn raw_config_is_copied_verbatim() {
+        use crate::raw::RawConfig;
+
+        let name = "test34.bin";
+        let file_handler = unwrap!(FileHandler::<RawConfig>::touch(name));
+        assert_eq!(unwrap!(file_handler.read_file()), RawConfig::default());
+
+        let keypair = RawConfig(vec![0, 159, 146, 150, 255]);
+        unwrap!(file_handler.write_file(&keypair));
+        assert_eq!(unwrap!(fs::read(file_handler.path())), keypair.0);
+        assert_eq!(unwrap!(file_handler.read_file()), keypair);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn open_all_returns_every_location() {
+        let dir = env::temp_dir().join("config_file_handler_test33");
+        unwrap!(fs::create_dir_all(&dir));
+        unwrap!(fs::write(dir.join("test33.json"), b"1"));
+        let file_handler = unwrap!(FileHandler::<u64>::new("test33.json", true));
+        unwrap!(file_handler.write_file(&2));
+
+        with_search_path(&dir, || {
+            let results = FileHandler::<u64>::open_all("test33.json", false);
+            let found: Vec<_> = results
+                .iter()
+                .filter_map(|result| result.as_ref().ok())
+                .map(|file_handler| (file_handler.location(), unwrap!(file_handler.read_file())))
+                .collect();
+            assert_eq!(found[0], (Location::AdditionalSearchPath, 1));
+            assert_eq!(found[1], (file_handler.location(), 2));
+            assert_eq!(results.len(), search_dirs(&Options::default()).len());
+        });
+
+        unwrap!(cleanup(&"test33.json"));
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn system_cache_can_be_disabled() {
+        let uses_system_cache = |options: &Options| {
+            let searched = search_dirs(options)
+                .iter()
+                .any(|&(location, _)| location == Location::SystemCacheDir);
+            let created = creation_dirs(options)
+                .iter()
+                .any(|&(location, _, _)| location == Location::SystemCacheDir);
+            searched || created
+        };
+
+        let mut options = Options::default();
+        assert!(uses_system_cache(&options));
+        options.disable_system_cache = true;
+        assert!(!uses_system_cache(&options));
+    }
+
+    #[test]
+    fn transaction_commit_and_conflict() {
+        let name = "test32.json";
+        let file_handler = unwrap!(FileHandler::<Vec<u64>>::new(name, true));
+
+        let mut txn = unwrap!(file_handler.begin());
+        txn.push(1);
+        unwrap!(txn.commit());
+        assert_eq!(unwrap!(file_handler.read_file()), vec![1]);
+
+        let mut discarded = unwrap!(file_handler.begin());
+        discarded.push(2);
+        drop(discarded);
+        assert_eq!(unwrap!(file_handler.read_file()), vec![1]);
+
+        let mut txn = unwrap!(file_handler.begin());
+        txn.push(3);
+        unwrap!(file_handler.write_file(&vec![4]));
+        match txn.commit() {
+            Err(Error::Conflict { path }) => assert_eq!(path, file_handler.path()),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(unwrap!(file_handler.read_file()), vec![4]);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn metadata_describes_file() {
+        let name = "test31.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(file_handler.write_file(&31));
+
+        let metadata = unwrap!(file_handler.metadata());
+        assert_eq!(metadata.path, file_handler.path());
+        assert_eq!(metadata.location, file_handler.location());
+        assert_eq!(metadata.len, 2);
+        assert_eq!(
+            metadata.modified,
+            unwrap!(unwrap!(fs::metadata(file_handler.path())).modified())
+        );
+        assert_eq!(metadata.writable, Some(true));
+
+        unwrap!(cleanup(&name));
+        assert!(file_handler.metadata().is_err());
+    }
+
+    #[test]
+    fn read_file_into_borrows_from_buffer() {
+        #[derive(Deserialize, Serialize)]
+        struct Borrowed<'a> {
+            name: &'a str,
+        }
+
+        let name = "test29.json";
+        unwrap!(FileHandler::create_new(name, &Borrowed { name: "test29" }));
+        let file_handler: FileHandler<Borrowed> = unwrap!(FileHandler::open(name, false));
+        let mut buf = b"stale".to_vec();
+        let config = unwrap!(file_handler.read_file_into(&mut buf));
+        assert_eq!(config.name, "test29");
+
+        unwrap!(fs::write(file_handler.path(), [0u8; 16]));
+        match file_handler.read_file_into(&mut Vec::new()) {
+            Err(Error::NotText { path }) => assert_eq!(path, file_handler.path()),
+            result => panic!("Unexpected result: {:?}", result.map(|config| config.name)),
+        }
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn create_new_fails_if_file_exists() {
+        let name = "test28.json";
+        let file_handler = unwrap!(FileHandler::create_new(name, &28u64));
+        assert!(file_handler.was_created());
+        assert_eq!(unwrap!(file_handler.read_file()), 28);
+
+        match FileHandler::create_new(name, &29u64) {
+            Err(Error::AlreadyExists { path }) => assert_eq!(path, file_handler.path()),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(unwrap!(file_handler.read_file()), 28);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn shared_config_reloads() {
+        use std::time::Instant;
+
+        let file_handler = unwrap!(FileHandler::<u64>::new("test27.json", true));
+        unwrap!(file_handler.write_file(&1));
+        let shared = unwrap!(SharedConfig::spawn(
+            file_handler.clone(),
+            Duration::from_millis(10)
+        ));
+        assert_eq!(*shared.get(), 1);
+
+        let wait_until = |condition: &dyn Fn() -> bool| {
+            let start = Instant::now();
+            while !condition() {
+                assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        unwrap!(file_handler.write_file(&2));
+        wait_until(&|| *shared.get() == 2);
+        assert!(shared.last_error().is_none());
+
+        unwrap!(fs::write(file_handler.path(), b"not json"));
+        wait_until(&|| shared.last_error().is_some());
+        assert_eq!(*shared.get(), 2);
+
+        unwrap!(cleanup(&"test27.json"));
+    }
+
+    #[test]
+    fn open_localized_falls_back() {
+        let base = unwrap!(FileHandler::<u64>::new("test26.json", true));
+        let french = unwrap!(FileHandler::<u64>::new("test26.fr.json", true));
+        unwrap!(base.write_file(&1));
+        unwrap!(french.write_file(&2));
+
+        let open = |locale| {
+            unwrap!(FileHandler::<u64>::open_localized(
+                "test26.json",
+                locale,
+                false
+            ))
+        };
+        assert_eq!(open("fr_CA").path(), french.path());
+        assert_eq!(open("fr").path(), french.path());
+        assert_eq!(open("de-DE").path(), base.path());
+        assert_eq!(open("").path(), base.path());
+
+        unwrap!(cleanup(&"test26.fr.json"));
+        unwrap!(cleanup(&"test26.json"));
+        assert!(FileHandler::<u64>::open_localized("test26.json", "fr", false).is_err());
+    }
+
+    #[test]
+    fn swap_exchanges_contents() {
+        let current = unwrap!(FileHandler::<u64>::new("test25.json", true));
+        let candidate = unwrap!(FileHandler::<u64>::new("test25.json.candidate", true));
+        unwrap!(current.write_file(&1));
+        unwrap!(candidate.write_file(&2));
+
+        let unrelated = current.path().with_extension("json.swap");
+        unwrap!(fs::write(&unrelated, "25"));
+
+        unwrap!(current.swap(&candidate));
+        assert_eq!(unwrap!(current.read_file()), 2);
+        assert_eq!(unwrap!(candidate.read_file()), 1);
+        unwrap!(candidate.swap(&current));
+        assert_eq!(unwrap!(current.read_file()), 1);
+        unwrap!(current.swap(&current));
+        assert_eq!(unwrap!(current.read_file()), 1);
+        assert_eq!(unwrap!(fs::read(&unrelated)), b"25");
+        unwrap!(fs::remove_file(&unrelated));
+        let dir = unwrap!(current.path().parent());
+        assert!(!unwrap!(fs::read_dir(dir))
+            .filter_map(Result::ok)
+            .any(|entry| entry.file_name().to_string_lossy().ends_with(".swap")));
+
+        unwrap!(cleanup(&"test25.json.candidate"));
+        assert!(current.swap(&candidate).is_err());
+        assert_eq!(unwrap!(current.read_file()), 1);
+        unwrap!(cleanup(&"test25.json"));
+    }
+
+    #[test]
+    fn parse_error_names_the_file() {
+        let name = "test24.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(fs::write(file_handler.path(), b"not json"));
+
+        match file_handler.read_file() {
+            Err(error @ Error::Parse { .. }) => {
+                let expected = format!("failed to parse {}: ", file_handler.path().display());
+                assert!(error.to_string().starts_with(&expected), "{}", error);
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn reset_to_default_restores_defaults() {
+        let name = "test23.json";
+        let file_handler = unwrap!(FileHandler::<Vec<u64>>::new(name, true));
+        unwrap!(file_handler.write_file(&vec![23]));
+        assert_eq!(unwrap!(file_handler.read_file()), vec![23]);
+
+        unwrap!(file_handler.reset_to_default());
+        assert!(unwrap!(file_handler.read_file()).is_empty());
+        unwrap!(cleanup(&name));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn deleted_exe_suffix_is_stripped() {
+        let path = paths::strip_deleted_suffix(PathBuf::from("/opt/app/bin/myapp (deleted)"));
+        assert_eq!(path, Path::new("/opt/app/bin/myapp"));
+        assert_eq!(unwrap!(path.file_stem()), "myapp");
+        assert_eq!(
+            paths::strip_deleted_suffix(PathBuf::from("/opt/app/bin/myapp")),
+            Path::new("/opt/app/bin/myapp")
+        );
+    }
+
+    #[test]
+    fn path_cache_can_be_cleared() {
+        let cached = unwrap!(current_bin_dir());
+        assert_eq!(unwrap!(current_bin_dir()), cached);
+        assert!(unwrap!(PATH_CACHE.lock()).contains_key(&CachedPath::CurrentExe));
+
+        clear_path_cache();
+        assert_eq!(unwrap!(current_bin_dir()), cached);
+    }
+
+    #[test]
+    fn env_vars_are_expanded() {
+        env::set_var("CONFIG_FILE_HANDLER_TEST21", "expanded");
+        assert_eq!(
+            unwrap!(expand_env_vars(
+                "$CONFIG_FILE_HANDLER_TEST21/${CONFIG_FILE_HANDLER_TEST21}.d/$/${x"
+            )),
+            OsString::from("expanded/expanded.d/$/${x")
+        );
+        match expand_env_vars("$CONFIG_FILE_HANDLER_TEST21_UNSET/x") {
+            Err(Error::UndefinedVariable { name }) => {
+                assert_eq!(name, "CONFIG_FILE_HANDLER_TEST21_UNSET")
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn is_writable_probe() {
+        let name = "test20.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(file_handler.write_file(&20));
+        assert!(file_handler.is_writable());
+        assert_eq!(unwrap!(file_handler.read_file()), 20);
+
+        unwrap!(cleanup(&name));
+        assert!(!file_handler.is_writable());
+    }
+
+    #[test]
+    fn relocate_between_dirs() {
+        let root = env::temp_dir().join("config_file_handler_test19");
+        let _ = fs::remove_dir_all(&root);
+        let (old_dir, new_dir) = (root.join("old"), root.join("new"));
+        unwrap!(fs::create_dir_all(&old_dir));
+        unwrap!(fs::create_dir_all(&new_dir));
+
+        let path = old_dir.join("test19.json");
+        unwrap!(fs::write(&path, b"19"));
+        let mut file_handler: FileHandler<u64> =
+            FileHandler::from_path(path.clone(), Location::UserAppDir, true, Options::default());
+
+        unwrap!(file_handler.relocate_to(&new_dir));
+        assert_eq!(file_handler.path(), new_dir.join("test19.json"));
+        assert!(!path.exists());
+        assert_eq!(unwrap!(file_handler.read_file()), 19);
+
+        assert!(file_handler.relocate_to(root.join("missing")).is_err());
+        assert_eq!(file_handler.path(), new_dir.join("test19.json"));
+        assert_eq!(unwrap!(file_handler.read_file()), 19);
+
+        unwrap!(fs::remove_dir_all(&root));
+    }
+
+    #[test]
+    fn write_file_with_custom_serializer() {
+        use std::collections::BTreeMap;
+
+        let name = "test18.json";
+        let file_handler = unwrap!(FileHandler::<BTreeMap<String, u64>>::new(name, true));
+        let mut contents = BTreeMap::new();
+        let _ = contents.insert("b".to_owned(), 2);
+        let _ = contents.insert("a".to_owned(), 1);
+        unwrap!(
+            file_handler.write_file_with(&contents, |contents| Ok(serde_json::to_vec(contents)?))
+        );
+
+        assert_eq!(unwrap!(fs::read(file_handler.path())), br#"{"a":1,"b":2}"#);
+        assert_eq!(unwrap!(file_handler.read_file()), contents);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn open_or_create_reports_outcome() {
+        let name = "test17.json";
+        let (file_handler, outcome) = unwrap!(FileHandler::<u64>::open_or_create(name, true));
+        assert_eq!(outcome, CreationOutcome::Created);
+        unwrap!(file_handler.write_file(&7));
+
+        let (file_handler, outcome) = unwrap!(FileHandler::<u64>::open_or_create(name, true));
+        assert_eq!(outcome, CreationOutcome::Opened);
+        assert_eq!(unwrap!(file_handler.read_file()), 7);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn config_store_implementations() {
+        use crate::store::{ConfigStore, InMemoryHandler};
+
+        fn increment<S: ConfigStore<u64>>(store: &S) -> Result<(), Error> {
+            let value = store.read()?;
+            store.write(&(value + 1))
+        }
+
+        let in_memory = InMemoryHandler::new();
+        assert!(in_memory.read().is_err());
+        let shared = in_memory.clone();
+        unwrap!(shared.write(&41));
+        unwrap!(increment(&in_memory));
+        assert_eq!(unwrap!(shared.read()), 42);
+
+        let name = "test16.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(increment(&file_handler));
+        assert_eq!(unwrap!(file_handler.read_file()), 1);
+        unwrap!(cleanup(&name));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn read_only_handler_reads_mapped_file() {
+        let name = "test37.json";
+        let file_handler = unwrap!(FileHandler::<Vec<u64>>::new(name, true));
+        unwrap!(file_handler.write_file(&vec![1, 2, 3]));
+
+        let mut read_only = unwrap!(FileHandler::<Vec<u64>>::open_read_only(name));
+        assert_eq!(read_only.path(), file_handler.path());
+        assert_eq!(unwrap!(read_only.get()), vec![1, 2, 3]);
+        assert_eq!(unwrap!(read_only.get()), vec![1, 2, 3]);
+
+        unwrap!(file_handler.write_file(&vec![4, 5, 6, 7]));
+        unwrap!(read_only.remap());
+        assert_eq!(unwrap!(read_only.get()), vec![4, 5, 6, 7]);
+
+        unwrap!(read_only.close());
+        unwrap!(file_handler.write_file(&vec![8]));
+        assert_eq!(unwrap!(read_only.get()), vec![8]);
+        drop(read_only);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn names_with_path_components_are_rejected() {
+        let names = [
+            "../../etc/passwd",
+            "..",
+            ".",
+            "",
+            "sub/test38.json",
+            "/test38.json",
+            "..\\..\\Windows\\win.ini",
+            "sub\\test38.json",
+            "C:\\test38.json",
+        ];
+        for name in &names {
+            match FileHandler::<u64>::open(name, false) {
+                Err(Error::InvalidName { .. }) => (),
+                result => panic!("Unexpected result for {:?}: {:?}", name, result),
+            }
+            match FileHandler::<u64>::new(name, true) {
+                Err(Error::InvalidName { .. }) => (),
+                result => panic!("Unexpected result for {:?}: {:?}", name, result),
+            }
+            match cleanup(name) {
+                Err(Error::InvalidName { .. }) => (),
+                result => panic!("Unexpected result for {:?}: {:?}", name, result),
+            }
+            match FileHandler::<u64>::touch(name) {
+                Err(Error::InvalidName { .. }) => (),
+                result => panic!("Unexpected result for {:?}: {:?}", name, result),
+            }
+            match FileHandler::create_new(name, &38u64) {
+                Err(Error::InvalidName { .. }) => (),
+                result => panic!("Unexpected result for {:?}: {:?}", name, result),
+            }
+            match FileHandler::<u64>::try_open(name, false) {
+                Err(Error::InvalidName { .. }) => (),
+                result => panic!("Unexpected result for {:?}: {:?}", name, result),
+            }
+            match &FileHandler::<u64>::open_all(name, false)[..] {
+                [Err(Error::InvalidName { .. })] => (),
+                results => panic!("Unexpected results for {:?}: {:?}", name, results),
+            }
+            match FileHandler::<u64>::read_file_layered(name) {
+                Err(Error::InvalidName { .. }) => (),
+                result => panic!("Unexpected result for {:?}: {:?}", name, result),
+            }
+            match FileHandler::<u64>::read_file_layered_sourced(name) {
+                Err(Error::InvalidName { .. }) => (),
+                result => panic!("Unexpected result for {:?}: {:?}", name, result),
+            }
+        }
+
+        for name in &names {
+            assert!(ConfigName::try_from(*name).is_err());
+        }
+        let name = unwrap!(ConfigName::try_from("test38.json"));
+        assert!(FileHandler::<u64>::open(&name, false).is_err());
+        unwrap!(cleanup(&name));
+
+        // A subdirectory can still be used explicitly.
+        let dir = unwrap!(current_bin_dir()).join("test38");
+        unwrap!(fs::create_dir_all(&dir));
+        unwrap!(fs::write(dir.join("test38.json"), "38"));
+        let file_handler = unwrap!(FileHandler::<u64>::open_unchecked(
+            "test38/test38.json",
+            false
+        ));
+        assert_eq!(unwrap!(file_handler.read_file()), 38);
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn read_file_layered_sourced_reports_locations() {
+        #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+        struct Config {
+            port: u16,
+            peers: Vec<String>,
+            verbose: bool,
+        }
+
+        let name = "test39.json";
+        let _cleaner = ScopedUserAppDirRemover::for_file(name);
+        let user_dir = unwrap!(user_app_dir());
+        unwrap!(fs::create_dir_all(&user_dir));
+        unwrap!(fs::write(
+            unwrap!(current_bin_dir()).join(name),
+            r#"{ "port": 1, "peers": ["a"] }"#
+        ));
+        unwrap!(fs::write(user_dir.join(name), r#"{ "port": 2 }"#));
+
+        let (config, sources) = unwrap!(FileHandler::<Config>::read_file_layered_sourced(name));
+        assert_eq!(
+            config,
+            Config {
+                port: 2,
+                peers: vec!["a".to_string()],
+                verbose: false,
+            }
+        );
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources["port"], Location::UserAppDir);
+        assert_eq!(sources["peers"], Location::CurrentBinDir);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn scoped_remover_for_file_leaves_other_files() {
+        let user_dir = unwrap!(user_app_dir());
+        unwrap!(fs::create_dir_all(&user_dir));
+        let (removed, kept) = (user_dir.join("test40.json"), user_dir.join("test40.keep"));
+        unwrap!(fs::write(&removed, "40"));
+        unwrap!(fs::write(&kept, "40"));
+
+        drop(ScopedUserAppDirRemover::for_file("test40.json"));
+        assert!(!removed.exists());
+        assert!(kept.exists());
+        unwrap!(fs::remove_file(&kept));
+    }
+
+    #[test]
+    fn open_and_new_with_base_use_only_base() {
+        let name = "test41.json";
+        let base = unwrap!(current_bin_dir()).join("test41").join("data");
+        let _ = fs::remove_dir_all(&base);
+        assert!(FileHandler::<u64>::open_with_base(&base, name, false).is_err());
+
+        let file_handler = unwrap!(FileHandler::<u64>::new_with_base(&base, name, true));
+        assert!(file_handler.was_created());
+        assert_eq!(file_handler.path(), base.join(name));
+        assert_eq!(PathBuf::from(&file_handler), base.join(name));
+        assert!(unwrap!(fs::metadata(&file_handler)).is_file());
+        assert_eq!(file_handler.location(), Location::Explicit);
+        unwrap!(file_handler.write_file(&41));
+        assert!(FileHandler::<u64>::open(name, false).is_err());
+
+        let reopened = unwrap!(FileHandler::<u64>::open_with_base(&base, name, true));
+        assert_eq!(unwrap!(reopened.read_file()), 41);
+        let existing = unwrap!(FileHandler::<u64>::new_with_base(&base, name, true));
+        assert!(!existing.was_created());
+        match FileHandler::<u64>::open_with_base(&base, "../data/test41.json", false) {
+            Err(Error::InvalidName { .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        match FileHandler::<u64>::new_with_base(&base, "../test41.json", true) {
+            Err(Error::InvalidName { .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(!unwrap!(base.parent()).join(name).exists());
+        unwrap!(fs::remove_dir_all(unwrap!(base.parent())));
+    }
+
+    #[test]
+    fn written_json_uses_configured_indent() {
+        use crate::Builder;
+
+        let name = "test42.json";
+        let file_handler: FileHandler<Vec<u64>> = unwrap!(Builder::new().create(name, true));
+        unwrap!(file_handler.write_file(&vec![42]));
+        assert_eq!(
+            unwrap!(fs::read_to_string(file_handler.path())),
+            "[\n  42\n]"
+        );
+
+        let file_handler: FileHandler<Vec<u64>> =
+            unwrap!(Builder::new().indent(4).open(name, true));
+        unwrap!(file_handler.write_file(&vec![42]));
+        assert_eq!(
+            unwrap!(fs::read_to_string(file_handler.path())),
+            "[\n    42\n]"
+        );
+
+        let file_handler: FileHandler<Vec<u64>> =
+            unwrap!(Builder::new().indent_with(b"\t").open(name, true));
+        unwrap!(file_handler.write_file(&vec![42]));
+        assert_eq!(
+            unwrap!(fs::read_to_string(file_handler.path())),
+            "[\n\t42\n]"
+        );
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn open_or_embedded_writes_embedded_config() {
+        const EMBEDDED: &[u8] = b"[\"peer.example.net\"]";
+
+        let name = "test43.json";
+        let _cleaner = ScopedUserAppDirRemover::for_file(name);
+        unwrap!(cleanup(&name));
+        match FileHandler::<Vec<String>>::open_or_embedded(name, true, b"not json") {
+            Err(Error::Parse { .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(FileHandler::<Vec<String>>::open(name, false).is_err());
+
+        let file_handler = unwrap!(FileHandler::<Vec<String>>::open_or_embedded(
+            name, true, EMBEDDED
+        ));
+        assert!(file_handler.was_created());
+        assert_eq!(unwrap!(fs::read(file_handler.path())), EMBEDDED);
+        unwrap!(file_handler.write_file(&vec![]));
+
+        let reopened = unwrap!(FileHandler::<Vec<String>>::open_or_embedded(
+            name, true, EMBEDDED
+        ));
+        assert!(!reopened.was_created());
+        assert!(unwrap!(reopened.read_file()).is_empty());
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn rename_config_round_trip() {
+        let (old_name, new_name) = ("test44.json", "test44.renamed.json");
+        let mut file_handler = unwrap!(FileHandler::<u64>::new(old_name, true));
+        unwrap!(file_handler.write_file(&44));
+        let old_path = file_handler.path().to_path_buf();
+
+        unwrap!(file_handler.rename_config(new_name, false));
+        assert!(!old_path.exists());
+        assert_eq!(file_handler.path(), old_path.with_file_name(new_name));
+        assert_eq!(unwrap!(file_handler.read_file()), 44);
+
+        let other = unwrap!(FileHandler::<u64>::new(old_name, true));
+        match file_handler.rename_config(old_name, false) {
+            Err(Error::AlreadyExists { ref path }) if path == other.path() => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(file_handler.rename_config(old_name, true));
+        assert_eq!(unwrap!(other.read_file()), 44);
+        assert!(!old_path.with_file_name(new_name).exists());
+        unwrap!(cleanup(&old_name));
+    }
+
+    #[cfg(feature = "detailed_errors")]
+    #[test]
+    fn parse_errors_include_field_path() {
+        #[derive(Debug, Default, Deserialize, Serialize)]
+        struct Peer {
+            port: u16,
+        }
+
+        #[derive(Debug, Default, Deserialize, Serialize)]
+        struct Network {
+            peers: Vec<Peer>,
+        }
+
+        #[derive(Debug, Default, Deserialize, Serialize)]
+        struct Config {
+            network: Network,
+        }
+
+        let name = "test45.json";
+        let file_handler = unwrap!(FileHandler::<Config>::new(name, true));
+        unwrap!(fs::write(
+            file_handler.path(),
+            r#"{ "network": { "peers": [{ "port": 1 }, { "port": "x" }] } }"#
+        ));
+        match file_handler.read_file() {
+            Err(Error::Parse { err, .. }) => match *err {
+                Error::JsonAt { ref path, .. } => assert_eq!(path, "network.peers[1].port"),
+                ref err => panic!("Unexpected error: {:?}", err),
+            },
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn handler_from_inherited_file() {
+        let path = unwrap!(current_bin_dir()).join("test46.json");
+        unwrap!(fs::write(&path, "[1, 2, 3]"));
+        let file = unwrap!(OpenOptions::new().read(true).write(true).open(&path));
+
+        let mut file_handler = FileHandler::<Vec<u64>>::from_file(file);
+        assert_eq!(file_handler.location(), Location::Explicit);
+        assert_eq!(unwrap!(file_handler.read_file()), vec![1, 2, 3]);
+        unwrap!(file_handler.write_file(&vec![4]));
+        assert_eq!(unwrap!(file_handler.clone().read_file()), vec![4]);
+        assert_eq!(
+            unwrap!(serde_json::from_slice::<Vec<u64>>(&unwrap!(fs::read(
+                &path
+            )))),
+            vec![4]
+        );
+        assert_eq!(
+            file_handler.size_hint(),
+            Some(unwrap!(fs::read(&path)).len())
+        );
+        let mut txn = unwrap!(file_handler.begin());
+        txn.push(5);
+        unwrap!(txn.commit());
+        assert_eq!(unwrap!(file_handler.read_file()), vec![4, 5]);
+        #[cfg(unix)]
+        assert_eq!(unwrap!(file_handler.metadata()).writable, Some(true));
+        unwrap!(file_handler.close());
+        assert!(file_handler.read_file().is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::IntoRawFd;
+
+            let fd = unwrap!(File::open(&path)).into_raw_fd();
+            #[allow(unsafe_code)]
+            let file_handler = unsafe { FileHandler::<Vec<u64>>::from_raw_fd(fd) };
+            assert_eq!(unwrap!(file_handler.read_file()), vec![4, 5]);
+            assert_eq!(unwrap!(file_handler.metadata()).writable, Some(false));
+        }
+        unwrap!(fs::remove_file(&path));
+    }
+
+    #[test]
+    fn compare_and_swap_only_writes_expected_value() {
+        let name = "test47.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(file_handler.write_file(&1));
+
+        assert!(unwrap!(file_handler.compare_and_swap(&1, &2)));
+        assert_eq!(unwrap!(file_handler.read_file()), 2);
+        assert!(!unwrap!(file_handler.compare_and_swap(&1, &3)));
+        assert_eq!(unwrap!(file_handler.read_file()), 2);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn bundle_resource_dir_beside_binary() {
+        let resources = unwrap!(current_bin_dir()).join("resources");
+        unwrap!(fs::create_dir_all(&resources));
+        let found = bundle_resource_dir();
+        unwrap!(fs::remove_dir(&resources));
+        assert_eq!(unwrap!(found), resources);
+    }
+
+    #[test]
+    fn diff_lists_changed_pointers() {
+        let name = "test48.json";
+        let file_handler = unwrap!(FileHandler::<Value>::new(name, true));
+        let on_disk = serde_json::json!({ "port": 1, "peers": ["a", "b"], "a/b": { "x": 1 } });
+        unwrap!(file_handler.write_file(&on_disk));
+        assert!(unwrap!(file_handler.diff(&on_disk)).is_empty());
+
+        let current = serde_json::json!({ "port": 2, "peers": ["a"], "a/b": { "x": 1, "y": 2 } });
+        assert_eq!(
+            unwrap!(file_handler.diff(&current)),
+            vec!["/a~1b/y", "/peers/1", "/port"]
+        );
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn batch_holds_global_mutex() {
+        let name = "test49.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        let written = batch(|| {
+            assert!(global_mutex::get_mutex().try_lock().is_err());
+            unwrap!(file_handler.write_file(&49));
+            batch(|| unwrap!(file_handler.write_file(&50)));
+            unwrap!(file_handler.read_file())
+        });
+        assert_eq!(written, 50);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn error_sources_can_be_walked() {
+        use std::error::Error as StdError;
+
+        let err = Error::from(io::Error::new(io::ErrorKind::NotFound, "test"));
+        let source = unwrap!(err.source());
+        assert_eq!(
+            unwrap!(source.downcast_ref::<io::Error>()).kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let name = "test50.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(fs::write(file_handler.path(), "not json"));
+        let err = unwrap!(file_handler.read_file().err());
+        let parser_error = unwrap!(err.source());
+        assert!(unwrap!(parser_error.source()).is::<serde_json::Error>());
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn canonical_writes_sort_keys() {
+        use crate::Builder;
+        use std::collections::HashMap;
+
+        let name = "test51.json";
+        let file_handler: FileHandler<HashMap<String, HashMap<String, u8>>> =
+            unwrap!(Builder::new().indent(4).create(name, true));
+        let mut contents = HashMap::new();
+        for i in 0..20u8 {
+            let mut inner = HashMap::new();
+            let _ = inner.insert(format!("n{}", i), i);
+            let _ = contents.insert(format!("k{:02}", i), inner);
+        }
+        unwrap!(file_handler.write_file_canonical(&contents));
+
+        let written = unwrap!(fs::read_to_string(file_handler.path()));
+        assert!(written.starts_with("{\n    \"k00\": {\n        \"n0\": 0\n    },\n    \"k01\""));
+        let positions = (0..20u8)
+            .map(|i| unwrap!(written.find(&format!("\"k{:02}\"", i))))
+            .collect::<Vec<_>>();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(unwrap!(file_handler.read_file()), contents);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn open_newest_prefers_latest_copy() {
+        let name = "test52.json";
+        let _cleaner = ScopedUserAppDirRemover::for_file(name);
+        let user_dir = unwrap!(user_app_dir());
+        unwrap!(fs::create_dir_all(&user_dir));
+        let (bin_copy, user_copy) = (unwrap!(current_bin_dir()).join(name), user_dir.join(name));
+        unwrap!(fs::write(&bin_copy, "1"));
+        unwrap!(fs::write(&user_copy, "2"));
+
+        let now = SystemTime::now();
+        unwrap!(unwrap!(File::options().write(true).open(&bin_copy)).set_modified(now));
+        unwrap!(unwrap!(File::options().write(true).open(&user_copy))
+            .set_modified(now + Duration::from_secs(10)));
+        let newest = unwrap!(FileHandler::<u64>::open_newest(name, false));
+        assert_eq!(newest.location(), Location::UserAppDir);
+        assert_eq!(unwrap!(newest.read_file()), 2);
+
+        // Ties go to the usual search order.
+        unwrap!(unwrap!(File::options().write(true).open(&user_copy)).set_modified(now));
+        let newest = unwrap!(FileHandler::<u64>::open_newest(name, false));
+        assert_eq!(newest.location(), Location::CurrentBinDir);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn write_file_all_updates_existing_copies() {
+        let name = "test53.json";
+        let _cleaner = ScopedUserAppDirRemover::for_file(name);
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        assert_eq!(file_handler.location(), Location::CurrentBinDir);
+        let user_copy = unwrap!(user_app_dir()).join(name);
+        unwrap!(fs::create_dir_all(unwrap!(user_copy.parent())));
+        unwrap!(fs::write(&user_copy, "0"));
+
+        unwrap!(file_handler.write_file_all(&53));
+        assert_eq!(unwrap!(file_handler.read_file()), 53);
+        assert_eq!(unwrap!(fs::read_to_string(&user_copy)), "53");
+        assert!(!unwrap!(system_cache_dir()).join(name).exists());
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn lock_exclusive_guard_holds_lock_across_read_and_write() {
+        let name = "test54.json";
+        let _cleaner = ScopedUserAppDirRemover::for_file(name);
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(file_handler.write_file(&54));
+
+        let other = file_handler.clone();
+        let mut guard = unwrap!(file_handler.lock_exclusive_guard());
+        let writer = thread::spawn(move || unwrap!(other.write_file(&1)));
+        thread::sleep(Duration::from_millis(100));
+        let value = unwrap!(guard.read());
+        unwrap!(guard.write(&(value * 100)));
+        // Shorter than the original contents, so the file must be truncated.
+        unwrap!(guard.write(&(value + 1)));
+        assert_eq!(unwrap!(guard.read()), 55);
+        drop(guard);
+
+        unwrap!(writer.join());
+        assert_eq!(unwrap!(file_handler.read_file()), 1);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn files_larger_than_max_size_are_rejected() {
+        use crate::Builder;
+
+        let name = "test55.json";
+        let file_handler: FileHandler<u64> = unwrap!(Builder::new().max_size(4).create(name, true));
+        unwrap!(file_handler.write_file(&55));
+        assert_eq!(unwrap!(file_handler.read_file()), 55);
+
+        unwrap!(file_handler.write_file(&123_456));
+        match file_handler.read_file() {
+            Err(Error::TooLarge { size: 6, limit: 4 }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        match file_handler.read_file_into(&mut Vec::new()) {
+            Err(Error::TooLarge { limit: 4, .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn watch_debounced_waits_for_the_file_to_settle() {
+        let name = "test56.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        let window = Duration::from_millis(200);
+        let receiver = unwrap!(file_handler.watch_debounced(window));
+
+        // An editor truncating the file before writing it shouldn't be reported.
+        unwrap!(fs::write(file_handler.path(), ""));
+        thread::sleep(window * 2);
+        unwrap!(fs::write(file_handler.path(), "5"));
+        thread::sleep(window / 4);
+        unwrap!(fs::write(file_handler.path(), "56"));
+        assert_eq!(unwrap!(unwrap!(receiver.recv_timeout(window * 10))), 56);
+        assert!(receiver.recv_timeout(window * 2).is_err());
+
+        unwrap!(fs::write(file_handler.path(), "not json"));
+        match receiver.recv_timeout(window * 10) {
+            Ok(Err(Error::Parse { .. })) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn env_variables_override_fields() {
+        #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+        struct Network {
+            port: u16,
+            max_peers: u32,
+        }
+
+        #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+        struct Config {
+            name: String,
+            network: Network,
+        }
+
+        let name = "test57.json";
+        let file_handler = unwrap!(FileHandler::<Config>::new(name, true));
+        env::set_var("TEST57_NAME", "overridden");
+        env::set_var("TEST57_NETWORK_PORT", "9000");
+        env::set_var("TEST57_NETWORK_MAX_PEERS", "57");
+        let config = unwrap!(file_handler.read_file_with_env_overrides("TEST57"));
+        env::remove_var("TEST57_NAME");
+        env::remove_var("TEST57_NETWORK_PORT");
+        env::remove_var("TEST57_NETWORK_MAX_PEERS");
+
+        let expected = Config {
+            name: "overridden".to_string(),
+            network: Network {
+                port: 9000,
+                max_peers: 57,
+            },
+        };
+        assert_eq!(config, expected);
+        assert_eq!(unwrap!(file_handler.read_file()), Config::default());
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn in_memory_handler() {
+        let file_handler = unwrap!(FileHandler::<u64>::new_in_memory_default());
+        assert_eq!(file_handler.path(), Path::new(""));
+        assert_eq!(unwrap!(file_handler.read_file()), 0);
+        assert_eq!(unwrap!(file_handler.read_file_or_default()), 0);
+
+        let clone = file_handler.clone();
+        assert_eq!(clone, file_handler);
+        unwrap!(clone.write_file(&58));
+        assert_eq!(unwrap!(file_handler.read_file()), 58);
+        assert_eq!(unwrap!(file_handler.read_file_nolock()), 58);
+        assert_eq!(unwrap!(file_handler.content_hash()), fnv1a(b"58"));
+        assert_eq!(file_handler.size_hint(), Some(2));
+        let mut buf = Vec::new();
+        assert_eq!(unwrap!(file_handler.read_file_into(&mut buf)), 58);
+        assert_eq!(unwrap!(file_handler.read_file_merged()), 58);
+        assert_eq!(unwrap!(file_handler.read_file_tolerant()), (58, Vec::new()));
+        assert_eq!(
+            unwrap!(file_handler.read_file_with_env_overrides("TEST58")),
+            58
+        );
+        assert_ne!(
+            unwrap!(FileHandler::<u64>::new_in_memory_default()),
+            file_handler
+        );
+
+        assert!(!unwrap!(file_handler.compare_and_swap(&0, &1)));
+        assert!(unwrap!(file_handler.compare_and_swap(&58, &59)));
+        assert!(!unwrap!(file_handler.write_file_if_changed(&59)));
+        assert!(unwrap!(file_handler.write_file_if_changed(&60)));
+        unwrap!(file_handler.write_file_all(&61));
+        let mut txn = unwrap!(file_handler.begin());
+        *txn += 1;
+        unwrap!(txn.commit());
+        assert_eq!(unwrap!(clone.read_file()), 62);
+
+        let array = unwrap!(FileHandler::<Vec<u64>>::new_in_memory_default());
+        unwrap!(array.append_to_array(&58));
+        unwrap!(array.append_to_array(&59));
+        assert_eq!(unwrap!(array.read_file()), [58, 59]);
+    }
+
+    #[test]
+    fn byte_order_marks_are_handled() {
+        let name = "test59.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+
+        unwrap!(fs::write(file_handler.path(), b"\xef\xbb\xbf59"));
+        assert_eq!(unwrap!(file_handler.read_file()), 59);
+        assert_eq!(unwrap!(file_handler.read_file_into(&mut Vec::new())), 59);
+
+        unwrap!(fs::write(file_handler.path(), b"\xff\xfe5\x009\x00"));
+        assert_eq!(unwrap!(file_handler.read_file()), 59);
+        unwrap!(fs::write(file_handler.path(), b"\xfe\xff\x005\x009"));
+        assert_eq!(unwrap!(file_handler.read_file()), 59);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn clock_can_be_frozen() {
+        use crate::clock::{self, Clock};
+
+        struct FixedClock(SystemTime);
+
+        impl Clock for FixedClock {
+            fn now(&self) -> SystemTime {
+                self.0
+            }
+        }
+
+        let frozen = SystemTime::UNIX_EPOCH + Duration::from_secs(60);
+        clock::set_clock_for_testing(FixedClock(frozen));
+        assert_eq!(clock::now(), frozen);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(clock::now(), frozen);
+        clock::reset_clock();
+        assert!(clock::now() > frozen);
+    }
+
+    #[test]
+    fn verify_reports_parse_errors() {
+        let name = "test60.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(file_handler.verify());
+
+        unwrap!(fs::write(file_handler.path(), "\"sixty\""));
+        match file_handler.verify() {
+            Err(Error::Parse { .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn append_to_array() {
+        let name = "test61.json";
+        let file_handler = unwrap!(FileHandler::<Vec<u64>>::new(name, true));
+        unwrap!(file_handler.append_to_array(&6));
+        unwrap!(file_handler.append_to_array(&1));
+        assert_eq!(unwrap!(file_handler.read_file()), vec![6, 1]);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn open_first_of_prefers_earlier_names() {
+        let (current, legacy) = ("test62.json", "test62_legacy.json");
+        match FileHandler::<u64>::open_first_of(&[current, legacy], false) {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let legacy_handler = unwrap!(FileHandler::<u64>::new(legacy, true));
+        let opened = unwrap!(FileHandler::<u64>::open_first_of(&[current, legacy], false));
+        assert_eq!(opened, legacy_handler);
+
+        let current_handler = unwrap!(FileHandler::<u64>::new(current, true));
+        let opened = unwrap!(FileHandler::<u64>::open_first_of(&[current, legacy], false));
+        assert_eq!(opened, current_handler);
+        unwrap!(cleanup(&current));
+        unwrap!(cleanup(&legacy));
+    }
+
+    #[test]
+    fn open_lists_the_attempted_paths() {
+        let name = "test63.json";
+        match FileHandler::<u64>::open(name, false) {
+            Err(Error::NotFoundAnywhere { ref attempts }) => {
+                assert!(!attempts.is_empty());
+                assert_eq!(attempts[0].0, unwrap!(current_bin_dir()).join(name));
+                assert!(attempts.iter().all(
+                    |&(ref path, kind)| path.ends_with(name) && kind == io::ErrorKind::NotFound
+                ));
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn dropins_are_merged_in_order() {
+        #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+        struct Config {
+            name: String,
+            port: u16,
+            peers: u32,
+        }
+
+        let name = "test64.json";
+        let dropins_dir = unwrap!(current_bin_dir()).join("test64.json.d");
+        unwrap!(fs::create_dir_all(&dropins_dir));
+        unwrap!(fs::write(
+            dropins_dir.join("20-port.json"),
+            r#"{"port": 2}"#
+        ));
+        unwrap!(fs::write(
+            dropins_dir.join("10-port.json"),
+            r#"{"port": 1, "peers": 64}"#
+        ));
+        unwrap!(fs::write(dropins_dir.join("README"), "not json"));
+
+        // Without the base file.
+        let expected = Config {
+            name: String::new(),
+            port: 2,
+            peers: 64,
+        };
+        assert_eq!(
+            unwrap!(FileHandler::<Config>::read_file_with_dropins(name)),
+            expected
+        );
+
+        let file_handler = unwrap!(FileHandler::<Config>::new(name, true));
+        unwrap!(fs::write(
+            file_handler.path(),
+            r#"{"name": "base", "port": 0}"#
+        ));
+        let expected = Config {
+            name: "base".to_string(),
+            ..expected
+        };
+        assert_eq!(
+            unwrap!(FileHandler::<Config>::read_file_with_dropins(name)),
+            expected
+        );
+
+        unwrap!(fs::remove_dir_all(&dropins_dir));
+        unwrap!(cleanup(&name));
+    }
+
+    // Other platforms' filesystems are typically case-insensitive already.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn case_insensitive_open() {
+        use crate::Builder;
+
+        let path = unwrap!(current_bin_dir()).join("Test65.json");
+        unwrap!(fs::write(&path, "65"));
+        assert!(FileHandler::<u64>::open("test65.json", false).is_err());
+
+        let file_handler: FileHandler<u64> = unwrap!(Builder::new()
+            .case_insensitive(true)
+            .open("test65.json", false));
+        assert_eq!(file_handler.path(), path);
+        assert_eq!(unwrap!(file_handler.read_file()), 65);
+        unwrap!(fs::remove_file(&path));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn export_json_converts_from_the_native_format() {
+        use crate::{Builder, Format};
+
+        let name = "test66.bin";
+        let file_handler: FileHandler<Vec<u64>> =
+            unwrap!(Builder::new().format(Format::Bincode).create(name, true));
+        unwrap!(file_handler.write_file(&vec![6, 6]));
+        assert_eq!(unwrap!(file_handler.export_json()), "[\n  6,\n  6\n]");
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn prewarm_reports_every_failure() {
+        let names = ["test67a.json", "test67b.json", "test67c.json"];
+        let file_handler = unwrap!(FileHandler::<u64>::new(names[1], true));
+
+        match FileHandler::<u64>::prewarm(&names) {
+            Err(ref failures) => {
+                let failed = failures.iter().map(|f| f.0.clone()).collect::<Vec<_>>();
+                assert_eq!(failed, [names[0], names[2]]);
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let file_handlers = unwrap!(FileHandler::<u64>::prewarm(&names[1..2]));
+        assert_eq!(file_handlers, [file_handler]);
+        unwrap!(cleanup(&names[1]));
+    }
+
+    #[test]
+    fn durable_creation_in_a_new_directory() {
+        let base = env::temp_dir().join("config_file_handler_test68");
+        let _ = fs::remove_dir_all(&base);
+        let mut file_handler = unwrap!(FileHandler::<u64>::new_with_base(
+            &base,
+            "test68.json",
+            true
+        ));
+        assert!(file_handler.was_created());
+        assert_eq!(file_handler.path(), base.join("test68.json"));
+        assert_eq!(unwrap!(file_handler.read_file()), 0);
+
+        unwrap!(file_handler.rename_config("test68_renamed.json", false));
+        assert_eq!(unwrap!(file_handler.read_file()), 0);
+        unwrap!(fs::remove_dir_all(&base));
+    }
+
+    #[test]
+    fn open_resilient_recovers_from_corrupt_files() {
+        let name = "test69.json";
+        let (file_handler, recovery) = unwrap!(FileHandler::<u64>::open_resilient(name, true));
+        assert_eq!(recovery, Recovery::Created);
+        unwrap!(file_handler.write_file(&69));
+        let (_, recovery) = unwrap!(FileHandler::<u64>::open_resilient(name, true));
+        assert_eq!(recovery, Recovery::Opened);
+
+        unwrap!(fs::write(file_handler.path(), "{ truncated"));
+        let (file_handler, recovery) = unwrap!(FileHandler::<u64>::open_resilient(name, true));
+        let backup = match recovery {
+            Recovery::RecoveredFromCorrupt { backup } => backup,
+            recovery => panic!("Unexpected recovery: {:?}", recovery),
+        };
+        let backup_name = unwrap!(unwrap!(backup.file_name()).to_str());
+        assert!(backup_name.starts_with("test69.json.corrupt."));
+        assert!(unwrap!(backup_name["test69.json.corrupt.".len()..].parse::<u64>()) > 0);
+        assert_eq!(unwrap!(fs::read_to_string(&backup)), "{ truncated");
+        assert_eq!(unwrap!(file_handler.read_file()), 0);
+        unwrap!(fs::remove_file(&backup));
+
+        // A crash can also leave the file filled with NUL bytes, which isn't text.
+        unwrap!(fs::write(file_handler.path(), [0u8; 16]));
+        let (file_handler, recovery) = unwrap!(FileHandler::<u64>::open_resilient(name, true));
+        let backup = match recovery {
+            Recovery::RecoveredFromCorrupt { backup } => backup,
+            recovery => panic!("Unexpected recovery: {:?}", recovery),
+        };
+        assert_eq!(unwrap!(fs::read(&backup)), [0u8; 16]);
+        assert_eq!(unwrap!(file_handler.read_file()), 0);
+        unwrap!(fs::remove_file(&backup));
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let options = Options {
+            retry_attempts: 3,
+            retry_backoff: Duration::from_millis(1),
+            ..Options::default()
+        };
+
+        let mut calls = 0;
+        let result = with_retry(&options, || {
+            calls += 1;
+            if calls < 3 {
+                Err(io::Error::from(io::ErrorKind::Interrupted))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(unwrap!(result), 3);
+
+        #[cfg(unix)]
+        {
+            calls = 0;
+            let result: Result<(), _> = with_retry(&options, || {
+                calls += 1;
+                Err(io::Error::from_raw_os_error(5))
+            });
+            assert!(result.is_err());
+            assert_eq!(calls, 4);
+        }
+
+        calls = 0;
+        let result: Result<(), _> = with_retry(&options, || {
+            calls += 1;
+            Err(Error::Io(io::Error::from(io::ErrorKind::NotFound)))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn open_or_wait_blocks_until_file_appears() {
+        let dir = env::temp_dir().join("config_file_handler_test70");
+        unwrap!(fs::create_dir_all(&dir));
+        let path = dir.join("test70.json");
+        let _ = fs::remove_file(&path);
+
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                unwrap!(fs::write(path, "70"));
+            })
+        };
+        let file_handler: FileHandler<u64> = with_search_path(&dir, || {
+            unwrap!(FileHandler::open_or_wait(
+                "test70.json",
+                false,
+                Duration::from_secs(10)
+            ))
+        });
+        unwrap!(writer.join());
+        assert_eq!(file_handler.path(), path);
+        assert_eq!(unwrap!(file_handler.read_file()), 70);
+
+        match FileHandler::<u64>::open_or_wait("test70_missing.json", false, Duration::ZERO) {
+            Err(Error::Timeout { name, .. }) => assert_eq!(name, Path::new("test70_missing.json")),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn written_json_ends_with_a_newline() {
+        use crate::Builder;
+
+        let name = "test71.json";
+        let file_handler: FileHandler<Vec<u64>> =
+            unwrap!(Builder::new().trailing_newline(true).create(name, true));
+        assert_eq!(unwrap!(fs::read_to_string(file_handler.path())), "[]\n");
+        for _ in 0..2 {
+            unwrap!(file_handler.write_file(&vec![71]));
+        }
+        let contents = unwrap!(fs::read_to_string(file_handler.path()));
+        assert!(contents.ends_with("]\n"));
+        assert!(!contents.ends_with("\n\n"));
+        assert_eq!(unwrap!(file_handler.read_file()), vec![71]);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn map_reinterprets_the_same_file() {
+        let name = "test72.json";
+        let file_handler: FileHandler<Vec<u64>> = unwrap!(FileHandler::new(name, true));
+        unwrap!(file_handler.write_file(&vec![7, 2]));
+        let path = file_handler.path().to_path_buf();
+
+        let value_handler: FileHandler<Value> = file_handler.map();
+        assert_eq!(value_handler.path(), path);
+        assert_eq!(
+            unwrap!(value_handler.read_file()),
+            serde_json::json!([7, 2])
+        );
+        let file_handler: FileHandler<Vec<u64>> = value_handler.map();
+        assert_eq!(unwrap!(file_handler.read_file()), vec![7, 2]);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn read_only_locations_are_skipped() {
+        let dir = env::temp_dir().join("config_file_handler_test73");
+        unwrap!(fs::create_dir_all(&dir));
+        let name = "test73.json";
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().push(dir.clone()));
+
+        let file_handler: FileHandler<u64> =
+            with_search_path(&dir, || unwrap!(FileHandler::new(name, true)));
+        assert!(file_handler.was_created());
+        assert_ne!(file_handler.location(), Location::AdditionalSearchPath);
+        assert!(!dir.join(name).exists());
+        unwrap!(cleanup(&name));
+
+        // With every location read-only, each attempt is reported with `ReadOnlyFilesystem`.
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().push(PathBuf::from("/")));
+        let attempts = match with_search_path(&dir, || FileHandler::<u64>::new(name, true)) {
+            Err(Error::NoWritableLocation { attempts }) => attempts,
+            result => panic!("Unexpected result: {:?}", result),
+        };
+        assert!(!attempts.is_empty());
+        assert!(attempts
+            .iter()
+            .all(|&(_, kind)| kind == io::ErrorKind::ReadOnlyFilesystem));
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().clear());
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn promote_to_writable_copies_read_only_file() {
+        let dir = env::temp_dir().join("config_file_handler_test74");
+        unwrap!(fs::create_dir_all(&dir));
+        let name = "test74.json";
+        unwrap!(fs::write(dir.join(name), "74"));
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().push(dir.clone()));
+
+        let mut file_handler: FileHandler<u64> =
+            with_search_path(&dir, || unwrap!(FileHandler::open(name, false)));
+        assert_eq!(file_handler.path(), dir.join(name));
+        assert!(!file_handler.is_writable());
+        assert!(with_search_path(&dir, || unwrap!(
+            file_handler.promote_to_writable()
+        )));
+        assert_ne!(file_handler.path(), dir.join(name));
+        assert!(file_handler.is_writable());
+        assert_eq!(unwrap!(file_handler.read_file()), 74);
+        unwrap!(file_handler.write_file(&75));
+        assert_eq!(unwrap!(fs::read_to_string(dir.join(name))), "74");
+        assert!(!unwrap!(file_handler.promote_to_writable()));
+
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().clear());
+        unwrap!(cleanup(&name));
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn write_file_if_changed_skips_identical_contents() {
+        let name = "test75.json";
+        let file_handler: FileHandler<u64> = unwrap!(FileHandler::new(name, true));
+        assert!(!unwrap!(file_handler.write_file_if_changed(&0)));
+        assert!(unwrap!(file_handler.write_file_if_changed(&75)));
+        assert_eq!(unwrap!(file_handler.read_file()), 75);
+
+        let modified = unwrap!(unwrap!(fs::metadata(file_handler.path())).modified());
+        thread::sleep(Duration::from_millis(20));
+        assert!(!unwrap!(file_handler.write_file_if_changed(&75)));
+        assert_eq!(
+            unwrap!(unwrap!(fs::metadata(file_handler.path())).modified()),
+            modified
+        );
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn open_layers_writes_to_the_user_layer() {
+        #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+        #[serde(default)]
+        struct Layered {
+            colour: String,
+            size: u64,
+        }
+
+        let name = "test76.json";
+        let defaults_path = unwrap!(current_bin_dir()).join(name);
+        unwrap!(fs::write(
+            &defaults_path,
+            r#"{ "colour": "red", "size": 1 }"#
+        ));
+
+        let mut layers = unwrap!(FileHandler::<Layered>::open_layers(name, true));
+        assert_eq!(unwrap!(layers.defaults.as_ref()).path(), defaults_path);
+        assert!(layers.user.is_none());
+        assert_eq!(unwrap!(layers.read()).colour, "red");
+
+        unwrap!(layers.write(&Layered {
+            colour: "blue".to_string(),
+            size: 1,
+        }));
+        let user = unwrap!(layers.user.as_ref());
+        assert_eq!(user.location(), Location::UserAppDir);
+        assert!(user.was_created());
+        assert_eq!(unwrap!(layers.read()).colour, "blue");
+        assert!(unwrap!(fs::read_to_string(&defaults_path)).contains("red"));
+
+        let layers = unwrap!(FileHandler::<Layered>::open_layers(name, true));
+        assert_eq!(
+            unwrap!(layers.user.as_ref()).location(),
+            Location::UserAppDir
+        );
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn binary_files_are_not_parsed_as_json() {
+        use crate::raw::RawConfig;
+
+        let name = "test77.json";
+        let file_handler: FileHandler<u64> = unwrap!(FileHandler::new(name, true));
+        unwrap!(fs::write(
+            file_handler.path(),
+            b"\x7fELF\x02\x01\x01\x00\x00"
+        ));
+        match file_handler.read_file() {
+            Err(Error::NotText { path }) => assert_eq!(path, file_handler.path()),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        match file_handler.read_file_nolock() {
+            Err(Error::NotText { .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        // Raw configs are read verbatim, whatever their contents.
+        let raw_handler: FileHandler<RawConfig> = file_handler.map();
+        assert_eq!(unwrap!(raw_handler.read_file()).0[..4], b"\x7fELF"[..]);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn default_creation_path_predicts_new() {
+        let dir = env::temp_dir().join("config_file_handler_test78");
+        unwrap!(fs::create_dir_all(&dir));
+        let name = "test78.json";
+
+        let predicted = with_search_path(&dir, || {
+            unwrap!(FileHandler::<u64>::default_creation_path(name))
+        });
+        assert_eq!(predicted, dir.join(name));
+        assert!(!predicted.exists());
+
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().push(dir.clone()));
+        let (predicted, file_handler) = with_search_path(&dir, || {
+            (
+                unwrap!(FileHandler::<u64>::default_creation_path(name)),
+                unwrap!(FileHandler::<u64>::new(name, true)),
+            )
+        });
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().clear());
+        assert_ne!(predicted, dir.join(name));
+        assert_eq!(predicted, file_handler.path());
+        unwrap!(cleanup(&name));
+
+        // Each rejected location is reported with the actual reason.
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().push(PathBuf::from("/")));
+        let attempts =
+            match with_search_path(&dir, || FileHandler::<u64>::default_creation_path(name)) {
+                Err(Error::NoWritableLocation { attempts }) => attempts,
+                result => panic!("Unexpected result: {:?}", result),
+            };
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().clear());
+        assert!(attempts.contains(&(dir.join(name), READ_ONLY_FS_KIND)));
+        assert!(attempts
+            .iter()
+            .all(|&(_, kind)| kind != io::ErrorKind::PermissionDenied));
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn write_hooks_are_called_after_writes() {
+        let name = "test79.json";
+        let mut file_handler: FileHandler<u64> = unwrap!(FileHandler::new(name, true));
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        file_handler.on_write(move |path| unwrap!(unwrap!(sender.lock()).send(path.to_owned())));
+        file_handler.on_write(|_| panic!("A failing hook doesn't fail the write"));
+
+        unwrap!(file_handler.write_file(&79));
+        assert_eq!(unwrap!(receiver.try_recv()), file_handler.path());
+        assert!(!unwrap!(file_handler.write_file_if_changed(&79)));
+        assert!(receiver.try_recv().is_err());
+        {
+            let mut guard = unwrap!(file_handler.lock_exclusive_guard());
+            unwrap!(guard.write(&80));
+            assert!(receiver.try_recv().is_err());
+        }
+        assert_eq!(unwrap!(receiver.try_recv()), file_handler.path());
+        assert_eq!(unwrap!(file_handler.read_file()), 80);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn snapshot_is_unaffected_by_later_writes() {
+        let name = "test80.json";
+        let file_handler: FileHandler<u64> = unwrap!(FileHandler::new(name, true));
+        unwrap!(file_handler.write_file(&80));
+
+        let snapshot = unwrap!(file_handler.snapshot());
+        assert!(snapshot.path().starts_with(env::temp_dir()));
+        unwrap!(file_handler.write_file(&81));
+        assert_eq!(unwrap!(snapshot.read_file()), 80);
+        assert_eq!(unwrap!(snapshot.read_file()), 80);
+
+        let path = snapshot.path().to_path_buf();
+        drop(snapshot);
+        assert!(!path.exists());
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn location_base_dirs() {
+        assert_eq!(
+            unwrap!(Location::CurrentBinDir.base_dir()),
+            unwrap!(current_bin_dir())
+        );
+        assert_eq!(
+            unwrap!(Location::UserAppDir.base_dir()),
+            unwrap!(user_app_dir())
+        );
+        let dir = env::temp_dir().join("config_file_handler_test81");
+        let base_dir = with_search_path(&dir, || Location::AdditionalSearchPath.base_dir());
+        assert_eq!(unwrap!(base_dir), dir);
+        match Location::Explicit.base_dir() {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::InvalidInput => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn config_subdir_must_be_relative() {
+        use crate::paths::set_config_subdir;
+
+        for subdir in &["../v2", "/etc/app"] {
+            match set_config_subdir(*subdir) {
+                Err(Error::InvalidName { name }) => assert_eq!(name, Path::new(subdir)),
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+        assert!(paths::config_subdir().is_none());
+    }
+
+    #[test]
+    fn configs_parse_and_serialize_like_files() {
+        let contents = unwrap!(serialize_config(&vec![8u64, 2]));
+        assert_eq!(contents, "[\n  8,\n  2\n]");
+        assert_eq!(unwrap!(parse_config::<Vec<u64>>(&contents)), vec![8, 2]);
+        assert_eq!(unwrap!(parse_config::<u64>("\u{feff}82")), 82);
+        assert!(parse_config::<u64>("eighty-two").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mode_and_dir_mode_ignore_umask() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("config_file_handler_test82_{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let options = Options {
+            mode: Some(0o640),
+            dir_mode: Some(0o750),
+            ..Options::default()
+        };
+        let (_, path, _) = unwrap!(create_file_in(
+            vec![(Location::Explicit, Ok(dir.join("nested")), true)],
+            OsStr::new("test82.json"),
+            b"82",
+            &options,
+            CreateMode::Exclusive,
+        ));
+        let mode = |path: &Path| unwrap!(fs::metadata(path)).permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o640);
+        assert_eq!(mode(&dir.join("nested")), 0o750);
+
+        // Existing files keep their permissions.
+        unwrap!(fs::set_permissions(
+            &path,
+            fs::Permissions::from_mode(0o600)
+        ));
+        drop(unwrap!(open_for_write(&path, &options, false)));
+        assert_eq!(mode(&path), 0o600);
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn open_with_profile_falls_back() {
+        let base = unwrap!(FileHandler::<u64>::new("test83.json", true));
+        let prod = unwrap!(FileHandler::<u64>::new("test83.prod.json", true));
+        unwrap!(base.write_file(&1));
+        unwrap!(prod.write_file(&2));
+
+        let open = |profile| {
+            unwrap!(FileHandler::<u64>::open_with_profile(
+                "test83.json",
+                profile,
+                false
+            ))
+        };
+        assert_eq!(unwrap!(open("prod").read_file()), 2);
+        assert_eq!(unwrap!(open("dev").read_file()), 1);
+        assert!(FileHandler::<u64>::open_with_profile("test83.json", "../prod", false).is_err());
+
+        unwrap!(cleanup(&"test83.prod.json"));
+        unwrap!(cleanup(&"test83.json"));
+        assert!(FileHandler::<u64>::open_with_profile("test83.json", "prod", false).is_err());
+    }
+
+    #[test]
+    fn location_classify() {
+        let bin_dir = unwrap!(current_bin_dir());
+        assert_eq!(
+            Location::classify(&bin_dir.join("test84.json")),
+            Some(Location::CurrentBinDir)
+        );
+        assert_eq!(
+            Location::classify(&unwrap!(user_app_dir()).join("test84.json")),
+            Some(Location::UserAppDir)
+        );
+        let dir = env::temp_dir().join("config_file_handler_test84");
+        let classified = with_search_path(&dir, || Location::classify(&dir.join("test84.json")));
+        assert_eq!(classified, Some(Location::AdditionalSearchPath));
+        assert_eq!(Location::classify(&dir.join("test84.json")), None);
+        assert_eq!(
+            Location::classify(&bin_dir.join("nested").join("test84.json")),
+            None
+        );
+        assert_eq!(Location::classify(Path::new("test84.json")), None);
+    }
+
+    #[test]
+    fn open_or_build_only_builds_when_missing() {
+        let name = "test85.json";
+        let cancelled = || {
+            Err(Error::Io(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "cancelled",
+            )))
+        };
+        match FileHandler::<u64>::open_or_build(name, true, cancelled) {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::Interrupted => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(unwrap!(FileHandler::<u64>::try_open(name, false)).is_none());
+
+        let file_handler = unwrap!(FileHandler::<u64>::open_or_build(name, true, || Ok(85)));
+        assert!(file_handler.was_created());
+        assert_eq!(unwrap!(file_handler.read_file()), 85);
+
+        let file_handler = unwrap!(FileHandler::<u64>::open_or_build(name, true, || {
+            panic!("Built an existing file")
+        }));
+        assert!(!file_handler.was_created());
+        assert_eq!(unwrap!(file_handler.read_file()), 85);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn racing_new_never_clobbers_a_written_value() {
+        use std::sync::Barrier;
+
+        const THREADS: usize = 8;
+        let name = "test86.json";
+        unwrap!(cleanup(&name));
+        for _ in 0..200 {
+            let barrier = Arc::new(Barrier::new(THREADS));
+            let threads = (0..THREADS)
+                .map(|_| {
+                    let barrier = Arc::clone(&barrier);
+                    thread::spawn(move || {
+                        let _ = barrier.wait();
+                        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+                        if file_handler.was_created() {
+                            unwrap!(file_handler.write_file(&86));
+                        }
+                        file_handler.was_created()
+                    })
+                })
+                .collect::<Vec<_>>();
+            let created = threads
+                .into_iter()
+                .map(|thread| unwrap!(thread.join()))
+                .filter(|&created| created)
+                .count();
+            assert_eq!(created, 1);
+            let file_handler = unwrap!(FileHandler::<u64>::open(name, false));
+            assert_eq!(unwrap!(file_handler.read_file()), 86);
+            unwrap!(cleanup(&name));
+        }
+    }
+
+    #[test]
+    fn reopen_after_replacement() {
+        let name = "test87.json";
+        let mut file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(file_handler.write_file(&1));
 
-        let contents = to_string_pretty(&T::default())?.into_bytes();
-        let name = name.as_ref();
+        let staged = file_handler.path().with_extension("staged");
+        unwrap!(fs::write(&staged, b"87"));
+        unwrap!(fs::rename(&staged, file_handler.path()));
+        unwrap!(file_handler.reopen());
+        assert_eq!(unwrap!(file_handler.read_file()), 87);
 
-        let _guard = global_mutex::get_mutex()
-            .lock()
-            .expect("Could not lock mutex");
+        let file = unwrap!(File::open(file_handler.path()));
+        match FileHandler::<u64>::from_file(file).reopen() {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::InvalidInput => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(cleanup(&name));
+    }
 
-        if let Some(mut path) = unwrap!(ADDITIONAL_SEARCH_PATH.lock()).clone() {
-            path.push(name);
-            if let Ok(mut f) = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&path)
-            {
-                write_with_lock(&mut f, &contents)?;
-                return Ok(FileHandler {
-                    path,
-                    _ph: PhantomData,
-                });
+    #[test]
+    fn lock_exclusive_guard_timeout_gives_up() {
+        let name = "test88.json";
+        let mut file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        assert!(file_handler
+            .set_lock_poll_interval(Duration::from_secs(0))
+            .is_err());
+        unwrap!(file_handler.set_lock_poll_interval(Duration::from_millis(5)));
+
+        let other = unwrap!(FileHandler::<u64>::open(name, true));
+        let guard = unwrap!(other.lock_exclusive_guard());
+        let timeout = Duration::from_millis(50);
+        match file_handler.lock_exclusive_guard_timeout(timeout) {
+            Err(Error::Timeout { name, timeout: t }) => {
+                assert_eq!(name, file_handler.path());
+                assert_eq!(t, timeout);
             }
+            result => panic!("Unexpected result: {:?}", result.map(|_| ())),
         }
+        drop(guard);
 
-        if let Ok(mut path) = current_bin_dir() {
-            path.push(name);
-            if let Ok(mut f) = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&path)
-            {
-                write_with_lock(&mut f, &contents)?;
-                return Ok(FileHandler {
-                    path,
-                    _ph: PhantomData,
-                });
-            }
+        let mut guard = unwrap!(file_handler.lock_exclusive_guard_timeout(timeout));
+        unwrap!(guard.write(&88));
+        drop(guard);
+        assert_eq!(unwrap!(other.read_file()), 88);
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn cleanup_app_dir_is_scoped() {
+        use crate::paths::is_app_dir;
+
+        let user_app_dir = unwrap!(user_app_dir());
+        assert!(is_app_dir(&user_app_dir, false));
+        assert!(!is_app_dir(&user_app_dir, true));
+        assert!(is_app_dir(&unwrap!(system_cache_dir()), false));
+        assert!(!is_app_dir(unwrap!(user_app_dir.parent()), false));
+        assert!(!is_app_dir(&user_app_dir.join("nested"), false));
+        assert!(!is_app_dir(Path::new("/"), false));
+        if let Some(home_dir) = dirs::home_dir() {
+            assert!(!is_app_dir(&home_dir, false));
         }
+    }
 
-        if let Ok(mut path) = user_app_dir() {
-            let avoid = if path.is_dir() {
-                false
-            } else {
-                fs::create_dir(&path).is_err()
-            };
-            if !avoid {
-                path.push(name);
-                if let Ok(mut f) = OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(&path)
-                {
-                    write_with_lock(&mut f, &contents)?;
-                    return Ok(FileHandler {
-                        path,
-                        _ph: PhantomData,
-                    });
-                }
-            }
+    #[test]
+    fn paths_from_uris() {
+        assert_eq!(
+            unwrap!(path_from_uri("relative/test89.json")),
+            Path::new("relative/test89.json")
+        );
+        match path_from_uri("https://example.com/test89.json") {
+            Err(Error::UnsupportedScheme { scheme }) => assert_eq!(scheme, "https"),
+            result => panic!("Unexpected result: {:?}", result),
         }
+        assert!(path_from_uri("file:///bad%2").is_err());
 
-        let mut path = system_cache_dir()?;
-        if !path.is_dir() {
-            fs::create_dir(&path)?;
+        #[cfg(unix)]
+        {
+            let path = |uri| unwrap!(path_from_uri(uri));
+            assert_eq!(
+                path("file:///etc/my%20app/test89.json"),
+                Path::new("/etc/my app/test89.json")
+            );
+            assert_eq!(
+                path("file://localhost/etc/test89.json"),
+                Path::new("/etc/test89.json")
+            );
+            assert_eq!(
+                path("FILE:/etc/test89.json?x#y"),
+                Path::new("/etc/test89.json")
+            );
+            assert_eq!(path("/etc/test89.json"), Path::new("/etc/test89.json"));
+            assert!(path_from_uri("file://remote/etc/test89.json").is_err());
         }
-        path.push(name);
-        match OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&path)
+        #[cfg(windows)]
         {
-            Ok(mut f) => {
-                write_with_lock(&mut f, &contents)?;
-                Ok(FileHandler {
-                    path,
-                    _ph: PhantomData,
-                })
-            }
-            Err(e) => Err(From::from(e)),
+            let path = |uri| unwrap!(path_from_uri(uri));
+            assert_eq!(
+                path("file:///C:/my%20app/test89.json"),
+                Path::new(r"C:\my app\test89.json")
+            );
+            assert_eq!(
+                path("file://server/share/test89.json"),
+                Path::new(r"\\server\share\test89.json")
+            );
+            assert_eq!(path(r"C:\test89.json"), Path::new(r"C:\test89.json"));
         }
-    }
-}
 
-impl<T> FileHandler<T>
-where
-    T: DeserializeOwned,
-{
-    /// Read the contents of the file and decode it as JSON.
-    #[allow(clippy::redundant_closure)] // because of lifetimes
-    pub fn read_file(&self) -> Result<T, Error> {
-        let mut file = File::open(&self.path)?;
-        let contents = shared_lock(&mut file, |file| from_reader(file))?;
-        Ok(contents)
+        let file_handler = unwrap!(FileHandler::<u64>::new("test89.json", true));
+        unwrap!(file_handler.write_file(&89));
+        let path = file_handler.path().display().to_string().replace('\\', "/");
+        let uri = format!("file://{}{}", if cfg!(windows) { "/" } else { "" }, path);
+        let opened = unwrap!(FileHandler::<u64>::open_uri(&uri, false));
+        assert_eq!(unwrap!(opened.read_file()), 89);
+        unwrap!(cleanup(&"test89.json"));
     }
-}
 
-impl<T> FileHandler<T>
-where
-    T: Serialize,
-{
-    /// Write `contents` to the file as JSON.
-    pub fn write_file(&self, contents: &T) -> Result<(), Error> {
-        let contents = to_string_pretty(contents)?.into_bytes();
-
-        let _guard = global_mutex::get_mutex()
-            .lock()
-            .expect("Could not lock mutex");
-
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.path)?;
-        write_with_lock(&mut file, &contents)?;
-        Ok(())
+    #[test]
+    fn restore_checkpoint() {
+        let name = "test90.json";
+        let file_handler = unwrap!(FileHandler::<Vec<u64>>::new(name, true));
+        unwrap!(fs::write(file_handler.path(), b"[9, 0] "));
+        let checkpoint = unwrap!(file_handler.checkpoint());
+
+        unwrap!(file_handler.write_file(&vec![1]));
+        assert_eq!(unwrap!(file_handler.read_file()), vec![1]);
+        unwrap!(file_handler.restore(&checkpoint));
+        assert_eq!(unwrap!(fs::read(file_handler.path())), b"[9, 0] ");
+        unwrap!(cleanup(&name));
     }
-}
 
-/// Remove the file from every location where it can be read.
-pub fn cleanup<S: AsRef<OsStr>>(name: &S) -> io::Result<()> {
-    let name = name.as_ref();
-    let i1 = current_bin_dir().into_iter();
-    let i2 = user_app_dir().into_iter();
-    let i3 = system_cache_dir().into_iter();
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_options_are_explicit() {
+        use crate::{BincodeOptions, Builder, Format};
 
-    let dirs = i1.chain(i2.chain(i3));
+        let name = "test91.bin";
+        let open = |format| -> FileHandler<Vec<u32>> {
+            unwrap!(Builder::new().format(format).create(name, true))
+        };
+        // The default encoding is the same on every architecture.
+        let file_handler = open(Format::Bincode);
+        unwrap!(file_handler.write_file(&vec![0x0102]));
+        assert_eq!(
+            unwrap!(fs::read(file_handler.path())),
+            [1, 0, 0, 0, 0, 0, 0, 0, 2, 1, 0, 0]
+        );
 
-    for mut path in dirs {
-        path.push(name);
-        if path.exists() {
-            fs::remove_file(path)?;
-        }
+        let options = BincodeOptions {
+            big_endian: true,
+            varint: true,
+        };
+        let file_handler = open(Format::BincodeWith(options));
+        unwrap!(file_handler.write_file(&vec![0x0102, 3]));
+        assert_eq!(unwrap!(fs::read(file_handler.path())), [2, 251, 1, 2, 3]);
+        assert_eq!(unwrap!(file_handler.read_file()), vec![0x0102, 3]);
+        unwrap!(cleanup(&name));
     }
 
-    Ok(())
-}
-
-fn exclusive_lock<F, R, E>(file: &mut File, f: F) -> Result<R, Error>
-where
-    F: FnOnce(&mut File) -> Result<R, E>,
-    Error: From<E>,
-{
-    file.lock_exclusive()?;
-    let result = f(file);
-    file.unlock()?;
-    result.map_err(From::from)
-}
-
-fn shared_lock<F, R, E>(file: &mut File, f: F) -> Result<R, Error>
-where
-    F: FnOnce(&mut File) -> Result<R, E>,
-    Error: From<E>,
-{
-    file.lock_shared()?;
-    let result = f(file);
-    file.unlock()?;
-    result.map_err(From::from)
-}
+    #[test]
+    fn open_filtered_skips_rejected_candidates() {
+        let name = "test92.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(file_handler.write_file(&92));
+        let location = file_handler.location();
 
-fn write_with_lock(file: &mut File, contents: &[u8]) -> Result<(), Error> {
-    exclusive_lock(file, |file| file.write_all(contents))
-}
+        let opened = unwrap!(FileHandler::<u64>::open_filtered(name, false, |path, _| {
+            path.ends_with(name)
+        }));
+        assert_eq!(opened.path(), file_handler.path());
+        match FileHandler::<u64>::open_filtered(name, false, |_, candidate| candidate != location) {
+            Err(Error::NotFoundAnywhere { attempts }) => {
+                assert!(attempts.iter().all(|(path, _)| path != file_handler.path()))
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(cleanup(&name));
+    }
 
-/// The full path to the directory containing the currently-running binary. See also [an example
-/// config file flowchart][1].
-///
-/// [1]: https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf
-pub fn current_bin_dir() -> Result<PathBuf, Error> {
-    match env::current_exe()?.parent() {
-        Some(path) => Ok(path.to_path_buf()),
-        None => Err(Error::Io(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Current bin dir",
-        ))),
+    #[test]
+    fn search_locations_are_in_search_order() {
+        let dir = env::temp_dir().join("config_file_handler_test93");
+        let locations = with_search_path(&dir, || search_locations().collect::<Vec<_>>());
+        match locations[0] {
+            (Location::AdditionalSearchPath, Ok(ref path)) => assert_eq!(*path, dir),
+            ref first => panic!("Unexpected first location: {:?}", first),
+        }
+        let kinds = search_locations()
+            .map(|(location, _)| location)
+            .filter(|&location| location != Location::AdditionalSearchPath)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                Location::CurrentBinDir,
+                Location::BundleResourceDir,
+                Location::UserAppDir,
+                Location::SystemCacheDir
+            ]
+        );
     }
-}
 
-/// The full path to the directory containing the resources to currently-running binary.
-/// For OSX this is special directory. For others it's an error.
-#[cfg(not(target_os = "macos"))]
-pub fn bundle_resource_dir() -> Result<PathBuf, Error> {
-    Err(Error::Io(io::Error::new(
-        io::ErrorKind::NotFound,
-        "Bundle resource directory only applicable to MacOs",
-    )))
-}
+    #[test]
+    fn with_mutex_replaces_the_global_mutex() {
+        use crate::Builder;
 
-/// The full path to the directory containing the resources to currently-running binary.
-/// For OSX this is special directory. For others it's an error.
-#[cfg(target_os = "macos")]
-pub fn bundle_resource_dir() -> Result<PathBuf, Error> {
-    let mut bundle_dir = env::current_exe()?
-        .parent()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Bundle resources directory"))?
-        .to_path_buf();
+        let name = "test94.json";
+        let mutex = Arc::new(Mutex::new(()));
+        let file_handler: FileHandler<u64> = unwrap!(Builder::new()
+            .with_mutex(Arc::clone(&mutex))
+            .create(name, true));
 
-    let is_inside_bundle = bundle_dir
-        .to_str()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Path is not unicode"))?
-        .ends_with(".app/Contents/MacOS");
+        // Writes don't need the global mutex, which a batch holds...
+        batch(|| {
+            let writer = file_handler.clone();
+            unwrap!(unwrap!(thread::spawn(move || writer.write_file(&94)).join()));
+        });
+        assert_eq!(unwrap!(file_handler.read_file()), 94);
 
-    if !is_inside_bundle {
-        return Err(Error::Io(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Not inside an Application Bundle",
-        )));
+        // ...but do wait for their own.
+        let guard = unwrap!(mutex.lock());
+        let writer = file_handler.clone();
+        let writing = thread::spawn(move || writer.write_file(&95));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(unwrap!(file_handler.read_file()), 94);
+        drop(guard);
+        unwrap!(unwrap!(writing.join()));
+        assert_eq!(unwrap!(file_handler.read_file()), 95);
+        unwrap!(cleanup(&name));
     }
 
-    bundle_dir = bundle_dir
-        .parent()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Bundle resource directory"))?
-        .to_path_buf();
-    bundle_dir.push("Resources");
+    #[test]
+    fn migrate_location_moves_legacy_file() {
+        let name = "test95.json";
+        let legacy = env::temp_dir().join("config_file_handler_test95");
+        unwrap!(fs::create_dir_all(&legacy));
+        unwrap!(fs::write(legacy.join(name), b"95"));
 
-    Ok(bundle_dir)
-}
+        let migrated = with_search_path(&legacy, || {
+            FileHandler::<u64>::migrate_location(
+                name,
+                Location::AdditionalSearchPath,
+                Location::UserAppDir,
+                true,
+            )
+        });
+        let migrated = unwrap!(migrated);
+        assert_eq!(migrated.location(), Location::UserAppDir);
+        assert_eq!(migrated.path(), unwrap!(user_app_dir()).join(name));
+        assert_eq!(unwrap!(migrated.read_file()), 95);
+        assert!(!legacy.join(name).exists());
 
-/// The full path to an application support directory for the current user.  See also [an example
-/// config file flowchart][1].
-///
-/// [1]: https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf
-#[cfg(windows)]
-pub fn user_app_dir() -> Result<PathBuf, Error> {
-    let path = env::var("APPDATA")?;
-    let app_dir = Path::new(&path);
+        // Once there is a copy in `to`, any other copy is left where it is.
+        unwrap!(fs::write(legacy.join(name), b"1"));
+        let opened = with_search_path(&legacy, || {
+            FileHandler::<u64>::migrate_location(
+                name,
+                Location::AdditionalSearchPath,
+                Location::UserAppDir,
+                false,
+            )
+        });
+        assert_eq!(unwrap!(opened).location(), Location::AdditionalSearchPath);
+        assert!(legacy.join(name).exists());
 
-    if app_dir.is_dir() {
-        Ok(join_exe_file_stem(app_dir)?)
-    } else {
-        Err(Error::Io(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Global user app directory not found.",
-        )))
+        unwrap!(fs::remove_dir_all(&legacy));
+        unwrap!(cleanup(&name));
     }
-}
 
-/// The full path to an application support directory for the current user.  See also [an example
-/// config file flowchart][1].
-///
-/// [1]: https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf
-#[cfg(all(unix, not(target_os = "macos")))]
-pub fn user_app_dir() -> Result<PathBuf, Error> {
-    let mut home_dir = dirs::home_dir()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found."))?;
-    home_dir.push(".config");
-
-    if home_dir.is_dir() {
-        Ok(join_exe_file_stem(&home_dir)?)
-    } else {
-        Err(Error::Io(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Global user app directory not found.",
-        )))
-    }
-}
+    #[test]
+    fn json_lines_appends_records() {
+        let name = "test96.jsonl";
+        let log = unwrap!(FileHandler::<Vec<u32>>::open_json_lines(name));
+        assert!(unwrap!(log.read_all()).is_empty());
 
-/// The full path to an application support directory for the current user.  See also [an example
-/// config file flowchart][1].
-///
-/// [1]: https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf
-#[cfg(target_os = "macos")]
-pub fn user_app_dir() -> Result<PathBuf, Error> {
-    let mut app_dir = dirs::home_dir()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found."))?;
-    app_dir.push("Library/Application Support");
-
-    if app_dir.is_dir() {
-        Ok(join_exe_file_stem(&app_dir)?)
-    } else {
-        Err(Error::Io(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Global user app directory not found.",
-        )))
-    }
-}
+        unwrap!(log.append(&vec![1, 2]));
+        unwrap!(log.append(&vec![]));
+        // Blank lines, e.g. from editing the file by hand, are skipped.
+        unwrap!(OpenOptions::new()
+            .append(true)
+            .open(log.path())
+            .and_then(|mut file| file.write_all(b"\n  \n")));
+        unwrap!(log.append(&vec![3]));
 
-/// The full path to a system cache directory available for all users. See also [an example config
-/// file flowchart][1].
-///
-/// [1]: https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf
-#[cfg(windows)]
-pub fn system_cache_dir() -> Result<PathBuf, Error> {
-    let path = env::var("ALLUSERSPROFILE")?;
-    let sys_cache_dir = Path::new(&path);
+        assert_eq!(
+            unwrap!(fs::read_to_string(log.path())),
+            "[1,2]\n[]\n\n  \n[3]\n"
+        );
+        let reopened = unwrap!(FileHandler::<Vec<u32>>::open_json_lines(name));
+        assert_eq!(reopened.path(), log.path());
+        assert_eq!(
+            unwrap!(reopened.read_all()),
+            vec![vec![1, 2], vec![], vec![3]]
+        );
 
-    if sys_cache_dir.is_dir() {
-        Ok(join_exe_file_stem(sys_cache_dir)?)
-    } else {
-        Err(Error::Io(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Global system cache directory not found.",
-        )))
+        unwrap!(log.append(&vec![4]));
+        unwrap!(OpenOptions::new()
+            .append(true)
+            .open(log.path())
+            .and_then(|mut file| file.write_all(b"not json\n")));
+        match log.read_all() {
+            Err(Error::Parse { .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(cleanup(&name));
     }
-}
 
-/// The full path to a system cache directory available for all users. See also [an example config
-/// file flowchart][1].
-///
-/// [1]: https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf
-#[cfg(all(unix, not(target_os = "macos")))]
-pub fn system_cache_dir() -> Result<PathBuf, Error> {
-    let sys_cache_dir = Path::new("/var/cache");
+    #[test]
+    fn lock_modes() {
+        let name = "test97.json";
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        unwrap!(file_handler.write_file(&97));
 
-    if sys_cache_dir.is_dir() {
-        Ok(join_exe_file_stem(sys_cache_dir)?)
-    } else {
-        Err(Error::Io(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Global system cache directory not found.",
-        )))
-    }
-}
+        // Without locking, the file can be read and written while another handle holds the
+        // exclusive lock.
+        let held = unwrap!(File::open(file_handler.path()));
+        unwrap!(held.lock_exclusive());
+        let unlocked = unwrap!(FileHandler::<u64>::open_with_lock_mode(
+            name,
+            true,
+            LockMode::None
+        ));
+        assert_eq!(unwrap!(unlocked.read_file()), 97);
+        unwrap!(unlocked.write_file(&98));
+        unwrap!(held.unlock());
 
-/// The full path to a system cache directory available for all users. See also [an example config
-/// file flowchart][1].
-///
-/// [1]: https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf
-#[cfg(target_os = "macos")]
-pub fn system_cache_dir() -> Result<PathBuf, Error> {
-    let sys_cache_dir = Path::new("/Library/Application Support");
+        let exclusive = unwrap!(FileHandler::<u64>::open_with_lock_mode(
+            name,
+            true,
+            LockMode::Exclusive
+        ));
+        assert_eq!(unwrap!(exclusive.read_file()), 98);
+        // With `LockMode::Exclusive`, reads are blocked by a shared lock.
+        unwrap!(held.lock_shared());
+        let (tx, rx) = mpsc::channel();
+        let reader = thread::spawn(move || unwrap!(tx.send(exclusive.read_file())));
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        unwrap!(held.unlock());
+        assert_eq!(unwrap!(unwrap!(rx.recv())), 98);
+        unwrap!(reader.join());
 
-    if sys_cache_dir.is_dir() {
-        Ok(join_exe_file_stem(sys_cache_dir)?)
-    } else {
-        Err(Error::Io(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Global system cache directory not found.",
-        )))
+        match lock_error(io::Error::from(io::ErrorKind::Unsupported)) {
+            Error::LockUnsupported(_) => (),
+            error => panic!("Unexpected error: {:?}", error),
+        }
+        unwrap!(cleanup(&name));
     }
-}
 
-/// The file name of the currently-running binary without any suffix or extension.  For example, if
-/// the binary is "C:\\Abc.exe" this function will return `Ok("Abc")`.
-pub fn exe_file_stem() -> Result<OsString, Error> {
-    if let Ok(exe_path) = env::current_exe() {
-        let file_stem = exe_path.file_stem();
-        Ok(file_stem
-            .ok_or_else(|| not_found_error(&exe_path))?
-            .to_os_string())
-    } else {
-        Ok(From::from("default"))
-    }
-}
+    #[test]
+    fn config_types_name_their_file() {
+        #[derive(Default, Debug, PartialEq, Serialize, Deserialize)]
+        struct PeerConfig {
+            port: u16,
+        }
 
-/// RAII object which removes the [`user_app_dir()`](fn.user_app_dir.html) when an instance is
-/// dropped.
-///
-/// Since the `user_app_dir` is frequently created by tests or examples which use Crust, this is a
-/// convenience object which tries to remove the directory when it is destroyed.
-///
-/// # Examples
-///
-/// ```
-/// use config_file_handler::{FileHandler, ScopedUserAppDirRemover};
-///
-/// {
-///     let _cleaner = ScopedUserAppDirRemover;
-///     let file_handler = FileHandler::new("test.json", true).unwrap();
-///     // User app dir is possibly created by this call.
-///     let _ = file_handler.write_file(&111u64);
-/// }
-/// // User app dir is now removed since '_cleaner' has gone out of scope.
-/// ```
-pub struct ScopedUserAppDirRemover;
+        impl Config for PeerConfig {
+            const FILE_NAME: &'static str = "test98.json";
+        }
 
-impl ScopedUserAppDirRemover {
-    fn remove_dir(&mut self) {
-        let _ = user_app_dir()
-            .and_then(|user_app_dir| fs::remove_dir_all(user_app_dir).map_err(Error::Io));
-    }
-}
+        let name = PeerConfig::FILE_NAME;
+        assert!(FileHandler::<PeerConfig>::open_default(false).is_err());
+        let file_handler = unwrap!(FileHandler::<PeerConfig>::new_default(true));
+        assert_eq!(unwrap!(file_handler.path().file_name()), name);
+        unwrap!(file_handler.write_file(&PeerConfig { port: 98 }));
 
-impl Drop for ScopedUserAppDirRemover {
-    fn drop(&mut self) {
-        self.remove_dir();
+        let opened = unwrap!(FileHandler::<PeerConfig>::open_default(false));
+        assert_eq!(opened, file_handler);
+        assert_eq!(unwrap!(opened.read_file()), PeerConfig { port: 98 });
+        unwrap!(cleanup(&name));
     }
-}
 
-fn not_found_error(file_name: &Path) -> io::Error {
-    let mut msg: String = From::from("No file name component: ");
-    msg.push_str(&file_name.to_string_lossy());
-    io::Error::new(io::ErrorKind::NotFound, msg)
-}
+    #[test]
+    fn read_file_tolerant_skips_bad_fields() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Settings {
+            name: String,
+            port: u16,
+            peers: Vec<String>,
+        }
 
-fn join_exe_file_stem(path: &Path) -> Result<PathBuf, Error> {
-    Ok(path.join(exe_file_stem()?))
-}
+        impl Default for Settings {
+            fn default() -> Self {
+                Settings {
+                    name: "default".to_string(),
+                    port: 5483,
+                    peers: Vec::new(),
+                }
+            }
+        }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+        let name = "test99.json";
+        let file_handler = unwrap!(FileHandler::<Settings>::new(name, true));
+        let (settings, rejected) = unwrap!(file_handler.read_file_tolerant());
+        assert_eq!(settings, Settings::default());
+        assert!(rejected.is_empty());
 
-    #[test]
-    fn read_write_file_test() {
-        let _cleaner = ScopedUserAppDirRemover;
-        let file_handler = match FileHandler::new("test0.json", true) {
-            Ok(result) => result,
-            Err(err) => panic!("failed accessing file with error {:?}", err),
-        };
-        let test_value = 123_456_789u64;
+        unwrap!(fs::write(
+            file_handler.path(),
+            r#"{"name": "node", "port": "not a port", "peers": ["a", 1]}"#
+        ));
+        let (settings, mut rejected) = unwrap!(file_handler.read_file_tolerant());
+        rejected.sort();
+        assert_eq!(
+            settings,
+            Settings {
+                name: "node".to_string(),
+                ..Settings::default()
+            }
+        );
+        assert_eq!(rejected, vec!["/peers".to_string(), "/port".to_string()]);
 
-        let _ = file_handler.write_file(&test_value);
-        let read_value = match file_handler.read_file() {
-            Ok(result) => result,
-            Err(err) => panic!("failed reading file with error {:?}", err),
-        };
-        assert_eq!(test_value, read_value);
+        // Anything other than an object is still rejected outright.
+        unwrap!(fs::write(file_handler.path(), "[1, 2]"));
+        match file_handler.read_file_tolerant() {
+            Err(Error::Parse { .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        unwrap!(cleanup(&name));
     }
 
     #[test]
-    fn existing_file_is_overwritten() {
-        let _cleaner = ScopedUserAppDirRemover;
-        let file_handler = FileHandler::new("test1.json", true).expect("failed accessing file");
-
-        let write_value0 = vec![1, 2, 3];
-        file_handler
-            .write_file(&write_value0)
-            .expect("failed writing file");
+    fn open_with_deadline_covers_the_lock() {
+        let name = "test100.json";
+        let start = Instant::now();
+        match FileHandler::<u64>::open_with_deadline(name, false, start + Duration::from_millis(50))
+        {
+            Err(Error::Timeout { .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(start.elapsed() >= Duration::from_millis(50));
 
-        let write_value1 = vec![4, 5, 6];
-        file_handler
-            .write_file(&write_value1)
-            .expect("failed writing file");
+        let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+        let held = unwrap!(File::open(file_handler.path()));
+        unwrap!(held.lock_exclusive());
+        let deadline = Instant::now() + Duration::from_millis(50);
+        match FileHandler::<u64>::open_with_deadline(name, false, deadline) {
+            Err(Error::Timeout { name, .. }) => assert_eq!(name, file_handler.path()),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert!(Instant::now() >= deadline);
 
-        let read_value = file_handler.read_file().expect("failed reading file");
-        assert_eq!(read_value, write_value1);
+        unwrap!(held.unlock());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let opened = unwrap!(FileHandler::<u64>::open_with_deadline(
+            name, false, deadline
+        ));
+        assert_eq!(opened, file_handler);
+        unwrap!(cleanup(&name));
     }
 
     #[test]
-    fn concurrent_writes() {
-        use std::iter;
-        use std::sync::{Arc, Barrier};
-        use std::thread;
+    fn byte_transforms_apply_in_order() {
+        use crate::{Builder, ByteTransform};
 
-        const NUM_THREADS: usize = 100;
-        const DATA_SIZE: usize = 10_000;
-        const FILE_NAME: &str = "test2.json";
+        struct Xor(u8);
 
-        let _cleaner = ScopedUserAppDirRemover;
-        let barrier = Arc::new(Barrier::new(NUM_THREADS));
+        impl ByteTransform for Xor {
+            fn on_write(&self, contents: Vec<u8>) -> Result<Vec<u8>, Error> {
+                Ok(contents.into_iter().map(|byte| byte ^ self.0).collect())
+            }
 
-        let handles = (0..NUM_THREADS)
-            .map(|i| {
-                let barrier = Arc::clone(&barrier);
+            fn on_read(&self, contents: Vec<u8>) -> Result<Vec<u8>, Error> {
+                self.on_write(contents)
+            }
+        }
 
-                thread::spawn(move || {
-                    let data = iter::repeat(i).take(DATA_SIZE).collect::<Vec<_>>();
+        struct Tag;
 
-                    let _ = barrier.wait();
+        impl ByteTransform for Tag {
+            fn on_write(&self, mut contents: Vec<u8>) -> Result<Vec<u8>, Error> {
+                contents.insert(0, b'#');
+                Ok(contents)
+            }
 
-                    let file_handler =
-                        FileHandler::new(FILE_NAME, true).expect("failed accessing file");
-                    file_handler.write_file(&data).expect("failed writing file");
-                })
-            })
-            .collect::<Vec<_>>();
+            fn on_read(&self, mut contents: Vec<u8>) -> Result<Vec<u8>, Error> {
+                if contents.first() != Some(&b'#') {
+                    return Err(Error::Io(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "No tag",
+                    )));
+                }
+                let _ = contents.remove(0);
+                Ok(contents)
+            }
+        }
 
-        for handle in handles {
-            unwrap!(handle.join());
+        let name = "test101.json";
+        let file_handler: FileHandler<u64> = unwrap!(Builder::new()
+            .transform(Xor(0x20))
+            .transform(Tag)
+            .create(name, true));
+        unwrap!(file_handler.write_file(&101));
+        let mut expected = b"101".iter().map(|byte| byte ^ 0x20).collect::<Vec<_>>();
+        expected.insert(0, b'#');
+        assert_eq!(unwrap!(fs::read(file_handler.path())), expected);
+        assert_eq!(unwrap!(file_handler.read_file()), 101);
+
+        // Applied in the wrong order, the tag isn't found.
+        let reversed: FileHandler<u64> = unwrap!(Builder::new()
+            .transform(Tag)
+            .transform(Xor(0x20))
+            .open(name, false));
+        assert!(reversed.read_file().is_err());
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn open_writable_stops_at_read_only_copy() {
+        let name = "test102.json";
+        match FileHandler::<u64>::open_writable(name) {
+            Err(Error::NotFoundAnywhere { attempts }) => assert!(!attempts.is_empty()),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        match FileHandler::<u64>::open_writable("../test102.json") {
+            Err(Error::InvalidName { .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
         }
 
-        let file_handler = FileHandler::new(FILE_NAME, true).expect("failed accessing file");
-        let mut data: Vec<usize> = file_handler.read_file().expect("failed reading file");
+        // A writable copy in the user app dir, shadowed by a read-only one earlier in the search.
+        let writable = unwrap!(FileHandler::<u64>::new(name, true));
+        let dir = env::temp_dir().join("config_file_handler_test102");
+        unwrap!(fs::create_dir_all(&dir));
+        let read_only = dir.join(name);
+        unwrap!(fs::write(&read_only, b"102"));
+        let mut permissions = unwrap!(fs::metadata(&read_only)).permissions();
+        permissions.set_readonly(true);
+        unwrap!(fs::set_permissions(&read_only, permissions.clone()));
+        // The permissions alone don't stop root from writing.
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().push(dir.clone()));
 
-        // Test that all elements in the vector are the same, to verify no
-        // interleaving took place.
-        data.sort();
-        data.dedup();
-        assert_eq!(data.len(), 1);
+        match with_search_path(&dir, || FileHandler::<u64>::open_writable(name)) {
+            Err(Error::PermissionDenied { path }) => assert_eq!(path, read_only),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        SIMULATED_READ_ONLY_DIRS.with(|dirs| dirs.borrow_mut().clear());
+        assert_eq!(unwrap!(FileHandler::<u64>::open_writable(name)), writable);
+
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        unwrap!(fs::set_permissions(&read_only, permissions));
+        unwrap!(fs::remove_dir_all(&dir));
+        unwrap!(cleanup(&name));
+    }
+
+    #[test]
+    fn copy_to_copies_raw_contents() {
+        let name = "test103.json";
+        let file_handler = unwrap!(FileHandler::<Vec<u64>>::new(name, true));
+        unwrap!(file_handler.write_file(&vec![1, 0, 3]));
+
+        let dir = env::temp_dir().join("config_file_handler_test103");
+        unwrap!(fs::create_dir_all(&dir));
+        let dest = dir.join(name);
+        unwrap!(file_handler.copy_to(&dest));
+        assert_eq!(
+            unwrap!(fs::read(&dest)),
+            unwrap!(fs::read(file_handler.path()))
+        );
+        let copy = unwrap!(FileHandler::<Vec<u64>>::open_with_base(&dir, name, false));
+        assert_eq!(unwrap!(copy.read_file()), vec![1, 0, 3]);
+
+        // An existing destination is replaced, not appended to or partly overwritten.
+        unwrap!(fs::write(&dest, vec![b' '; 1024]));
+        unwrap!(file_handler.write_file(&vec![4]));
+        unwrap!(file_handler.copy_to(&dest));
+        assert_eq!(
+            unwrap!(fs::read(&dest)),
+            unwrap!(fs::read(file_handler.path()))
+        );
+        assert_eq!(unwrap!(copy.read_file()), vec![4]);
+
+        unwrap!(fs::remove_dir_all(&dir));
+        unwrap!(cleanup(&name));
     }
 
     // Run as `cargo test -- --ignored --nocapture` to print the paths
@@ -665,25 +6377,15 @@ mod test {
             "Unknown".to_string()
         };
 
-        let current_bin_dir = match current_bin_dir() {
-            Ok(x) => format!("{:?}", x),
-            Err(x) => format!("{:?}", x),
-        };
-
-        let bundle_resource_dir = match bundle_resource_dir() {
-            Ok(x) => format!("{:?}", x),
-            Err(x) => format!("{:?}", x),
-        };
-
-        let user_app_dir = match user_app_dir() {
-            Ok(x) => format!("{:?}", x),
-            Err(x) => format!("{:?}", x),
-        };
-
-        let system_cache_dir = match system_cache_dir() {
-            Ok(x) => format!("{:?}", x),
-            Err(x) => format!("{:?}", x),
+        let dirs = resolved_dirs();
+        let describe = |dir: &Result<PathBuf, Error>| match *dir {
+            Ok(ref x) => format!("{:?}", x),
+            Err(ref x) => format!("{:?}", x),
         };
+        let current_bin_dir = describe(&dirs.current_bin_dir);
+        let bundle_resource_dir = describe(&dirs.bundle_resource_dir);
+        let user_app_dir = describe(&dirs.user_app_dir);
+        let system_cache_dir = describe(&dirs.system_cache_dir);
 
         println!("=================================");
         println!("Current bin dir in {}: {}", os, current_bin_dir);
@@ -0,0 +1,330 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::error::Error;
+use serde::de::{Deserialize, DeserializeOwned};
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Encoding used for the contents of a config file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Pretty-printed JSON.  This is the default.
+    #[default]
+    Json,
+    /// JSON5, which allows comments, trailing commas and other human-friendly extensions, parsed
+    /// using `json5`.  Files are written as strict pretty-printed JSON.
+    #[cfg(feature = "json5")]
+    Json5,
+    /// Compact binary encoding using `bincode`, with its default options: little-endian with
+    /// fixed-width integers, regardless of the architecture, so files can be moved between
+    /// machines.  This is the same as `BincodeWith(BincodeOptions::default())`.
+    #[cfg(feature = "bincode")]
+    Bincode,
+    /// Compact binary encoding using `bincode` with explicit options, e.g. to match files written
+    /// by another program.
+    #[cfg(feature = "bincode")]
+    BincodeWith(BincodeOptions),
+    /// CBOR (RFC 7049) using `ciborium`, for interoperability with other languages.
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+/// The indentation of pretty-printed JSON unless set otherwise, as used by `serde_json`.
+pub(crate) const DEFAULT_INDENT: &[u8] = b"  ";
+
+impl Format {
+    /// Whether this is one of the text formats, rather than a binary one.
+    pub(crate) fn is_text(self) -> bool {
+        match self {
+            Format::Json => true,
+            #[cfg(feature = "json5")]
+            Format::Json5 => true,
+            #[cfg(feature = "bincode")]
+            Format::Bincode | Format::BincodeWith(_) => false,
+            #[cfg(feature = "cbor")]
+            Format::Cbor => false,
+        }
+    }
+
+    /// Encode `value`.  `indent` is the indentation of each level of nested JSON, and is ignored
+    /// by the binary formats.
+    pub(crate) fn serialize<T: Serialize>(
+        self,
+        value: &T,
+        indent: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        match self {
+            Format::Json => to_json_pretty(value, indent),
+            #[cfg(feature = "json5")]
+            Format::Json5 => to_json_pretty(value, indent),
+            #[cfg(feature = "bincode")]
+            Format::Bincode => BincodeOptions::default().serialize(value),
+            #[cfg(feature = "bincode")]
+            Format::BincodeWith(options) => options.serialize(value),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => {
+                let mut contents = Vec::new();
+                ciborium::ser::into_writer(value, &mut contents)?;
+                Ok(contents)
+            }
+        }
+    }
+
+    /// Decode a value from `reader`.  For the text formats, a leading byte order mark is skipped,
+    /// and UTF-16 text is transcoded to UTF-8 first.
+    pub(crate) fn deserialize<T: DeserializeOwned, R: Read>(self, reader: R) -> Result<T, Error> {
+        let mut reader = BufReader::new(reader);
+        match self {
+            Format::Json => match strip_bom(&mut reader)? {
+                Some(contents) => from_json(serde_json::Deserializer::from_str(&contents)),
+                None => from_json(serde_json::Deserializer::from_reader(reader)),
+            },
+            #[cfg(feature = "json5")]
+            Format::Json5 => {
+                let contents = match strip_bom(&mut reader)? {
+                    Some(contents) => contents,
+                    None => {
+                        let mut contents = String::new();
+                        let _ = reader.read_to_string(&mut contents)?;
+                        contents
+                    }
+                };
+                Ok(json5::from_str(&contents)?)
+            }
+            #[cfg(feature = "bincode")]
+            Format::Bincode => BincodeOptions::default().deserialize_from(reader),
+            #[cfg(feature = "bincode")]
+            Format::BincodeWith(options) => options.deserialize_from(reader),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Ok(ciborium::de::from_reader(reader)?),
+        }
+    }
+
+    /// Decode `bytes`, allowing the result to borrow from them.  CBOR doesn't support this.  For
+    /// the text formats, a leading UTF-8 byte order mark is skipped, but UTF-16 isn't supported.
+    pub(crate) fn deserialize_slice<'de, T: Deserialize<'de>>(
+        self,
+        bytes: &'de [u8],
+    ) -> Result<T, Error> {
+        let text = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+        match self {
+            Format::Json => from_json(serde_json::Deserializer::from_slice(text)),
+            #[cfg(feature = "json5")]
+            Format::Json5 => {
+                let contents = std::str::from_utf8(text)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Ok(json5::from_str(contents)?)
+            }
+            #[cfg(feature = "bincode")]
+            Format::Bincode => BincodeOptions::default().deserialize(bytes),
+            #[cfg(feature = "bincode")]
+            Format::BincodeWith(options) => options.deserialize(bytes),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Err(Error::Io(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "CBOR can't be decoded into borrowed data",
+            ))),
+        }
+    }
+}
+
+/// The encoding used by [`Format::BincodeWith`](enum.Format.html#variant.BincodeWith).  The
+/// default is little-endian with fixed-width integers, as for `Format::Bincode`.
+///
+/// The options aren't recorded in the file, so it must be read with the same options as it was
+/// written with.
+#[cfg(feature = "bincode")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BincodeOptions {
+    /// Encode integers and floats big-endian rather than little-endian.
+    pub big_endian: bool,
+    /// Encode integers with a variable-length encoding, in which small values take fewer bytes,
+    /// rather than at their fixed width.
+    pub varint: bool,
+}
+
+/// Evaluate `$body` with `$config` bound to the `bincode::Options` for `$options`.  Trailing bytes
+/// are allowed, as for `bincode::deserialize()`.
+#[cfg(feature = "bincode")]
+macro_rules! with_bincode_config {
+    ($options:expr, $config:ident => $body:expr) => {{
+        use bincode::Options;
+
+        let config = bincode::DefaultOptions::new().allow_trailing_bytes();
+        match ($options.big_endian, $options.varint) {
+            (false, false) => {
+                let $config = config.with_little_endian().with_fixint_encoding();
+                $body
+            }
+            (false, true) => {
+                let $config = config.with_little_endian().with_varint_encoding();
+                $body
+            }
+            (true, false) => {
+                let $config = config.with_big_endian().with_fixint_encoding();
+                $body
+            }
+            (true, true) => {
+                let $config = config.with_big_endian().with_varint_encoding();
+                $body
+            }
+        }
+    }};
+}
+
+#[cfg(feature = "bincode")]
+impl BincodeOptions {
+    fn serialize<T: Serialize>(self, value: &T) -> Result<Vec<u8>, Error> {
+        Ok(with_bincode_config!(self, config => config.serialize(value))?)
+    }
+
+    fn deserialize_from<T: DeserializeOwned, R: Read>(self, reader: R) -> Result<T, Error> {
+        Ok(with_bincode_config!(self, config => config.deserialize_from(reader))?)
+    }
+
+    fn deserialize<'de, T: Deserialize<'de>>(self, bytes: &'de [u8]) -> Result<T, Error> {
+        Ok(with_bincode_config!(self, config => config.deserialize(bytes))?)
+    }
+}
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+const UTF16_LE_BOM: &[u8] = b"\xff\xfe";
+const UTF16_BE_BOM: &[u8] = b"\xfe\xff";
+
+/// Whether `sample`, the start of a file, looks like text: UTF-16 with a byte order mark, or else
+/// valid UTF-8 without any NUL bytes.  A multi-byte character cut off at the end of the sample is
+/// allowed.
+pub(crate) fn looks_like_text(sample: &[u8]) -> bool {
+    if sample.starts_with(UTF16_LE_BOM) || sample.starts_with(UTF16_BE_BOM) {
+        return true;
+    }
+    if sample.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// Skip a UTF-8 byte order mark at the start of `reader`, as written by e.g. Notepad.  If `reader`
+/// instead starts with a UTF-16 byte order mark, the rest of it is read and returned transcoded to
+/// UTF-8.
+fn strip_bom<R: BufRead>(reader: &mut R) -> Result<Option<String>, Error> {
+    let buf = reader.fill_buf()?;
+    let big_endian = if buf.starts_with(UTF8_BOM) {
+        reader.consume(UTF8_BOM.len());
+        return Ok(None);
+    } else if buf.starts_with(UTF16_LE_BOM) {
+        false
+    } else if buf.starts_with(UTF16_BE_BOM) {
+        true
+    } else {
+        return Ok(None);
+    };
+    reader.consume(UTF16_LE_BOM.len());
+
+    let mut bytes = Vec::new();
+    let _ = reader.read_to_end(&mut bytes)?;
+    let invalid = |message| Error::Io(io::Error::new(io::ErrorKind::InvalidData, message));
+    if bytes.len() % 2 != 0 {
+        return Err(invalid("UTF-16 text has an odd number of bytes"));
+    }
+    let units = bytes.chunks_exact(2).map(|pair| {
+        let pair = [pair[0], pair[1]];
+        if big_endian {
+            u16::from_be_bytes(pair)
+        } else {
+            u16::from_le_bytes(pair)
+        }
+    });
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map(Some)
+        .map_err(|_| invalid("UTF-16 text is invalid"))
+}
+
+/// Decode a single JSON value from `de`, failing if anything other than whitespace follows it.
+/// With the `detailed_errors` feature, errors include the path to the offending field.
+fn from_json<'de, T, R>(mut de: serde_json::Deserializer<R>) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+    R: serde_json::de::Read<'de>,
+{
+    #[cfg(feature = "detailed_errors")]
+    let value = serde_path_to_error::deserialize(&mut de)?;
+    #[cfg(not(feature = "detailed_errors"))]
+    let value = T::deserialize(&mut de)?;
+    de.end()?;
+    Ok(value)
+}
+
+fn to_json_pretty<T: Serialize>(value: &T, indent: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut contents = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent);
+    value.serialize(&mut serde_json::Serializer::with_formatter(
+        &mut contents,
+        formatter,
+    ))?;
+    Ok(contents)
+}
+
+/// Types which a `FileHandler` can decode from a file.
+///
+/// This is implemented for every type implementing `DeserializeOwned`, decoded using the handler's
+/// [`Format`](enum.Format.html), and for [`RawConfig`](struct.RawConfig.html), whose bytes are
+/// copied verbatim.
+pub trait Decode: Sized {
+    #[doc(hidden)]
+    fn decode<R: Read>(reader: R, format: Format) -> Result<Self, Error>;
+
+    /// Whether the contents must be text for `format`, so that a binary file can be rejected
+    /// before decoding it.
+    #[doc(hidden)]
+    fn expects_text(format: Format) -> bool {
+        format.is_text()
+    }
+}
+
+impl<T: DeserializeOwned> Decode for T {
+    fn decode<R: Read>(reader: R, format: Format) -> Result<Self, Error> {
+        format.deserialize(reader)
+    }
+}
+
+/// Types which a `FileHandler` can encode to a file.
+///
+/// This is implemented for every type implementing `Serialize`, encoded using the handler's
+/// [`Format`](enum.Format.html), and for [`RawConfig`](struct.RawConfig.html), whose bytes are
+/// copied verbatim.
+pub trait Encode {
+    #[doc(hidden)]
+    fn encode(
+        &self,
+        format: Format,
+        indent: &[u8],
+        trailing_newline: bool,
+    ) -> Result<Vec<u8>, Error>;
+}
+
+impl<T: Serialize> Encode for T {
+    fn encode(
+        &self,
+        format: Format,
+        indent: &[u8],
+        trailing_newline: bool,
+    ) -> Result<Vec<u8>, Error> {
+        let mut contents = format.serialize(self, indent)?;
+        if trailing_newline && format.is_text() {
+            contents.push(b'\n');
+        }
+        Ok(contents)
+    }
+}
@@ -7,7 +7,8 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use std::sync::{Mutex, Once, ONCE_INIT};
+use std::cell::Cell;
+use std::sync::{Arc, Mutex, MutexGuard, Once, ONCE_INIT};
 
 pub type GlobalMutex = Mutex<()>;
 
@@ -24,3 +25,77 @@ pub fn get_mutex<'a>() -> &'a GlobalMutex {
         &*GLOBAL_MUTEX
     }
 }
+
+thread_local! {
+    static HELD_BY_BATCH: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Lock the global mutex, unless the current thread already holds it for a batch, in which case
+/// `None` is returned and the batch's lock is relied upon instead.
+pub fn lock() -> Option<MutexGuard<'static, ()>> {
+    if HELD_BY_BATCH.with(Cell::get) {
+        None
+    } else {
+        Some(get_mutex().lock().expect("Could not lock mutex"))
+    }
+}
+
+/// Lock `mutex`, as set for a handler with `Builder::with_mutex()`, or else the global mutex as for
+/// `lock()`.  A handler's own mutex is locked even within a batch, which only holds the global one.
+pub fn lock_in(mutex: &Option<Arc<Mutex<()>>>) -> Option<MutexGuard<'_, ()>> {
+    match *mutex {
+        Some(ref mutex) => Some(mutex.lock().expect("Could not lock mutex")),
+        None => lock(),
+    }
+}
+
+/// Lock the mutexes of two handlers as `lock_in()` would lock each, in a fixed order so that two
+/// threads locking the same pair can't deadlock.  A mutex used by both is only locked once.
+pub fn lock_both<'a>(
+    first: &'a Option<Arc<Mutex<()>>>,
+    second: &'a Option<Arc<Mutex<()>>>,
+) -> (Option<MutexGuard<'a, ()>>, Option<MutexGuard<'a, ()>>) {
+    let address = |mutex: &Option<Arc<Mutex<()>>>| {
+        mutex
+            .as_ref()
+            .map_or(0, |mutex| Arc::as_ptr(mutex) as usize)
+    };
+    let (first, second) = if address(first) <= address(second) {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    let first_guard = lock_in(first);
+    let second_guard = if address(first) == address(second) {
+        None
+    } else {
+        lock_in(second)
+    };
+    (first_guard, second_guard)
+}
+
+/// Run `f` while holding the global mutex, so that calls to `lock()` from this thread within it
+/// don't block.
+pub fn hold_for<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    struct Release;
+
+    impl Drop for Release {
+        fn drop(&mut self) {
+            HELD_BY_BATCH.with(|held| held.set(false));
+        }
+    }
+
+    let guard = match lock() {
+        Some(guard) => guard,
+        None => return f(),
+    };
+    HELD_BY_BATCH.with(|held| held.set(true));
+    let release = Release;
+    let result = f();
+    drop(release);
+    drop(guard);
+    result
+}
@@ -0,0 +1,96 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::builder::Options;
+use crate::error::Error;
+use crate::file_handler;
+use crate::global_mutex;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+/// An append-only file of newline-delimited JSON records, e.g. an event log.
+///
+/// Returned by [`FileHandler::open_json_lines()`](struct.FileHandler.html#method.open_json_lines).
+/// Each record is serialised as JSON on a line of its own, regardless of the handler's
+/// [`Format`](enum.Format.html), so that appending one doesn't rewrite the rest of the file.
+pub struct JsonLinesHandler<T> {
+    path: PathBuf,
+    options: Options,
+    _ph: PhantomData<T>,
+}
+
+impl<T> JsonLinesHandler<T> {
+    pub(crate) fn new(path: PathBuf, options: Options) -> JsonLinesHandler<T> {
+        JsonLinesHandler {
+            path,
+            options,
+            _ph: PhantomData,
+        }
+    }
+
+    /// Get the full path to the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<T: Serialize> JsonLinesHandler<T> {
+    /// Append `record` to the end of the file, followed by a newline.
+    ///
+    /// The line is written with a single write under the exclusive lock, to a file opened for
+    /// appending, so concurrent appends from other handlers or processes don't interleave.
+    pub fn append(&self, record: &T) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let _guard = global_mutex::lock_in(&self.options.mutex);
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        file_handler::exclusive_lock(
+            &mut file,
+            self.options.lock_mode,
+            |file| -> Result<(), Error> {
+                file.write_all(&line)?;
+                if self.options.durable {
+                    file.sync_all()?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+impl<T: DeserializeOwned> JsonLinesHandler<T> {
+    /// Read every record in the file, in the order they were appended.  Blank lines are skipped.
+    pub fn read_all(&self) -> Result<Vec<T>, Error> {
+        let mut file = File::open(&self.path)?;
+        self.options.check_size(file.metadata()?.len())?;
+        let path = &self.path;
+        file_handler::shared_lock(
+            &mut file,
+            self.options.lock_mode,
+            |file| -> Result<Vec<T>, Error> {
+                let mut records = Vec::new();
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let record = serde_json::from_str(&line)
+                        .map_err(|e| file_handler::parse_error(path, e.into()))?;
+                    records.push(record);
+                }
+                Ok(records)
+            },
+        )
+    }
+}
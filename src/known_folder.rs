@@ -0,0 +1,82 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Resolution of the Windows base directories.
+
+use crate::error::Error;
+use std::env;
+use std::path::PathBuf;
+
+/// The Windows folders which the config locations are based on, named after their `FOLDERID`s.
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Copy)]
+pub(crate) enum KnownFolder {
+    RoamingAppData,
+    LocalAppData,
+    ProgramData,
+}
+
+impl KnownFolder {
+    /// Resolve the folder.
+    ///
+    /// With the `known_folders` feature, this asks the shell via `SHGetKnownFolderPath`, which
+    /// works even where the environment isn't populated, e.g. in a Windows service.  Otherwise,
+    /// or if that fails, the folder is read from its environment variable.
+    pub fn path(self) -> Result<PathBuf, Error> {
+        #[cfg(feature = "known_folders")]
+        {
+            if let Some(path) = self.query_shell() {
+                return Ok(path);
+            }
+        }
+        Ok(PathBuf::from(env::var(self.env_var())?))
+    }
+
+    fn env_var(self) -> &'static str {
+        match self {
+            KnownFolder::RoamingAppData => "APPDATA",
+            KnownFolder::LocalAppData => "LOCALAPPDATA",
+            KnownFolder::ProgramData => "ALLUSERSPROFILE",
+        }
+    }
+
+    #[cfg(feature = "known_folders")]
+    #[allow(unsafe_code)]
+    fn query_shell(self) -> Option<PathBuf> {
+        use std::ffi::{c_void, OsString};
+        use std::os::windows::ffi::OsStringExt;
+        use std::{ptr, slice};
+        use windows_sys::Win32::Globalization::lstrlenW;
+        use windows_sys::Win32::System::Com::CoTaskMemFree;
+        use windows_sys::Win32::UI::Shell::{
+            FOLDERID_LocalAppData, FOLDERID_ProgramData, FOLDERID_RoamingAppData,
+            SHGetKnownFolderPath,
+        };
+
+        let id = match self {
+            KnownFolder::RoamingAppData => FOLDERID_RoamingAppData,
+            KnownFolder::LocalAppData => FOLDERID_LocalAppData,
+            KnownFolder::ProgramData => FOLDERID_ProgramData,
+        };
+
+        // The returned string must be freed with `CoTaskMemFree` whether or not the call succeeds.
+        unsafe {
+            let mut raw_path = ptr::null_mut();
+            let result = SHGetKnownFolderPath(&id, 0, 0, &mut raw_path);
+            let path = if result == 0 {
+                let wide = slice::from_raw_parts(raw_path, lstrlenW(raw_path) as usize);
+                Some(PathBuf::from(OsString::from_wide(wide)))
+            } else {
+                None
+            };
+            CoTaskMemFree(raw_path as *const c_void);
+            path
+        }
+    }
+}
@@ -59,20 +59,115 @@
     missing_debug_implementations,
     variant_size_differences
 )]
+// `quick_error!` recurses once per `Error` variant.
+#![recursion_limit = "256"]
 
 #[macro_use]
 extern crate lazy_static;
+#[cfg(all(feature = "json", feature = "log"))]
+#[macro_use]
+extern crate log;
 #[macro_use]
 extern crate quick_error;
 #[macro_use]
 extern crate unwrap;
 
+// Without the `log` feature, the logging macros expand to nothing, but still type-check their
+// arguments.
+#[cfg(all(feature = "json", not(feature = "log")))]
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+#[cfg(all(feature = "json", not(feature = "log")))]
+macro_rules! trace {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+#[cfg(all(feature = "json", not(feature = "log")))]
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(feature = "json")]
+mod builder;
+#[cfg(feature = "encryption")]
+mod cipher;
+#[cfg(feature = "json")]
+mod clock;
+#[cfg(feature = "json")]
+mod config;
 mod error;
+#[cfg(feature = "json")]
 mod file_handler;
+#[cfg(feature = "json")]
+mod format;
+#[cfg(feature = "json")]
 mod global_mutex;
+#[cfg(feature = "json")]
+mod json_lines;
+#[cfg(windows)]
+mod known_folder;
+mod location;
+#[cfg(feature = "json")]
+mod merge;
+mod name;
+mod paths;
+#[cfg(feature = "json")]
+mod raw;
+#[cfg(feature = "mmap")]
+mod read_only;
+#[cfg(feature = "json")]
+mod shared;
+#[cfg(feature = "json")]
+mod snapshot;
+#[cfg(feature = "json")]
+mod store;
+#[cfg(feature = "json")]
+mod transform;
+mod uri;
 
+#[cfg(feature = "json")]
+pub use crate::builder::Builder;
+#[cfg(feature = "json")]
+pub use crate::config::Config;
 pub use crate::error::Error;
+#[cfg(feature = "json")]
 pub use crate::file_handler::{
-    cleanup, current_bin_dir, exe_file_stem, set_additional_search_path, system_cache_dir,
-    user_app_dir, FileHandler, ScopedUserAppDirRemover,
+    batch, cleanup, list_configs, parse_config, read_from_stdin, search_locations,
+    serialize_config, write_to_stdout, Checkpoint, ConfigLayers, ConfigLockGuard, CreationOutcome,
+    FileHandler, LockMode, Recovery,
+};
+#[cfg(feature = "bincode")]
+pub use crate::format::BincodeOptions;
+#[cfg(feature = "json")]
+pub use crate::format::{Decode, Encode, Format};
+#[cfg(feature = "json")]
+pub use crate::json_lines::JsonLinesHandler;
+pub use crate::location::Location;
+pub use crate::name::ConfigName;
+#[cfg(any(test, feature = "testing"))]
+pub use crate::paths::reset_global_state_for_testing;
+pub use crate::paths::{
+    bundle_resource_dir, cleanup_app_dir, clear_path_cache, current_bin_dir, exe_file_stem,
+    local_user_app_dir, resolved_dirs, set_additional_search_path,
+    set_additional_search_path_expanded, set_app_name, set_config_subdir, set_project_dirs,
+    system_cache_dir, user_app_dir, with_search_path, ResolvedDirs, ScopedUserAppDirRemover,
 };
+#[cfg(feature = "json")]
+pub use crate::raw::RawConfig;
+#[cfg(feature = "mmap")]
+pub use crate::read_only::ReadOnlyHandler;
+#[cfg(feature = "json")]
+pub use crate::shared::SharedConfig;
+#[cfg(feature = "json")]
+pub use crate::snapshot::SnapshotHandler;
+#[cfg(feature = "json")]
+pub use crate::store::{ConfigStore, InMemoryHandler};
+#[cfg(feature = "json")]
+pub use crate::transform::ByteTransform;
+pub use crate::uri::path_from_uri;
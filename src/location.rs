@@ -0,0 +1,110 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::error::Error;
+use crate::paths;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The kinds of location searched for config files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Location {
+    /// The path set by [`set_additional_search_path()`](fn.set_additional_search_path.html).
+    AdditionalSearchPath,
+    /// [`current_bin_dir()`](fn.current_bin_dir.html).
+    CurrentBinDir,
+    /// [`bundle_resource_dir()`](fn.bundle_resource_dir.html).
+    BundleResourceDir,
+    /// [`user_app_dir()`](fn.user_app_dir.html), or
+    /// [`local_user_app_dir()`](fn.local_user_app_dir.html) if preferred.
+    UserAppDir,
+    /// [`system_cache_dir()`](fn.system_cache_dir.html).
+    SystemCacheDir,
+    /// A directory given explicitly, e.g. to
+    /// [`FileHandler::open_with_base()`](struct.FileHandler.html#method.open_with_base).  This is
+    /// never searched otherwise.
+    Explicit,
+}
+
+impl Location {
+    /// Resolve the base directory of this location, e.g. to place other files beside a config.
+    ///
+    /// For `AdditionalSearchPath`, this is the path set for the current thread by
+    /// [`with_search_path()`](fn.with_search_path.html) if there is one, or else the path set by
+    /// [`set_additional_search_path()`](fn.set_additional_search_path.html); if neither is set, a
+    /// `NotFound` IO error is returned.  `Explicit` has no base directory of its own, so an
+    /// `InvalidInput` IO error is returned for it.
+    pub fn base_dir(&self) -> Result<PathBuf, Error> {
+        match *self {
+            Location::AdditionalSearchPath => paths::additional_search_paths()
+                .into_iter()
+                .next()
+                .ok_or_else(|| {
+                    Error::Io(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "No additional search path has been set",
+                    ))
+                }),
+            Location::CurrentBinDir => paths::current_bin_dir(),
+            Location::BundleResourceDir => paths::bundle_resource_dir(),
+            Location::UserAppDir => paths::user_app_dir(),
+            Location::SystemCacheDir => paths::system_cache_dir(),
+            Location::Explicit => Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "An explicit location has no base directory",
+            ))),
+        }
+    }
+
+    /// Find the standard location which `path` is directly in, e.g. to only allow editing files
+    /// under the user's directory, or `None` if its parent isn't any of their base directories.
+    ///
+    /// The base directories are resolved as for [`base_dir()`](#method.base_dir), and the
+    /// subdirectory set by [`set_config_subdir()`](fn.set_config_subdir.html), if any, is also
+    /// accepted beneath each of them.  The user directory matches both
+    /// [`user_app_dir()`](fn.user_app_dir.html) and
+    /// [`local_user_app_dir()`](fn.local_user_app_dir.html).  Locations are checked in search
+    /// order, so a path in a directory shared by two of them is classified as the first.  Paths
+    /// are compared as given and, where both exist, canonicalised, but the file itself needn't
+    /// exist.
+    pub fn classify(path: &Path) -> Option<Location> {
+        let parent = path.parent()?;
+        let canonical_parent = parent.canonicalize().ok();
+        let subdir = paths::config_subdir();
+        let matches = |dir: &Path| {
+            dir == parent
+                || canonical_parent.as_ref().is_some_and(|canonical_parent| {
+                    dir.canonicalize().ok().as_ref() == Some(canonical_parent)
+                })
+        };
+
+        let locations = [
+            Location::AdditionalSearchPath,
+            Location::CurrentBinDir,
+            Location::BundleResourceDir,
+            Location::UserAppDir,
+            Location::SystemCacheDir,
+        ];
+        locations.iter().cloned().find(|location| {
+            let mut dirs = match *location {
+                Location::AdditionalSearchPath => paths::additional_search_paths(),
+                Location::UserAppDir => vec![paths::user_app_dir(), paths::local_user_app_dir()]
+                    .into_iter()
+                    .filter_map(Result::ok)
+                    .collect(),
+                _ => location.base_dir().into_iter().collect(),
+            };
+            if let Some(ref subdir) = subdir {
+                let subdirs = dirs.iter().map(|dir| dir.join(subdir)).collect::<Vec<_>>();
+                dirs.extend(subdirs);
+            }
+            dirs.iter().any(|dir| matches(dir))
+        })
+    }
+}
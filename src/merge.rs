@@ -0,0 +1,105 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use serde_json::{Map, Value};
+
+/// Recursively merge `overlay` into `base`.  Objects are merged key by key, with the values in
+/// `overlay` winning; any other kind of value in `overlay` replaces the one in `base` outright.
+pub(crate) fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (&mut Value::Object(ref mut base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        let _ = base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Append the JSON pointer (RFC 6901) of each value which differs between `old` and `new` to
+/// `changes`, relative to `pointer`.  Objects and arrays are compared element by element, so a
+/// changed leaf is reported rather than its parents; a key or element present in only one of them
+/// is reported as changed.
+pub(crate) fn diff(old: &Value, new: &Value, pointer: &str, changes: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut keys = old.keys().chain(new.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let pointer = format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+                match (old.get(key), new.get(key)) {
+                    (Some(old), Some(new)) => diff(old, new, &pointer, changes),
+                    _ => changes.push(pointer),
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for index in 0..old.len().max(new.len()) {
+                let pointer = format!("{}/{}", pointer, index);
+                match (old.get(index), new.get(index)) {
+                    (Some(old), Some(new)) => diff(old, new, &pointer, changes),
+                    _ => changes.push(pointer),
+                }
+            }
+        }
+        (old, new) => {
+            if old != new {
+                changes.push(pointer.to_string());
+            }
+        }
+    }
+}
+
+/// Recursively sort the keys of every object in `value`.  This is a no-op unless `serde_json`'s
+/// `preserve_order` feature has been enabled, e.g. by another crate in the build.
+pub(crate) fn sort_keys(value: &mut Value) {
+    match *value {
+        Value::Object(ref mut map) => {
+            let mut entries = std::mem::take(map).into_iter().collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            *map = entries
+                .into_iter()
+                .map(|(key, mut value)| {
+                    sort_keys(&mut value);
+                    (key, value)
+                })
+                .collect::<Map<_, _>>();
+        }
+        Value::Array(ref mut values) => values.iter_mut().for_each(sort_keys),
+        _ => (),
+    }
+}
+
+/// Set the value at the nested key given by `segments` in `base` to `value`, creating objects as
+/// needed and replacing any other kind of value in the way.  At each level, the key is the longest
+/// run of the remaining segments joined by `_` which already exists, so that `["max", "peers"]`
+/// sets `max_peers` if there is such a key, and `max.peers` otherwise.
+pub(crate) fn set_nested(base: &mut Value, segments: &[String], value: Value) {
+    if segments.is_empty() {
+        *base = value;
+        return;
+    }
+    if !base.is_object() {
+        *base = Value::Object(Map::new());
+    }
+    if let Value::Object(ref mut map) = *base {
+        let len = (1..=segments.len())
+            .rev()
+            .find(|&len| map.contains_key(&segments[..len].join("_")))
+            .unwrap_or(1);
+        let entry = map.entry(segments[..len].join("_")).or_insert(Value::Null);
+        set_nested(entry, &segments[len..], value);
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::error::Error;
+use std::convert::TryFrom;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// The name of a config file, checked to be a plain file name rather than a path.
+///
+/// `FileHandler::open`, `FileHandler::new` and `cleanup` take a name rather than a full path, and
+/// return `Error::InvalidName` if it contains a path separator or a component such as `..`.
+/// Parsing a name into a `ConfigName` up front, e.g. where it is read from user input, reports
+/// that mistake at the point it is made.  A `ConfigName` can be passed wherever a name is
+/// expected.
+///
+/// # Examples
+///
+/// ```
+/// use config_file_handler::ConfigName;
+/// use std::convert::TryFrom;
+///
+/// assert!(ConfigName::try_from("app.config").is_ok());
+/// assert!(ConfigName::try_from("/etc/app/app.config").is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConfigName(OsString);
+
+impl ConfigName {
+    /// Check that `name` is a plain file name.
+    pub fn new<S: AsRef<OsStr> + ?Sized>(name: &S) -> Result<ConfigName, Error> {
+        let name = name.as_ref();
+        validate_name(name)?;
+        Ok(ConfigName(name.to_os_string()))
+    }
+
+    /// The name as an `OsStr`.
+    pub fn as_os_str(&self) -> &OsStr {
+        &self.0
+    }
+}
+
+impl AsRef<OsStr> for ConfigName {
+    fn as_ref(&self) -> &OsStr {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for ConfigName {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.0)
+    }
+}
+
+impl fmt::Display for ConfigName {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", Path::new(&self.0).display())
+    }
+}
+
+impl<'a> TryFrom<&'a str> for ConfigName {
+    type Error = Error;
+
+    fn try_from(name: &'a str) -> Result<Self, Error> {
+        ConfigName::new(name)
+    }
+}
+
+impl<'a> TryFrom<&'a OsStr> for ConfigName {
+    type Error = Error;
+
+    fn try_from(name: &'a OsStr) -> Result<Self, Error> {
+        ConfigName::new(name)
+    }
+}
+
+impl TryFrom<String> for ConfigName {
+    type Error = Error;
+
+    fn try_from(name: String) -> Result<Self, Error> {
+        ConfigName::new(&name)
+    }
+}
+
+/// Check that `name` is a plain file name, i.e. that joining it to a directory can't refer to a
+/// path outside that directory.  Both `/` and `\` are rejected on all platforms, so that a name
+/// which is safe on one platform is safe on all of them.
+pub(crate) fn validate_name(name: &OsStr) -> Result<(), Error> {
+    let mut components = Path::new(name).components();
+    let is_plain = match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => !name.to_string_lossy().contains(['/', '\\']),
+        _ => false,
+    };
+    if is_plain {
+        Ok(())
+    } else {
+        Err(Error::InvalidName {
+            name: PathBuf::from(name),
+        })
+    }
+}
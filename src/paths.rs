@@ -0,0 +1,679 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Resolution of the base directories searched for config files.  None of this needs `serde`, so
+//! it is available with the default features disabled.
+
+use crate::error::Error;
+#[cfg(windows)]
+use crate::known_folder::KnownFolder;
+use crate::name::validate_name;
+use directories::ProjectDirs;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref ADDITIONAL_SEARCH_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+    static ref PROJECT_DIRS: Mutex<Option<ProjectDirs>> = Mutex::new(None);
+    static ref APP_NAME: Mutex<Option<OsString>> = Mutex::new(None);
+    static ref CONFIG_SUBDIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+    pub(crate) static ref PATH_CACHE: Mutex<HashMap<CachedPath, PathBuf>> =
+        Mutex::new(HashMap::new());
+}
+
+/// The environment variable which overrides the name of the currently-running binary in the base
+/// directories.  See `set_app_name()`.
+const APP_STEM_ENV_VAR: &str = "CONFIG_FILE_HANDLER_APP_STEM";
+
+/// The paths which are resolved once and then remembered by `cached_path()`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum CachedPath {
+    CurrentExe,
+    UserAppDir,
+    LocalUserAppDir,
+    SystemCacheDir,
+}
+
+thread_local! {
+    static THREAD_SEARCH_PATH: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Set an additional search path. This, if set, will be tried before the other default ones.
+pub fn set_additional_search_path<P: AsRef<OsStr> + ?Sized>(path: &P) {
+    *unwrap!(ADDITIONAL_SEARCH_PATH.lock()) = Some(From::from(path));
+}
+
+/// Set an additional search path like
+/// [`set_additional_search_path()`](fn.set_additional_search_path.html), after expanding any
+/// environment variables in it.
+///
+/// Variables can be written as `$VAR` or `${VAR}` and, on Windows, as `%VAR%`.  If a variable isn't
+/// set, `Error::UndefinedVariable` is returned and the search path is left unchanged.
+pub fn set_additional_search_path_expanded(path: &str) -> Result<(), Error> {
+    let expanded = expand_env_vars(path)?;
+    set_additional_search_path(&expanded);
+    Ok(())
+}
+
+/// Run `f` with an additional search path set for the current thread only.
+///
+/// While `f` runs, `path` is tried before the path set by
+/// [`set_additional_search_path()`](fn.set_additional_search_path.html) and the other default
+/// ones, but only by calls made from this thread.  This allows tests running in parallel to each
+/// use their own directory.  The previous thread-local path is restored when `f` returns or
+/// panics.
+pub fn with_search_path<P, F, R>(path: &P, f: F) -> R
+where
+    P: AsRef<OsStr> + ?Sized,
+    F: FnOnce() -> R,
+{
+    struct Restore(Option<PathBuf>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            THREAD_SEARCH_PATH.with(|path| *path.borrow_mut() = previous);
+        }
+    }
+
+    let previous =
+        THREAD_SEARCH_PATH.with(|current| current.borrow_mut().replace(From::from(path)));
+    let _restore = Restore(previous);
+    f()
+}
+
+pub(crate) fn expand_env_vars(path: &str) -> Result<OsString, Error> {
+    fn lookup(name: &str) -> Result<OsString, Error> {
+        env::var_os(name).ok_or_else(|| Error::UndefinedVariable {
+            name: name.to_string(),
+        })
+    }
+
+    fn is_name_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '_'
+    }
+
+    let mut expanded = OsString::new();
+    let mut rest = path;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("${") {
+            if let Some(end) = after.find('}') {
+                expanded.push(lookup(&after[..end])?);
+                rest = &after[end + 1..];
+                continue;
+            }
+        } else if let Some(after) = rest.strip_prefix('$') {
+            let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+            if end > 0 {
+                expanded.push(lookup(&after[..end])?);
+                rest = &after[end..];
+                continue;
+            }
+        } else if cfg!(windows) && c == '%' {
+            let after = &rest[1..];
+            if let Some(end) = after.find('%') {
+                if end > 0 && after[..end].chars().all(is_name_char) {
+                    expanded.push(lookup(&after[..end])?);
+                    rest = &after[end + 1..];
+                    continue;
+                }
+            }
+        }
+        expanded.push(&rest[..c.len_utf8()]);
+        rest = &rest[c.len_utf8()..];
+    }
+    Ok(expanded)
+}
+
+/// The additional search paths, in order of preference: the one set for this thread by
+/// `with_search_path()`, then the one set by `set_additional_search_path()`.
+pub(crate) fn additional_search_paths() -> Vec<PathBuf> {
+    let thread_local = THREAD_SEARCH_PATH.with(|path| path.borrow().clone());
+    let global = unwrap!(ADDITIONAL_SEARCH_PATH.lock()).clone();
+    thread_local.into_iter().chain(global).collect()
+}
+
+/// Resolve [`user_app_dir()`](fn.user_app_dir.html) following the platform conventions of the
+/// `directories` crate, i.e. using `ProjectDirs::from(qualifier, organization, application)`,
+/// rather than naming the directory after the currently-running binary.  For example, this gives
+/// `%APPDATA%\<organization>\<application>\config` on Windows.
+///
+/// This is opt-in so that existing deployments don't have their files moved.  It returns an error
+/// if no valid home directory could be found.
+pub fn set_project_dirs(
+    qualifier: &str,
+    organization: &str,
+    application: &str,
+) -> Result<(), Error> {
+    let project_dirs = ProjectDirs::from(qualifier, organization, application)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found."))?;
+    *unwrap!(PROJECT_DIRS.lock()) = Some(project_dirs);
+    Ok(())
+}
+
+/// Name the directories created in [`user_app_dir()`](fn.user_app_dir.html),
+/// [`system_cache_dir()`](fn.system_cache_dir.html) and the other base directories `name` rather
+/// than after the currently-running binary, e.g. so that `app-cli` and `app-daemon` share the
+/// directory `app`.
+///
+/// This takes precedence over the `CONFIG_FILE_HANDLER_APP_STEM` environment variable, which can
+/// otherwise be set to override the binary's name, e.g. to give each instance of a service its own
+/// directories.
+///
+/// The cached paths are cleared, as for [`clear_path_cache()`](fn.clear_path_cache.html).  `name`
+/// must be a plain directory name, otherwise `Error::InvalidName` is returned.
+pub fn set_app_name<S: Into<OsString>>(name: S) -> Result<(), Error> {
+    let name = name.into();
+    validate_name(&name)?;
+    *unwrap!(APP_NAME.lock()) = Some(name);
+    clear_path_cache();
+    Ok(())
+}
+
+/// Keep config files in `subdir` of each base directory rather than directly in it, e.g. `v2` so
+/// that two major versions of an application running side by side each have their own files in
+/// `~/.config/app/v2`.  An empty path restores the default of no subdirectory.
+///
+/// This applies to the locations searched by `FileHandler::open()`, and by `FileHandler::new()`,
+/// which creates the subdirectory if needed, and to [`cleanup()`](fn.cleanup.html).  Files with an
+/// explicit base directory are unaffected.  `subdir` must be a relative path without `..`
+/// components, otherwise `Error::InvalidName` is returned.
+pub fn set_config_subdir<P: Into<PathBuf>>(subdir: P) -> Result<(), Error> {
+    let subdir = subdir.into();
+    if !subdir
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(Error::InvalidName { name: subdir });
+    }
+    let subdir = if subdir.as_os_str().is_empty() {
+        None
+    } else {
+        Some(subdir)
+    };
+    *unwrap!(CONFIG_SUBDIR.lock()) = subdir;
+    Ok(())
+}
+
+/// The subdirectory set by `set_config_subdir()`, if any.
+pub(crate) fn config_subdir() -> Option<PathBuf> {
+    unwrap!(CONFIG_SUBDIR.lock()).clone()
+}
+
+/// The name of the directory created in each base directory: the one set by `set_app_name()`, or
+/// else the value of `APP_STEM_ENV_VAR` if it is set and not empty, or else the file stem of the
+/// currently-running binary.
+fn app_name() -> Result<OsString, Error> {
+    if let Some(ref name) = *unwrap!(APP_NAME.lock()) {
+        return Ok(name.clone());
+    }
+    match env::var_os(APP_STEM_ENV_VAR) {
+        Some(ref name) if !name.is_empty() => {
+            validate_name(name)?;
+            Ok(name.clone())
+        }
+        _ => exe_file_stem(),
+    }
+}
+
+/// The base directories searched for config files, each with the result of resolving it, as
+/// returned by [`resolved_dirs()`](fn.resolved_dirs.html).
+#[derive(Debug)]
+pub struct ResolvedDirs {
+    /// [`current_bin_dir()`](fn.current_bin_dir.html).
+    pub current_bin_dir: Result<PathBuf, Error>,
+    /// [`bundle_resource_dir()`](fn.bundle_resource_dir.html).
+    pub bundle_resource_dir: Result<PathBuf, Error>,
+    /// [`user_app_dir()`](fn.user_app_dir.html).
+    pub user_app_dir: Result<PathBuf, Error>,
+    /// [`system_cache_dir()`](fn.system_cache_dir.html).
+    pub system_cache_dir: Result<PathBuf, Error>,
+}
+
+/// The full path to the directory containing the currently-running binary. See also [an example
+/// config file flowchart][1].
+///
+/// [1]: https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf
+pub fn current_bin_dir() -> Result<PathBuf, Error> {
+    match current_exe()?.parent() {
+        Some(path) => Ok(path.to_path_buf()),
+        None => Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Current bin dir",
+        ))),
+    }
+}
+
+/// Resolve all of the base directories at once, e.g. for a diagnostic command which prints where
+/// config files are looked for.  A directory which can't be resolved is reported with its error
+/// rather than failing the whole call.
+pub fn resolved_dirs() -> ResolvedDirs {
+    ResolvedDirs {
+        current_bin_dir: current_bin_dir(),
+        bundle_resource_dir: bundle_resource_dir(),
+        user_app_dir: user_app_dir(),
+        system_cache_dir: system_cache_dir(),
+    }
+}
+
+/// The full path to the directory containing the resources of the currently-running binary.
+///
+/// On macOS, if the binary is inside an application bundle, this is the bundle's `Resources`
+/// directory.  Otherwise, it is the first of these directories which exists:
+///
+///   1. `resources` beside the binary, e.g. in a portable install
+///   2. `share/<exe_file_stem>` beside the binary's directory, e.g. `/usr/share/app` for
+///      `/usr/bin/app`, including within an AppImage.  The name set by
+///      [`set_app_name()`](fn.set_app_name.html) is used instead of the file stem if there is one.
+///
+/// If there is none, a `NotFound` error is returned.
+pub fn bundle_resource_dir() -> Result<PathBuf, Error> {
+    let bin_dir = current_bin_dir()?;
+
+    #[cfg(target_os = "macos")]
+    {
+        let is_inside_bundle = bin_dir
+            .to_str()
+            .is_some_and(|dir| dir.ends_with(".app/Contents/MacOS"));
+        if is_inside_bundle {
+            if let Some(contents_dir) = bin_dir.parent() {
+                return Ok(contents_dir.join("Resources"));
+            }
+        }
+    }
+
+    let mut candidates = vec![bin_dir.join("resources")];
+    if let Some(prefix) = bin_dir.parent() {
+        candidates.push(prefix.join("share").join(app_name()?));
+    }
+    candidates
+        .into_iter()
+        .find(|dir| dir.is_dir())
+        .ok_or_else(|| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Bundle resource directory not found",
+            ))
+        })
+}
+
+/// The full path to an application support directory for the current user.  See also [an example
+/// config file flowchart][1].
+///
+/// The directory is named after the currently-running binary, unless overridden by
+/// [`set_app_name()`](fn.set_app_name.html) or the `CONFIG_FILE_HANDLER_APP_STEM` environment
+/// variable.
+///
+/// If [`set_project_dirs()`](fn.set_project_dirs.html) has been called, this is instead the config
+/// directory given by the platform conventions of the `directories` crate.
+///
+/// [1]: https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf
+pub fn user_app_dir() -> Result<PathBuf, Error> {
+    if let Some(ref project_dirs) = *unwrap!(PROJECT_DIRS.lock()) {
+        return Ok(project_dirs.config_dir().to_path_buf());
+    }
+    cached_path(CachedPath::UserAppDir, platform_user_app_dir)
+}
+
+#[cfg(windows)]
+fn platform_user_app_dir() -> Result<PathBuf, Error> {
+    let path = KnownFolder::RoamingAppData.path()?;
+    let app_dir = Path::new(&path);
+
+    if app_dir.is_dir() {
+        Ok(join_exe_file_stem(app_dir)?)
+    } else {
+        Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Global user app directory not found.",
+        )))
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn platform_user_app_dir() -> Result<PathBuf, Error> {
+    let mut home_dir = dirs::home_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found."))?;
+    home_dir.push(".config");
+
+    if home_dir.is_dir() {
+        Ok(join_exe_file_stem(&home_dir)?)
+    } else {
+        Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Global user app directory not found.",
+        )))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_user_app_dir() -> Result<PathBuf, Error> {
+    let mut app_dir = dirs::home_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Home directory not found."))?;
+    app_dir.push("Library/Application Support");
+
+    if app_dir.is_dir() {
+        Ok(join_exe_file_stem(&app_dir)?)
+    } else {
+        Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Global user app directory not found.",
+        )))
+    }
+}
+
+/// The full path to a non-roaming application support directory for the current user.
+///
+/// On Windows this is `%LOCALAPPDATA%\<exe file stem>`, which, unlike the roaming
+/// [`user_app_dir()`](fn.user_app_dir.html), isn't synced across machines and so is suitable for
+/// machine-specific data such as a bootstrap cache.  On other platforms this is the same as
+/// `user_app_dir()`.
+pub fn local_user_app_dir() -> Result<PathBuf, Error> {
+    if let Some(ref project_dirs) = *unwrap!(PROJECT_DIRS.lock()) {
+        return Ok(project_dirs.config_local_dir().to_path_buf());
+    }
+    cached_path(CachedPath::LocalUserAppDir, platform_local_user_app_dir)
+}
+
+#[cfg(windows)]
+fn platform_local_user_app_dir() -> Result<PathBuf, Error> {
+    let path = KnownFolder::LocalAppData.path()?;
+    let app_dir = Path::new(&path);
+
+    if app_dir.is_dir() {
+        Ok(join_exe_file_stem(app_dir)?)
+    } else {
+        Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Local user app directory not found.",
+        )))
+    }
+}
+
+#[cfg(not(windows))]
+fn platform_local_user_app_dir() -> Result<PathBuf, Error> {
+    platform_user_app_dir()
+}
+
+/// The full path to a system cache directory available for all users. See also [an example config
+/// file flowchart][1].
+///
+/// [1]: https://github.com/maidsafe/crust/blob/master/docs/vault_config_file_flowchart.pdf
+pub fn system_cache_dir() -> Result<PathBuf, Error> {
+    cached_path(CachedPath::SystemCacheDir, platform_system_cache_dir)
+}
+
+#[cfg(windows)]
+fn platform_system_cache_dir() -> Result<PathBuf, Error> {
+    let path = KnownFolder::ProgramData.path()?;
+    let sys_cache_dir = Path::new(&path);
+
+    if sys_cache_dir.is_dir() {
+        Ok(join_exe_file_stem(sys_cache_dir)?)
+    } else {
+        Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Global system cache directory not found.",
+        )))
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn platform_system_cache_dir() -> Result<PathBuf, Error> {
+    let sys_cache_dir = Path::new("/var/cache");
+
+    if sys_cache_dir.is_dir() {
+        Ok(join_exe_file_stem(sys_cache_dir)?)
+    } else {
+        Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Global system cache directory not found.",
+        )))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_system_cache_dir() -> Result<PathBuf, Error> {
+    let sys_cache_dir = Path::new("/Library/Application Support");
+
+    if sys_cache_dir.is_dir() {
+        Ok(join_exe_file_stem(sys_cache_dir)?)
+    } else {
+        Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Global system cache directory not found.",
+        )))
+    }
+}
+
+/// The file name of the currently-running binary without any suffix or extension.  For example, if
+/// the binary is "C:\\Abc.exe" this function will return `Ok("Abc")`.
+pub fn exe_file_stem() -> Result<OsString, Error> {
+    if let Ok(exe_path) = current_exe() {
+        let file_stem = exe_path.file_stem();
+        Ok(file_stem
+            .ok_or_else(|| not_found_error(&exe_path))?
+            .to_os_string())
+    } else {
+        Ok(From::from("default"))
+    }
+}
+
+/// RAII object which removes a file from the [`user_app_dir()`](fn.user_app_dir.html), or the
+/// whole directory, when an instance is dropped.
+///
+/// Since the `user_app_dir` is frequently created by tests or examples which use Crust, this is a
+/// convenience object which tries to clean it up when it is destroyed.  Prefer
+/// [`for_file()`](#method.for_file) over [`all()`](#method.all), so that other files in the
+/// directory, e.g. those of a real user or of tests running in parallel, are left alone.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "json")]
+/// # {
+/// use config_file_handler::{FileHandler, ScopedUserAppDirRemover};
+///
+/// {
+///     let _cleaner = ScopedUserAppDirRemover::for_file("test.json");
+///     let file_handler = FileHandler::new("test.json", true).unwrap();
+///     // The file is possibly created in the user app dir by this call.
+///     let _ = file_handler.write_file(&111u64);
+/// }
+/// // The file is now removed from the user app dir since '_cleaner' has gone out of scope.
+/// # }
+/// ```
+pub struct ScopedUserAppDirRemover {
+    name: Option<OsString>,
+}
+
+impl ScopedUserAppDirRemover {
+    /// Remove the whole of [`user_app_dir()`](fn.user_app_dir.html) on drop, including any other
+    /// files in it.
+    pub fn all() -> Self {
+        ScopedUserAppDirRemover { name: None }
+    }
+
+    /// Remove only the file `name` from [`user_app_dir()`](fn.user_app_dir.html) on drop, leaving
+    /// the directory and any other files in it untouched.
+    pub fn for_file<S: AsRef<OsStr> + ?Sized>(name: &S) -> Self {
+        ScopedUserAppDirRemover {
+            name: Some(name.as_ref().to_os_string()),
+        }
+    }
+
+    fn remove(&mut self) {
+        let _ = user_app_dir().and_then(|user_app_dir| {
+            match self.name {
+                Some(ref name) => fs::remove_file(user_app_dir.join(name)),
+                None => fs::remove_dir_all(user_app_dir),
+            }
+            .map_err(Error::Io)
+        });
+    }
+}
+
+impl Drop for ScopedUserAppDirRemover {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+/// Remove the application's own directories under [`user_app_dir()`](fn.user_app_dir.html),
+/// [`local_user_app_dir()`](fn.local_user_app_dir.html) and
+/// [`system_cache_dir()`](fn.system_cache_dir.html) with everything in them, e.g. from an
+/// uninstaller, returning those which existed and were removed.
+///
+/// Only the directory named after the application is removed, never the base directory holding
+/// it, e.g. `~/.config/<app>` rather than `~/.config`.  As a guard against a misconfigured name,
+/// if any of the directories isn't such an application directory, an `InvalidInput` IO error is
+/// returned before anything is removed.  Each is checked against the rule which named it, so
+/// after [`set_project_dirs()`](fn.set_project_dirs.html) the user directories must be inside the
+/// project directories while the system cache directory must still be named after the
+/// application.  Directories which can't be resolved, e.g. because they have never been created,
+/// are skipped.
+pub fn cleanup_app_dir() -> Result<Vec<PathBuf>, Error> {
+    let has_project_dirs = unwrap!(PROJECT_DIRS.lock()).is_some();
+    let mut dirs: Vec<(PathBuf, bool)> = Vec::new();
+    for (dir, in_project_dirs) in [
+        (user_app_dir(), has_project_dirs),
+        (local_user_app_dir(), has_project_dirs),
+        (system_cache_dir(), false),
+    ] {
+        if let Ok(dir) = dir {
+            if dirs.iter().all(|(existing, _)| *existing != dir) {
+                dirs.push((dir, in_project_dirs));
+            }
+        }
+    }
+    if let Some((dir, _)) = dirs
+        .iter()
+        .find(|(dir, in_project_dirs)| !is_app_dir(dir, *in_project_dirs))
+    {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Refusing to remove {}, which isn't an application directory",
+                dir.display()
+            ),
+        )));
+    }
+
+    let mut removed = Vec::new();
+    for (dir, _) in dirs {
+        match fs::remove_dir_all(&dir) {
+            Ok(()) => removed.push(dir),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(removed)
+}
+
+/// Whether `dir` is a directory of the application's own, below a base directory: one inside the
+/// project directories set by `set_project_dirs()` if `in_project_dirs` is set, or else one named
+/// after the application.
+pub(crate) fn is_app_dir(dir: &Path, in_project_dirs: bool) -> bool {
+    let below_base = dir.parent().and_then(Path::parent).is_some()
+        && dirs::home_dir().is_none_or(|home_dir| !home_dir.starts_with(dir));
+    if !below_base {
+        return false;
+    }
+    if in_project_dirs {
+        return unwrap!(PROJECT_DIRS.lock())
+            .as_ref()
+            .is_some_and(|project_dirs| {
+                let project_path = project_dirs.project_path();
+                dir.ancestors().any(|dir| dir.ends_with(project_path))
+            });
+    }
+    app_name().is_ok_and(|name| dir.file_name() == Some(&*name))
+}
+
+/// Clear the cached paths of the currently-running binary and the base directories, so that they
+/// are resolved afresh on next use.
+///
+/// These are otherwise only resolved once per process, as they are needed by every `open` and
+/// `new`.  This is mainly useful for tests which manipulate the environment, e.g. `HOME`.  The
+/// additional search path is never cached.
+pub fn clear_path_cache() {
+    unwrap!(PATH_CACHE.lock()).clear();
+}
+
+/// Restore every process-wide setting made through this crate to its default, e.g. between tests
+/// which change them: the additional search path, and the settings made by
+/// [`set_project_dirs()`](fn.set_project_dirs.html),
+/// [`set_app_name()`](fn.set_app_name.html) and
+/// [`set_config_subdir()`](fn.set_config_subdir.html).  The cached paths are cleared too.
+///
+/// Search paths set for a thread by [`with_search_path()`](fn.with_search_path.html) are only
+/// in effect for the duration of that call, so are unaffected.  Hooks registered with
+/// `FileHandler::on_write()` belong to their handler rather than being global.  This is only
+/// available with the `testing` feature.
+#[cfg(any(test, feature = "testing"))]
+pub fn reset_global_state_for_testing() {
+    *unwrap!(ADDITIONAL_SEARCH_PATH.lock()) = None;
+    *unwrap!(PROJECT_DIRS.lock()) = None;
+    *unwrap!(APP_NAME.lock()) = None;
+    *unwrap!(CONFIG_SUBDIR.lock()) = None;
+    clear_path_cache();
+    #[cfg(all(test, feature = "json"))]
+    crate::clock::reset_clock();
+}
+
+/// Return the cached path for `key`, resolving and caching it first if needed.  Errors are not
+/// cached, so e.g. a user app directory which doesn't exist yet is looked for again next time.
+fn cached_path<F>(key: CachedPath, resolve: F) -> Result<PathBuf, Error>
+where
+    F: FnOnce() -> Result<PathBuf, Error>,
+{
+    if let Some(path) = unwrap!(PATH_CACHE.lock()).get(&key) {
+        return Ok(path.clone());
+    }
+    // The lock isn't held while resolving, since that may need other cached paths.
+    let path = resolve()?;
+    let _ = unwrap!(PATH_CACHE.lock()).insert(key, path.clone());
+    Ok(path)
+}
+
+fn current_exe() -> Result<PathBuf, Error> {
+    cached_path(CachedPath::CurrentExe, || {
+        Ok(strip_deleted_suffix(env::current_exe()?))
+    })
+}
+
+/// On Linux, the path of a binary which has been replaced while running, e.g. by an in-place
+/// upgrade, is reported with a " (deleted)" suffix.  Strip it, so that the binary's directory and
+/// file stem are still correct.
+pub(crate) fn strip_deleted_suffix(path: PathBuf) -> PathBuf {
+    const SUFFIX: &str = " (deleted)";
+
+    if cfg!(target_os = "linux") {
+        if let Some(stripped) = path.to_str().and_then(|path| path.strip_suffix(SUFFIX)) {
+            return PathBuf::from(stripped);
+        }
+    }
+    path
+}
+
+pub(crate) fn not_found_error(file_name: &Path) -> io::Error {
+    let mut msg: String = From::from("No file name component: ");
+    msg.push_str(&file_name.to_string_lossy());
+    io::Error::new(io::ErrorKind::NotFound, msg)
+}
+
+fn join_exe_file_stem(path: &Path) -> Result<PathBuf, Error> {
+    Ok(path.join(app_name()?))
+}
@@ -0,0 +1,45 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::error::Error;
+use crate::format::{Decode, Encode, Format};
+use std::io::Read;
+
+/// The contents of a config file which is an opaque blob of bytes, such as a serialised keypair.
+///
+/// A `FileHandler<RawConfig>` reads and writes the file's bytes verbatim rather than via serde,
+/// ignoring its [`Format`](enum.Format.html), while the search, locking and (with the
+/// `encryption` feature) encryption are as for any other `FileHandler`.  Since there is no
+/// serialised default, such a file can be created with
+/// [`FileHandler::touch()`](struct.FileHandler.html#method.touch).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RawConfig(pub Vec<u8>);
+
+impl Decode for RawConfig {
+    fn decode<R: Read>(mut reader: R, _format: Format) -> Result<Self, Error> {
+        let mut contents = Vec::new();
+        let _ = reader.read_to_end(&mut contents)?;
+        Ok(RawConfig(contents))
+    }
+
+    fn expects_text(_format: Format) -> bool {
+        false
+    }
+}
+
+impl Encode for RawConfig {
+    fn encode(
+        &self,
+        _format: Format,
+        _indent: &[u8],
+        _trailing_newline: bool,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self.0.clone())
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::builder::Options;
+use crate::error::Error;
+use crate::file_handler;
+use crate::format::Decode;
+use memmap2::Mmap;
+use std::fs::File;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A read-only view of a config file which is memory-mapped once, for fast repeated reads.
+///
+/// Returned by [`FileHandler::open_read_only()`](struct.FileHandler.html#method.open_read_only).
+/// Each call to [`get()`](#method.get) decodes from the mapped bytes without touching the
+/// filesystem.  Changes to the file are only picked up by calling [`remap()`](#method.remap).
+///
+/// The file is mapped under the shared lock, but the lock isn't held for the lifetime of the
+/// mapping.  The mapping therefore assumes that the file isn't truncated or rewritten by another
+/// writer while it is in use, which includes writes made through a `FileHandler`; if it may be,
+/// call `remap()` only once the writer has finished, or use `FileHandler::read_file()` instead.
+///
+/// The mapping can be released early with [`close()`](#method.close), in which case the file is
+/// mapped again by the next call to `get()`.
+pub struct ReadOnlyHandler<T> {
+    path: PathBuf,
+    options: Options,
+    map: Mutex<Option<Mmap>>,
+    _ph: PhantomData<T>,
+}
+
+impl<T> ReadOnlyHandler<T> {
+    pub(crate) fn new(path: PathBuf, options: Options) -> Result<ReadOnlyHandler<T>, Error> {
+        let map = map_file(&path, &options)?;
+        Ok(ReadOnlyHandler {
+            path,
+            options,
+            map: Mutex::new(Some(map)),
+            _ph: PhantomData,
+        })
+    }
+
+    /// Get the full path to the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Map the file again, so that subsequent calls to [`get()`](#method.get) see its current
+    /// contents.
+    ///
+    /// This also picks up a file which has been replaced at [`path()`](#method.path), e.g. by
+    /// renaming a new copy over it: on Unix the existing mapping keeps the old inode alive, and
+    /// only `remap()` or [`close()`](#method.close) switch to the new one.
+    pub fn remap(&mut self) -> Result<(), Error> {
+        let map = map_file(&self.path, &self.options)?;
+        *unwrap!(self.map.get_mut()) = Some(map);
+        Ok(())
+    }
+
+    /// Unmap the file, releasing the mapping and its file descriptor, e.g. to stay within a tight
+    /// budget of open files.  The file is mapped again by the next call to `get()`.
+    pub fn close(&mut self) -> Result<(), Error> {
+        *unwrap!(self.map.get_mut()) = None;
+        Ok(())
+    }
+}
+
+impl<T: Decode> ReadOnlyHandler<T> {
+    /// Decode the contents of the file as they were when it was last mapped, mapping it first if
+    /// it has been closed.
+    pub fn get(&self) -> Result<T, Error> {
+        let mut map = unwrap!(self.map.lock());
+        if map.is_none() {
+            *map = Some(map_file(&self.path, &self.options)?);
+        }
+        let map = unwrap!(map.as_ref());
+        self.options
+            .decode(&map[..])
+            .map_err(|e| file_handler::parse_error(&self.path, e))
+    }
+}
+
+fn map_file(path: &Path, options: &Options) -> Result<Mmap, Error> {
+    let mut file = File::open(path)?;
+    let map = file_handler::shared_lock(&mut file, options.lock_mode, |file| {
+        // Safe as long as the file isn't truncated while mapped, which is documented above.
+        #[allow(unsafe_code)]
+        unsafe {
+            Mmap::map(&*file)
+        }
+    })?;
+    options.check_size(map.len() as u64)?;
+    Ok(map)
+}
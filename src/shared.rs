@@ -0,0 +1,96 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::error::Error;
+use crate::file_handler::FileHandler;
+use crate::format::Decode;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, Weak};
+use std::thread;
+use std::time::Duration;
+
+struct Inner<T> {
+    value: RwLock<T>,
+    last_error: Mutex<Option<Error>>,
+}
+
+/// A config value which is kept up to date with its file by a background thread.
+///
+/// Returned by [`FileHandler::into_shared()`](struct.FileHandler.html#method.into_shared).  The
+/// file is polled for changes to its contents, and re-read whenever it changes.  Clones share the
+/// same value, and the background thread exits once the last clone has been dropped.
+pub struct SharedConfig<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> SharedConfig<T>
+where
+    T: Decode + Send + Sync + 'static,
+{
+    pub(crate) fn spawn(
+        file_handler: FileHandler<T>,
+        poll_interval: Duration,
+    ) -> Result<SharedConfig<T>, Error> {
+        let mut last_hash = file_handler.content_hash()?;
+        let inner = Arc::new(Inner {
+            value: RwLock::new(file_handler.read_file()?),
+            last_error: Mutex::new(None),
+        });
+
+        let weak = Arc::downgrade(&inner);
+        let _ = thread::spawn(move || loop {
+            thread::sleep(poll_interval);
+            let inner: Arc<Inner<T>> = match Weak::upgrade(&weak) {
+                Some(inner) => inner,
+                None => return,
+            };
+            let hash = match file_handler.content_hash() {
+                Ok(hash) => hash,
+                Err(e) => {
+                    *unwrap!(inner.last_error.lock()) = Some(e);
+                    continue;
+                }
+            };
+            if hash == last_hash {
+                continue;
+            }
+            last_hash = hash;
+            match file_handler.read_file() {
+                Ok(value) => {
+                    *unwrap!(inner.value.write()) = value;
+                    *unwrap!(inner.last_error.lock()) = None;
+                }
+                Err(e) => *unwrap!(inner.last_error.lock()) = Some(e),
+            }
+        });
+
+        Ok(SharedConfig { inner })
+    }
+}
+
+impl<T> SharedConfig<T> {
+    /// Get the current value.  The guard should be dropped promptly, as it blocks the background
+    /// thread from updating the value.
+    pub fn get(&self) -> RwLockReadGuard<'_, T> {
+        unwrap!(self.inner.value.read())
+    }
+
+    /// The error from the most recent attempt to reload the file, if it failed.  The last
+    /// successfully-read value is kept until the file can be read again.
+    pub fn last_error(&self) -> MutexGuard<'_, Option<Error>> {
+        unwrap!(self.inner.last_error.lock())
+    }
+}
+
+impl<T> Clone for SharedConfig<T> {
+    fn clone(&self) -> Self {
+        SharedConfig {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::builder::Options;
+use crate::error::Error;
+use crate::file_handler;
+use crate::format::Decode;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Distinguishes the snapshots taken by this process.
+static NEXT_SNAPSHOT: AtomicUsize = AtomicUsize::new(0);
+
+/// A private copy of a config file, for repeatable reads which aren't affected by later writes to
+/// the original.
+///
+/// Returned by [`FileHandler::snapshot()`](struct.FileHandler.html#method.snapshot).  The copy
+/// is kept in the system temp directory, and is removed when the snapshot is dropped.
+pub struct SnapshotHandler<T> {
+    path: PathBuf,
+    options: Options,
+    _ph: PhantomData<T>,
+}
+
+impl<T> SnapshotHandler<T> {
+    /// Copy `contents`, the raw bytes of the file at `original`, to a new file in the temp
+    /// directory.
+    pub(crate) fn new(
+        original: &Path,
+        contents: &[u8],
+        options: Options,
+    ) -> Result<SnapshotHandler<T>, Error> {
+        let mut name = OsString::from(format!(
+            "config_file_handler-{}-{}-",
+            process::id(),
+            NEXT_SNAPSHOT.fetch_add(1, Ordering::Relaxed)
+        ));
+        name.push(original.file_name().unwrap_or_default());
+        let path = env::temp_dir().join(name);
+
+        let mut open_options = OpenOptions::new();
+        let _ = open_options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            let _ = open_options.mode(0o600);
+        }
+        let mut file = open_options.open(&path)?;
+        let snapshot = SnapshotHandler {
+            path,
+            options,
+            _ph: PhantomData,
+        };
+        file.write_all(contents)?;
+        Ok(snapshot)
+    }
+
+    /// Get the full path to the copy.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<T: Decode> SnapshotHandler<T> {
+    /// Decode the contents of the original file as they were when the snapshot was taken.
+    pub fn read_file(&self) -> Result<T, Error> {
+        let mut file = File::open(&self.path)?;
+        self.options.check_size(file.metadata()?.len())?;
+        self.options
+            .decode(&mut file)
+            .map_err(|e| file_handler::parse_error(&self.path, e))
+    }
+}
+
+impl<T> Drop for SnapshotHandler<T> {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,93 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::error::Error;
+use crate::file_handler::FileHandler;
+use crate::format::{Decode, Encode};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// Storage for a single config value.
+///
+/// This is implemented by `FileHandler` and by `InMemoryHandler`, so code which is generic over
+/// `ConfigStore` can be tested without touching the filesystem.
+pub trait ConfigStore<T> {
+    /// Read the stored value.
+    fn read(&self) -> Result<T, Error>;
+
+    /// Replace the stored value with `contents`.
+    fn write(&self, contents: &T) -> Result<(), Error>;
+}
+
+impl<T> ConfigStore<T> for FileHandler<T>
+where
+    T: Decode + Encode,
+{
+    fn read(&self) -> Result<T, Error> {
+        self.read_file()
+    }
+
+    fn write(&self, contents: &T) -> Result<(), Error> {
+        self.write_file(contents)
+    }
+}
+
+/// A `ConfigStore` which keeps the value in memory, e.g. for use in tests.
+///
+/// Clones of an `InMemoryHandler` share the same storage.
+pub struct InMemoryHandler<T> {
+    value: Arc<Mutex<Option<T>>>,
+}
+
+impl<T> InMemoryHandler<T> {
+    /// Create an empty store.  Reading from it fails with a `NotFound` IO error until a value has
+    /// been written.
+    pub fn new() -> Self {
+        InMemoryHandler {
+            value: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Create a store holding `value`.
+    pub fn with_value(value: T) -> Self {
+        InMemoryHandler {
+            value: Arc::new(Mutex::new(Some(value))),
+        }
+    }
+}
+
+impl<T> Default for InMemoryHandler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for InMemoryHandler<T> {
+    fn clone(&self) -> Self {
+        InMemoryHandler {
+            value: Arc::clone(&self.value),
+        }
+    }
+}
+
+impl<T: Clone> ConfigStore<T> for InMemoryHandler<T> {
+    fn read(&self) -> Result<T, Error> {
+        unwrap!(self.value.lock()).clone().ok_or_else(|| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No value has been written",
+            ))
+        })
+    }
+
+    fn write(&self, contents: &T) -> Result<(), Error> {
+        *unwrap!(self.value.lock()) = Some(contents.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::error::Error;
+
+/// A transformation of a config file's bytes between serialisation and disk, e.g. compression or
+/// an application-specific checksum.
+///
+/// Transforms are registered with [`Builder::transform()`](struct.Builder.html#method.transform).
+/// `on_read()` should invert `on_write()`.
+pub trait ByteTransform: Send + Sync {
+    /// Transform `contents` on its way to disk.
+    fn on_write(&self, contents: Vec<u8>) -> Result<Vec<u8>, Error>;
+
+    /// Transform `contents` read from disk, reversing `on_write()`.
+    fn on_read(&self, contents: Vec<u8>) -> Result<Vec<u8>, Error>;
+}
@@ -0,0 +1,110 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use crate::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+/// Convert `uri` to a native path, e.g. for a config path taken from a settings field which may
+/// hold either a plain path or a `file://` URL.
+///
+/// A `file:` URL is percent-decoded, and its host must be empty or `localhost`, except on Windows
+/// where any other host gives a UNC path.  On Windows, `file:///C:/dir/config.json` gives
+/// `C:\dir\config.json`.  Any query or fragment is ignored.  Anything without a scheme is returned
+/// as a path unchanged.  Any other scheme, e.g. `https:`, gives `Error::UnsupportedScheme`.
+pub fn path_from_uri(uri: &str) -> Result<PathBuf, Error> {
+    let (scheme, rest) = match split_scheme(uri) {
+        Some(split) => split,
+        None => return Ok(PathBuf::from(uri)),
+    };
+    if !scheme.eq_ignore_ascii_case("file") {
+        return Err(Error::UnsupportedScheme {
+            scheme: scheme.to_owned(),
+        });
+    }
+
+    let rest = rest.split(['?', '#']).next().unwrap_or("");
+    let (host, path) = match rest.strip_prefix("//") {
+        Some(authority) => match authority.find('/') {
+            Some(index) => authority.split_at(index),
+            None => (authority, ""),
+        },
+        None => ("", rest),
+    };
+    let path = percent_decode(path)?;
+    native_path(host, path)
+}
+
+/// Split `uri` into its scheme and the rest, if it has a scheme.  A single letter before the
+/// colon is taken to be a Windows drive rather than a scheme.
+fn split_scheme(uri: &str) -> Option<(&str, &str)> {
+    let index = uri.find(':')?;
+    let scheme = &uri[..index];
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+    if !valid || scheme.len() == 1 {
+        return None;
+    }
+    Some((scheme, &uri[index + 1..]))
+}
+
+fn percent_decode(path: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || {
+        Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid percent-encoding in {:?}", path),
+        ))
+    };
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3).ok_or_else(invalid)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(unix)]
+fn native_path(host: &str, path: Vec<u8>) -> Result<PathBuf, Error> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Can't open a file on the remote host {:?}", host),
+        )));
+    }
+    Ok(PathBuf::from(OsString::from_vec(path)))
+}
+
+#[cfg(not(unix))]
+fn native_path(host: &str, path: Vec<u8>) -> Result<PathBuf, Error> {
+    let path = String::from_utf8(path)
+        .map_err(|e| Error::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    let path = path.replace('/', "\\");
+    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+        return Ok(PathBuf::from(format!("\\\\{}{}", host, path)));
+    }
+    // `\C:\dir` is really `C:\dir`.
+    let bytes = path.as_bytes();
+    if bytes.len() >= 3 && bytes[0] == b'\\' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':' {
+        return Ok(PathBuf::from(&path[1..]));
+    }
+    Ok(PathBuf::from(path))
+}
@@ -0,0 +1,32 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! `set_app_name()` changes the directories used by every handler in the process, so it is tested
+//! in a process of its own rather than alongside the unit tests.
+
+use config_file_handler::{set_app_name, system_cache_dir, user_app_dir, Error};
+use unwrap::unwrap;
+
+#[test]
+fn app_name_names_the_app_dirs() {
+    let default_user_app_dir = unwrap!(user_app_dir());
+    let name = "cfh-app-name-test";
+    unwrap!(set_app_name(name));
+
+    let app_dir = unwrap!(user_app_dir());
+    assert!(app_dir.ends_with(name));
+    assert_eq!(app_dir.parent(), default_user_app_dir.parent());
+    assert!(unwrap!(system_cache_dir()).ends_with(name));
+
+    match set_app_name("a/b") {
+        Err(Error::InvalidName { .. }) => (),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+    assert!(unwrap!(user_app_dir()).ends_with(name));
+}
@@ -0,0 +1,45 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! The `CONFIG_FILE_HANDLER_APP_STEM` environment variable applies to the whole process, so it is
+//! tested in a process of its own rather than alongside the unit tests.
+
+use config_file_handler::{clear_path_cache, set_app_name, system_cache_dir, user_app_dir, Error};
+use std::env;
+use unwrap::unwrap;
+
+const APP_STEM_ENV_VAR: &str = "CONFIG_FILE_HANDLER_APP_STEM";
+
+#[test]
+fn env_var_overrides_the_app_name() {
+    // Only the environment variable.
+    env::set_var(APP_STEM_ENV_VAR, "cfh-env-stem-test");
+    clear_path_cache();
+    assert!(unwrap!(user_app_dir()).ends_with("cfh-env-stem-test"));
+    assert!(unwrap!(system_cache_dir()).ends_with("cfh-env-stem-test"));
+
+    // An invalid value is rejected rather than used as a path.
+    env::set_var(APP_STEM_ENV_VAR, "a/b");
+    clear_path_cache();
+    match user_app_dir() {
+        Err(Error::InvalidName { .. }) => (),
+        result => panic!("Unexpected result: {:?}", result),
+    }
+
+    // The name set programmatically wins over the environment variable.
+    env::set_var(APP_STEM_ENV_VAR, "cfh-env-stem-test");
+    unwrap!(set_app_name("cfh-programmatic-test"));
+    assert!(unwrap!(user_app_dir()).ends_with("cfh-programmatic-test"));
+    assert!(unwrap!(system_cache_dir()).ends_with("cfh-programmatic-test"));
+    env::set_var(APP_STEM_ENV_VAR, "a/b");
+    clear_path_cache();
+    assert!(unwrap!(user_app_dir()).ends_with("cfh-programmatic-test"));
+
+    env::remove_var(APP_STEM_ENV_VAR);
+}
@@ -0,0 +1,55 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! `cleanup_app_dir()` removes the directories used by every handler in the process, and is
+//! tested with names set by `set_app_name()` and `set_project_dirs()`, so it is tested in a
+//! process of its own rather than alongside the unit tests.
+
+use config_file_handler::{
+    cleanup_app_dir, set_app_name, set_project_dirs, system_cache_dir, user_app_dir,
+};
+use std::fs;
+use std::path::PathBuf;
+use unwrap::unwrap;
+
+/// Create the user app and system cache directories, returning those which could be created.
+fn create_app_dirs() -> Vec<PathBuf> {
+    vec![unwrap!(user_app_dir()), unwrap!(system_cache_dir())]
+        .into_iter()
+        .filter(|dir| fs::create_dir_all(dir).is_ok())
+        .collect()
+}
+
+#[test]
+fn cleanup_removes_each_app_dir() {
+    unwrap!(set_app_name("cfh-cleanup-test"));
+    let created = create_app_dirs();
+    assert!(!created.is_empty());
+    let removed = unwrap!(cleanup_app_dir());
+    for dir in &created {
+        assert!(removed.contains(dir), "{} wasn't removed", dir.display());
+        assert!(!dir.exists());
+    }
+    assert!(unwrap!(cleanup_app_dir()).is_empty());
+
+    // The user directories now come from the project directories, but the system cache directory
+    // is still named after the application, and both are removed.
+    unwrap!(set_project_dirs(
+        "net",
+        "MaidSafe",
+        "cfh-cleanup-project-test"
+    ));
+    let created = create_app_dirs();
+    assert!(created.contains(&unwrap!(user_app_dir())));
+    let removed = unwrap!(cleanup_app_dir());
+    for dir in &created {
+        assert!(removed.contains(dir), "{} wasn't removed", dir.display());
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,56 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! `set_project_dirs()` changes the directories used by every handler in the process, so it is
+//! tested in a process of its own rather than alongside the unit tests.
+
+#![cfg(feature = "json")]
+
+use config_file_handler::{
+    cleanup, local_user_app_dir, set_project_dirs, user_app_dir, FileHandler, Location,
+};
+use directories::ProjectDirs;
+use std::fs;
+use unwrap::unwrap;
+
+#[test]
+fn project_dirs_are_used_for_user_dirs() {
+    let (qualifier, organization, application) = ("net", "MaidSafe", "cfh-project-dirs-test");
+    let expected = unwrap!(ProjectDirs::from(qualifier, organization, application));
+    unwrap!(set_project_dirs(qualifier, organization, application));
+
+    assert_eq!(unwrap!(user_app_dir()), expected.config_dir());
+    assert_eq!(unwrap!(local_user_app_dir()), expected.config_local_dir());
+
+    // `new()` creates files in the directory of the test binary, which comes first and is
+    // writable, so the file is moved to the user app dir to check where that is.
+    let name = "project_dirs_test.json";
+    let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+    unwrap!(file_handler.write_file(&545));
+    let migrated = unwrap!(FileHandler::<u64>::migrate_location(
+        name,
+        file_handler.location(),
+        Location::UserAppDir,
+        true
+    ));
+    assert_eq!(migrated.path(), expected.config_dir().join(name));
+
+    let file_handler = unwrap!(FileHandler::<u64>::new(name, true));
+    assert!(!file_handler.was_created());
+    assert_eq!(file_handler.location(), Location::UserAppDir);
+    assert_eq!(file_handler.path(), expected.config_dir().join(name));
+    assert_eq!(unwrap!(file_handler.read_file()), 545);
+
+    unwrap!(cleanup(&name));
+    for dir in &[expected.config_dir(), expected.config_local_dir()] {
+        if dir.exists() {
+            unwrap!(fs::remove_dir_all(dir));
+        }
+    }
+}
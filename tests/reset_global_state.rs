@@ -0,0 +1,47 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! `reset_global_state_for_testing()` changes the directories used by every handler in the
+//! process, so it is tested in a process of its own rather than alongside the unit tests.
+
+#![cfg(all(feature = "json", feature = "testing"))]
+
+use config_file_handler::{
+    reset_global_state_for_testing, search_locations, set_additional_search_path, set_app_name,
+    set_project_dirs, user_app_dir, Location,
+};
+use std::env;
+use std::path::PathBuf;
+use unwrap::unwrap;
+
+fn search_dirs() -> Vec<(Location, Option<PathBuf>)> {
+    search_locations()
+        .map(|(location, dir)| (location, dir.ok()))
+        .collect()
+}
+
+#[test]
+fn reset_restores_defaults() {
+    let default_user_app_dir = user_app_dir().ok();
+    let default_search_dirs = search_dirs();
+
+    let search_path = env::temp_dir().join("config_file_handler_reset_test");
+    set_additional_search_path(&search_path);
+    unwrap!(set_app_name("cfh-reset-test"));
+    unwrap!(set_project_dirs("net", "MaidSafe", "cfh-reset-test"));
+    assert_ne!(user_app_dir().ok(), default_user_app_dir);
+    assert!(search_dirs().contains(&(Location::AdditionalSearchPath, Some(search_path))));
+
+    reset_global_state_for_testing();
+    assert_eq!(user_app_dir().ok(), default_user_app_dir);
+    assert_eq!(search_dirs(), default_search_dirs);
+    assert!(search_dirs()
+        .iter()
+        .all(|&(location, _)| location != Location::AdditionalSearchPath));
+}